@@ -0,0 +1,188 @@
+use proc_macro::TokenStream;
+use proc_macro2::Span;
+use quote::{format_ident, quote};
+use syn::{
+    parse_macro_input, Data, DeriveInput, Error, Fields, GenericArgument, Ident, PathArguments,
+    Type,
+};
+
+enum IndexKind {
+    Plain,
+    Unique,
+    Many,
+}
+
+struct IndexedField {
+    name: Ident,
+    ty: Type,
+    kind: IndexKind,
+}
+
+#[proc_macro_derive(HashSyncRow, attributes(index))]
+pub fn derive_hash_sync_row(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    match expand(input) {
+        Ok(tokens) => tokens.into(),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+fn expand(input: DeriveInput) -> Result<proc_macro2::TokenStream, Error> {
+    if !input.generics.params.is_empty() {
+        return Err(Error::new_spanned(
+            &input.generics,
+            "HashSyncRow does not support generic row types",
+        ));
+    }
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => {
+                return Err(Error::new_spanned(
+                    &input.ident,
+                    "HashSyncRow requires a struct with named fields",
+                ))
+            }
+        },
+        _ => {
+            return Err(Error::new_spanned(
+                &input.ident,
+                "HashSyncRow can only be derived for structs",
+            ))
+        }
+    };
+
+    let mut indexed = Vec::new();
+    for field in fields {
+        for attr in field
+            .attrs
+            .iter()
+            .filter(|attr| attr.path().is_ident("index"))
+        {
+            let mut kind = IndexKind::Plain;
+            if !matches!(attr.meta, syn::Meta::Path(_)) {
+                attr.parse_nested_meta(|meta| {
+                    if meta.path.is_ident("unique") {
+                        kind = IndexKind::Unique;
+                        Ok(())
+                    } else if meta.path.is_ident("many") {
+                        kind = IndexKind::Many;
+                        Ok(())
+                    } else {
+                        Err(meta.error("expected `unique` or `many`"))
+                    }
+                })?;
+            }
+            indexed.push(IndexedField {
+                name: field.ident.clone().unwrap(),
+                ty: field.ty.clone(),
+                kind,
+            });
+        }
+    }
+
+    let row = &input.ident;
+    let vis = &input.vis;
+    let indexes = format_ident!("{}Indexes", row);
+
+    let mut handle_fields = Vec::new();
+    let mut accessors = Vec::new();
+    let mut registrations = Vec::new();
+    for field in indexed.iter() {
+        let name = &field.name;
+        let by_name = format_ident!("by_{}", name);
+        let ty = &field.ty;
+        match field.kind {
+            IndexKind::Plain => {
+                handle_fields.push(quote! {
+                    pub #name: ::hashsync::index::IndexRead<#ty, #row>
+                });
+                accessors.push(quote! {
+                    pub fn #by_name(&self, key: &#ty) -> ::std::vec::Vec<#row> {
+                        self.#name.get_values(key)
+                    }
+                });
+                registrations.push(quote! {
+                    #name: hs.index(|row: &#row| ::std::clone::Clone::clone(&row.#name))
+                });
+            }
+            IndexKind::Unique => {
+                handle_fields.push(quote! {
+                    pub #name: ::hashsync::unique::UniqueIndexRead<#ty, #row>
+                });
+                accessors.push(quote! {
+                    pub fn #by_name(&self, key: &#ty) -> ::std::option::Option<#row> {
+                        self.#name.get_one_value(key)
+                    }
+                });
+                registrations.push(quote! {
+                    #name: hs.unique_index(|row: &#row| ::std::clone::Clone::clone(&row.#name))?
+                });
+            }
+            IndexKind::Many => {
+                let element = element_type(ty).ok_or_else(|| {
+                    Error::new_spanned(ty, "#[index(many)] requires a field like Vec<T>")
+                })?;
+                handle_fields.push(quote! {
+                    pub #name: ::hashsync::index::IndexRead<#element, #row>
+                });
+                accessors.push(quote! {
+                    pub fn #by_name(&self, key: &#element) -> ::std::vec::Vec<#row> {
+                        self.#name.get_values(key)
+                    }
+                });
+                registrations.push(quote! {
+                    #name: hs.index_many(|row: &#row| {
+                        row.#name.iter().cloned().collect()
+                    })
+                });
+            }
+        }
+    }
+
+    if indexed.is_empty() {
+        return Err(Error::new(
+            Span::call_site(),
+            "HashSyncRow needs at least one #[index] field",
+        ));
+    }
+
+    Ok(quote! {
+        #vis struct #indexes {
+            #(#handle_fields,)*
+        }
+
+        impl #indexes {
+            #(#accessors)*
+        }
+
+        impl ::hashsync::row::HashSyncRow for #row {
+            type Indexes = #indexes;
+
+            fn register_indexes<'a>(
+                hs: &mut ::hashsync::hashsync::HashSync<'a, Self>,
+            ) -> ::std::result::Result<Self::Indexes, ::hashsync::error::UniqueViolation>
+            where
+                Self: 'a,
+            {
+                ::std::result::Result::Ok(#indexes {
+                    #(#registrations,)*
+                })
+            }
+        }
+    })
+}
+
+fn element_type(ty: &Type) -> Option<&Type> {
+    let segment = match ty {
+        Type::Path(path) => path.path.segments.last()?,
+        _ => return None,
+    };
+    match &segment.arguments {
+        PathArguments::AngleBracketed(args) => args.args.iter().find_map(|arg| match arg {
+            GenericArgument::Type(ty) => Some(ty),
+            _ => None,
+        }),
+        _ => None,
+    }
+}
@@ -0,0 +1,188 @@
+use std::{
+    hash::Hash,
+    time::{Duration, Instant},
+};
+
+use fxhash::FxHashMap;
+
+use crate::{error::Throttled, hashsync::HashSync, id::RowId};
+
+pub trait AdmissionControl<PartitionT> {
+    fn admit(&mut self, partition: &PartitionT, now: Instant) -> Result<(), Throttled>;
+}
+
+pub struct TokenBucket<PartitionT> {
+    capacity: u32,
+    refill_every: Duration,
+    buckets: FxHashMap<PartitionT, (u32, Instant)>,
+    sweep_at: usize,
+}
+
+const MIN_SWEEP_AT: usize = 64;
+
+impl<PartitionT: Hash + Eq + Clone> TokenBucket<PartitionT> {
+    pub fn new(capacity: u32, refill_every: Duration) -> Self {
+        TokenBucket {
+            capacity,
+            refill_every,
+            buckets: FxHashMap::default(),
+            sweep_at: MIN_SWEEP_AT,
+        }
+    }
+
+    pub fn tokens(&self, partition: &PartitionT) -> u32 {
+        self.buckets
+            .get(partition)
+            .map_or(self.capacity, |(tokens, _refilled_at)| *tokens)
+    }
+
+    fn sweep_idle(&mut self, now: Instant) {
+        // A bucket that has refilled to capacity behaves exactly like a fresh
+        // one, so idle partitions can be forgotten. Sweeping only once the map
+        // has doubled keeps the cost amortized per admit
+        if self.buckets.len() < self.sweep_at {
+            return;
+        }
+        let (capacity, refill_every) = (self.capacity, self.refill_every);
+        self.buckets.retain(|_partition, (tokens, refilled_at)| {
+            refill_every
+                .checked_mul(capacity - *tokens)
+                .and_then(|until_full| refilled_at.checked_add(until_full))
+                .is_none_or(|full_at| full_at > now)
+        });
+        self.sweep_at = MIN_SWEEP_AT.max(self.buckets.len() * 2);
+    }
+}
+
+impl<PartitionT: Hash + Eq + Clone> AdmissionControl<PartitionT> for TokenBucket<PartitionT> {
+    fn admit(&mut self, partition: &PartitionT, now: Instant) -> Result<(), Throttled> {
+        self.sweep_idle(now);
+        let (tokens, refilled_at) = self
+            .buckets
+            .entry(partition.clone())
+            .or_insert((self.capacity, now));
+
+        // Only whole refill periods are credited; the remainder carries over
+        // by advancing refilled_at by exactly the time that was credited
+        let elapsed = now.saturating_duration_since(*refilled_at);
+        let periods = elapsed.as_nanos() / self.refill_every.as_nanos().max(1);
+        let periods = u32::try_from(periods).unwrap_or(u32::MAX);
+        if periods > 0 {
+            *tokens = self.capacity.min(tokens.saturating_add(periods));
+            *refilled_at = if *tokens == self.capacity {
+                now
+            } else {
+                *refilled_at + self.refill_every * periods
+            };
+        }
+
+        if *tokens == 0 {
+            let retry_after = (*refilled_at + self.refill_every).saturating_duration_since(now);
+            return Err(Throttled::new(retry_after));
+        }
+        *tokens -= 1;
+        Ok(())
+    }
+}
+
+impl<'a, RowT: Clone + 'a> HashSync<'a, RowT> {
+    pub fn try_insert<PartitionT>(
+        &mut self,
+        control: &mut impl AdmissionControl<PartitionT>,
+        partition: &PartitionT,
+        row: RowT,
+    ) -> Result<RowId, Throttled> {
+        control.admit(partition, Instant::now())?;
+        Ok(self.insert(row))
+    }
+
+    pub fn try_replace<PartitionT>(
+        &mut self,
+        control: &mut impl AdmissionControl<PartitionT>,
+        partition: &PartitionT,
+        id: RowId,
+        row: RowT,
+    ) -> Result<(), Throttled> {
+        control.admit(partition, Instant::now())?;
+        self.replace(id, row);
+        Ok(())
+    }
+
+    pub fn try_delete<PartitionT>(
+        &mut self,
+        control: &mut impl AdmissionControl<PartitionT>,
+        partition: &PartitionT,
+        id: RowId,
+    ) -> Result<Option<RowT>, Throttled> {
+        control.admit(partition, Instant::now())?;
+        Ok(self.delete(id))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn token_bucket_refills_per_partition() {
+        let mut bucket = TokenBucket::new(2, Duration::from_secs(1));
+        let start = Instant::now();
+
+        assert!(bucket.admit(&"a", start).is_ok());
+        assert!(bucket.admit(&"a", start).is_ok());
+        let err = bucket.admit(&"a", start).unwrap_err();
+        assert_eq!(err.retry_after(), Duration::from_secs(1));
+        assert!(bucket.admit(&"b", start).is_ok());
+
+        let later = start + Duration::from_millis(1500);
+        assert!(bucket.admit(&"a", later).is_ok());
+        let err = bucket.admit(&"a", later).unwrap_err();
+        assert_eq!(err.retry_after(), Duration::from_millis(500));
+    }
+
+    #[test]
+    fn long_idle_periods_refill_fully() {
+        let mut bucket = TokenBucket::new(3, Duration::from_nanos(1));
+        let start = Instant::now();
+        for _ in 0..3 {
+            assert!(bucket.admit(&"a", start).is_ok());
+        }
+        assert!(bucket.admit(&"a", start).is_err());
+
+        let later = start + Duration::from_nanos(1 << 32);
+        for _ in 0..3 {
+            assert!(bucket.admit(&"a", later).is_ok());
+        }
+        assert!(bucket.admit(&"a", later).is_err());
+    }
+
+    #[test]
+    fn idle_partitions_are_evicted() {
+        let mut bucket = TokenBucket::new(1, Duration::from_secs(1));
+        let start = Instant::now();
+        for partition in 0..1_000 {
+            assert!(bucket.admit(&partition, start).is_ok());
+        }
+        assert_eq!(bucket.buckets.len(), 1_000);
+
+        let later = start + Duration::from_secs(2);
+        for partition in 1_000..1_100 {
+            assert!(bucket.admit(&partition, later).is_ok());
+        }
+        assert_eq!(bucket.buckets.len(), 100);
+        assert_eq!(bucket.tokens(&0), 1);
+        assert!(bucket.admit(&1_000, later).is_err());
+    }
+
+    #[test]
+    fn try_insert_is_rejected_when_throttled() {
+        let mut hs = HashSync::new();
+        let mut bucket = TokenBucket::new(1, Duration::from_secs(60));
+
+        let id = hs.try_insert(&mut bucket, &"writer", (1, 2)).unwrap();
+        assert!(hs.try_insert(&mut bucket, &"writer", (1, 3)).is_err());
+        assert!(hs.try_delete(&mut bucket, &"writer", id).is_err());
+        assert_eq!(hs.keys(), vec![id]);
+        assert_eq!(bucket.tokens(&"writer"), 0);
+    }
+}
@@ -0,0 +1,368 @@
+use std::{
+    cmp::Ordering,
+    collections::BTreeMap,
+    hash::Hash,
+    ops::{Add, Sub},
+    sync::{Arc, RwLock},
+};
+
+use fxhash::FxHashMap;
+
+use crate::{
+    describe::IndexDescription,
+    hashsync::HashSync,
+    id::{Indexed, RowId},
+    index::{IndexId, Indexable},
+    stamp::Stamp,
+};
+
+pub type AggregateFunction<KeyT, AggT, ValueT> = Box<dyn Fn(&ValueT) -> (KeyT, AggT) + Send + Sync>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Aggregate<AggT> {
+    count: usize,
+    sum: AggT,
+    min: AggT,
+    max: AggT,
+}
+
+impl<AggT: Copy> Aggregate<AggT> {
+    pub fn count(&self) -> usize {
+        self.count
+    }
+
+    pub fn sum(&self) -> AggT {
+        self.sum
+    }
+
+    pub fn min(&self) -> AggT {
+        self.min
+    }
+
+    pub fn max(&self) -> AggT {
+        self.max
+    }
+}
+
+// Orders values by partial_cmp; only values comparable with themselves are
+// wrapped, and among those a partial order like f64's is total
+struct Ordered<AggT>(AggT);
+
+impl<AggT: PartialOrd> PartialEq for Ordered<AggT> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl<AggT: PartialOrd> Eq for Ordered<AggT> {}
+
+impl<AggT: PartialOrd> PartialOrd for Ordered<AggT> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<AggT: PartialOrd> Ord for Ordered<AggT> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.partial_cmp(&other.0).unwrap_or(Ordering::Equal)
+    }
+}
+
+fn is_comparable<AggT: PartialOrd>(value: &AggT) -> bool {
+    value.partial_cmp(value) == Some(Ordering::Equal)
+}
+
+// Values that don't compare equal to themselves, like NaN, are counted but
+// kept out of the sum, min and max
+struct Group<AggT> {
+    count: usize,
+    sum: AggT,
+    values: BTreeMap<Ordered<AggT>, usize>,
+    incomparable: Vec<AggT>,
+}
+
+// The sum follows from the values, and float sums rebuilt in another order
+// can round differently, so groups compare by their values alone
+impl<AggT: PartialOrd> PartialEq for Group<AggT> {
+    fn eq(&self, other: &Self) -> bool {
+        self.count == other.count
+            && self.incomparable.len() == other.incomparable.len()
+            && self.values == other.values
+    }
+}
+
+pub struct AggregateIndex<KeyT, AggT, ValueT> {
+    aggregate_function: AggregateFunction<KeyT, AggT, ValueT>,
+    groups: FxHashMap<KeyT, Group<AggT>>,
+}
+
+// Sums use AggT's own + and -, so an integer sum that leaves AggT's range
+// panics in debug builds and wraps in release; aggregate into a type wide
+// enough for the largest group, such as i64 for i32 columns
+impl<KeyT, AggT, ValueT> AggregateIndex<KeyT, AggT, ValueT>
+where
+    KeyT: PartialEq + Eq + Hash,
+    AggT: Copy + PartialOrd + Default + Add<Output = AggT> + Sub<Output = AggT>,
+{
+    pub fn new(aggregate_function: AggregateFunction<KeyT, AggT, ValueT>) -> Self {
+        AggregateIndex {
+            aggregate_function,
+            groups: FxHashMap::default(),
+        }
+    }
+
+    // A group with only incomparable values reports one of them as its min
+    // and max
+    pub fn get(&self, key: &KeyT) -> Option<Aggregate<AggT>> {
+        let group = self.groups.get(key)?;
+        let fallback = group.incomparable.first().copied().unwrap_or(group.sum);
+        let min = group.values.keys().next().map_or(fallback, |min| min.0);
+        let max = group
+            .values
+            .keys()
+            .next_back()
+            .map_or(fallback, |max| max.0);
+        Some(Aggregate {
+            count: group.count,
+            sum: group.sum,
+            min,
+            max,
+        })
+    }
+
+    pub fn key_count(&self) -> usize {
+        self.groups.len()
+    }
+
+    fn add(groups: &mut FxHashMap<KeyT, Group<AggT>>, key: KeyT, value: AggT) {
+        let group = groups.entry(key).or_insert_with(|| Group {
+            count: 0,
+            sum: AggT::default(),
+            values: BTreeMap::new(),
+            incomparable: Vec::new(),
+        });
+        group.count += 1;
+        if is_comparable(&value) {
+            group.sum = group.sum + value;
+            *group.values.entry(Ordered(value)).or_default() += 1;
+        } else {
+            group.incomparable.push(value);
+        }
+    }
+
+    fn remove(groups: &mut FxHashMap<KeyT, Group<AggT>>, key: &KeyT, value: AggT) {
+        let group = match groups.get_mut(key) {
+            Some(group) => group,
+            None => return,
+        };
+        let removed = if is_comparable(&value) {
+            let ordered = Ordered(value);
+            match group.values.get_mut(&ordered) {
+                Some(occurrences) => {
+                    *occurrences -= 1;
+                    if *occurrences == 0 {
+                        group.values.remove(&ordered);
+                    }
+                    group.sum = group.sum - value;
+                    true
+                }
+                None => false,
+            }
+        } else {
+            group.incomparable.pop().is_some()
+        };
+        // Taking an infinity back out of a float sum leaves NaN, so the sum is
+        // rebuilt from the remaining values instead
+        if removed && !is_comparable(&group.sum) {
+            group.sum = group
+                .values
+                .iter()
+                .flat_map(|(value, occurrences)| std::iter::repeat_n(value.0, *occurrences))
+                .fold(AggT::default(), |sum, value| sum + value);
+        }
+        if removed {
+            group.count -= 1;
+        }
+        if group.count == 0 {
+            groups.remove(key);
+        }
+    }
+}
+
+impl<KeyT, AggT, ValueT> Indexable<ValueT> for AggregateIndex<KeyT, AggT, ValueT>
+where
+    KeyT: PartialEq + Eq + Hash,
+    AggT: Copy + PartialOrd + Default + Add<Output = AggT> + Sub<Output = AggT>,
+{
+    fn insert(&mut self, row: &Indexed<ValueT>) -> IndexId {
+        let (key, value) = (self.aggregate_function)(row.value());
+        Self::add(&mut self.groups, key, value);
+        IndexId::new(0)
+    }
+
+    fn delete(&mut self, row: &Indexed<ValueT>) {
+        let (key, value) = (self.aggregate_function)(row.value());
+        Self::remove(&mut self.groups, &key, value);
+    }
+
+    fn verify(&self, rows: &[Indexed<ValueT>]) -> (Vec<RowId>, Vec<RowId>) {
+        // Groups do not remember which rows they came from, so rows whose
+        // group disagrees with a rebuild are all reported as missing
+        let mut expected = FxHashMap::default();
+        for row in rows {
+            let (key, value) = (self.aggregate_function)(row.value());
+            Self::add(&mut expected, key, value);
+        }
+        let mut missing: Vec<RowId> = rows
+            .iter()
+            .filter(|row| {
+                let (key, _value) = (self.aggregate_function)(row.value());
+                expected.get(&key) != self.groups.get(&key)
+            })
+            .map(|row| row.id())
+            .collect();
+        missing.sort();
+        (Vec::new(), missing)
+    }
+
+    fn describe(&self) -> IndexDescription {
+        IndexDescription::new("aggregate").keyed_by::<KeyT>()
+    }
+}
+
+pub struct AggregateIndexRead<KeyT, AggT, ValueT> {
+    index: Arc<RwLock<AggregateIndex<KeyT, AggT, ValueT>>>,
+    stamp: Stamp,
+}
+
+impl<KeyT, AggT, ValueT> Clone for AggregateIndexRead<KeyT, AggT, ValueT> {
+    fn clone(&self) -> Self {
+        AggregateIndexRead {
+            index: self.index.clone(),
+            stamp: self.stamp.clone(),
+        }
+    }
+}
+
+impl<KeyT, AggT, ValueT> AggregateIndexRead<KeyT, AggT, ValueT>
+where
+    KeyT: PartialEq + Eq + Hash,
+    AggT: Copy + PartialOrd + Default + Add<Output = AggT> + Sub<Output = AggT>,
+{
+    pub fn new(index: Arc<RwLock<AggregateIndex<KeyT, AggT, ValueT>>>) -> Self {
+        AggregateIndexRead {
+            index,
+            stamp: Stamp::default(),
+        }
+    }
+
+    pub(crate) fn stamped(self, stamp: Stamp) -> Self {
+        AggregateIndexRead { stamp, ..self }
+    }
+
+    pub fn is_live(&self) -> bool {
+        self.stamp.is_current()
+    }
+
+    // Groups are maintained incrementally, so rows past their TTL stay in
+    // them until expire_stale deletes the rows
+    pub fn get(&self, key: &KeyT) -> Option<Aggregate<AggT>> {
+        self.stamp.consistent(|| {
+            if !self.is_live() {
+                return None;
+            }
+            self.index.read().unwrap().get(key)
+        })
+    }
+
+    pub fn key_count(&self) -> usize {
+        self.stamp.consistent(|| {
+            if !self.is_live() {
+                return 0;
+            }
+            self.index.read().unwrap().key_count()
+        })
+    }
+}
+
+impl<'a, RowT: Clone + 'a> HashSync<'a, RowT> {
+    pub fn aggregate_index<IndexKeyT, AggT, KeyFn, ValueFn>(
+        &mut self,
+        key_fn: KeyFn,
+        value_fn: ValueFn,
+    ) -> AggregateIndexRead<IndexKeyT, AggT, RowT>
+    where
+        KeyFn: Fn(&RowT) -> IndexKeyT + Send + Sync + 'static,
+        ValueFn: Fn(&RowT) -> AggT + Send + Sync + 'static,
+        IndexKeyT: PartialEq + Eq + Hash + Send + Sync + 'a,
+        AggT: Copy
+            + PartialOrd
+            + Default
+            + Add<Output = AggT>
+            + Sub<Output = AggT>
+            + Send
+            + Sync
+            + 'a,
+    {
+        let aggregate_fn = move |row: &RowT| (key_fn(row), value_fn(row));
+        let index = self.register_index(AggregateIndex::new(Box::new(aggregate_fn)));
+        AggregateIndexRead::new(index).stamped(self.stamp())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn aggregates_follow_mutations() {
+        let mut hs = HashSync::new();
+        let id = hs.insert(("a", 5));
+        let stats = hs.aggregate_index(|row: &(&str, i64)| row.0, |row| row.1);
+        hs.insert(("a", 2));
+        hs.insert(("a", 9));
+        hs.insert(("b", 1));
+
+        let a = stats.get(&"a").unwrap();
+        assert_eq!((a.count(), a.sum(), a.min(), a.max()), (3, 16, 2, 9));
+
+        hs.replace(id, ("b", 7));
+        let a = stats.get(&"a").unwrap();
+        assert_eq!((a.count(), a.sum(), a.min(), a.max()), (2, 11, 2, 9));
+        let b = stats.get(&"b").unwrap();
+        assert_eq!((b.count(), b.sum(), b.min(), b.max()), (2, 8, 1, 7));
+
+        hs.delete_where(|row| row.0 == "b");
+        assert_eq!(stats.get(&"b"), None);
+        assert_eq!(stats.key_count(), 1);
+        hs.verify_indexes().unwrap();
+    }
+
+    #[test]
+    fn float_aggregates_keep_nan_out_of_sum_min_and_max() {
+        let mut hs = HashSync::new();
+        let stats = hs.aggregate_index(|row: &(&str, f64)| row.0, |row| row.1);
+        hs.insert(("a", 1.5));
+        let nan = hs.insert(("a", f64::NAN));
+        hs.insert(("a", -2.0));
+
+        let a = stats.get(&"a").unwrap();
+        assert_eq!((a.count(), a.sum(), a.min(), a.max()), (3, -0.5, -2.0, 1.5));
+        hs.verify_indexes().unwrap();
+
+        hs.delete(nan);
+        let infinite = hs.insert(("a", f64::INFINITY));
+        assert_eq!(stats.get(&"a").unwrap().sum(), f64::INFINITY);
+        hs.delete(infinite);
+        let a = stats.get(&"a").unwrap();
+        assert_eq!((a.count(), a.sum(), a.min(), a.max()), (2, -0.5, -2.0, 1.5));
+
+        let only_nan = hs.insert(("b", f64::NAN));
+        let b = stats.get(&"b").unwrap();
+        assert_eq!(b.count(), 1);
+        assert!(b.min().is_nan() && b.max().is_nan());
+        hs.delete(only_nan);
+        assert_eq!(stats.get(&"b"), None);
+        hs.verify_indexes().unwrap();
+    }
+}
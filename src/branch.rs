@@ -0,0 +1,348 @@
+use std::{collections::BTreeMap, convert::Infallible, hash::Hash};
+
+use fxhash::FxHashMap;
+
+use crate::{
+    error::{MergeConflict, MergeError, TransactionError},
+    hashsync::HashSync,
+    id::{IdMapping, Indexed, RowId},
+    index::IndexRead,
+    store::StoreRead,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConflictPolicy {
+    Abort,
+    PreferChild,
+    PreferParent,
+}
+
+// The branch is a live view: it stages its writes over the parent's shared
+// rows instead of copying them, so every row it hasn't touched, including
+// ones the parent inserts after branching, reads through to the parent. The
+// first write to a parent row records the value the branch saw, and a merge
+// conflicts where the parent has moved on from it. Rows the branch inserts
+// take ids counted down from the top of the id space, clear of anything the
+// parent hands out, and are given fresh parent ids on merge
+pub struct Branch<RowT> {
+    parent: StoreRead<RowT>,
+    next_insert: RowId,
+    bases: FxHashMap<RowId, Option<RowT>>,
+    changes: FxHashMap<RowId, Option<RowT>>,
+    inserted: BTreeMap<RowId, RowT>,
+}
+
+impl<RowT: Clone> Branch<RowT> {
+    pub fn new(parent: StoreRead<RowT>) -> Self {
+        Branch {
+            parent,
+            next_insert: RowId::new(usize::MAX),
+            bases: FxHashMap::default(),
+            changes: FxHashMap::default(),
+            inserted: BTreeMap::new(),
+        }
+    }
+
+    fn is_inserted_id(&self, id: RowId) -> bool {
+        id > self.next_insert
+    }
+
+    pub fn keys(&self) -> Vec<RowId> {
+        let mut keys: Vec<RowId> = self
+            .parent
+            .keys()
+            .into_iter()
+            .filter(|id| !self.changes.contains_key(id))
+            .collect();
+        keys.extend(
+            self.changes
+                .iter()
+                .filter(|(_id, row)| row.is_some())
+                .map(|(id, _row)| *id),
+        );
+        keys.sort();
+        keys.extend(self.inserted.keys().rev().copied());
+        keys
+    }
+
+    pub fn by_id(&self, id: RowId) -> Option<RowT> {
+        if self.is_inserted_id(id) {
+            return self.inserted.get(&id).cloned();
+        }
+        match self.changes.get(&id) {
+            Some(row) => row.clone(),
+            None => self.parent.by_id(id),
+        }
+    }
+
+    // Reads the parent's index for rows the branch hasn't touched and runs
+    // the index function over the rows it has
+    pub fn get_by<IndexKeyT: PartialEq + Eq + Hash>(
+        &self,
+        index: &IndexRead<IndexKeyT, RowT>,
+        key: &IndexKeyT,
+    ) -> Vec<Indexed<RowT>> {
+        let mut rows: Vec<Indexed<RowT>> = index
+            .get(key)
+            .into_iter()
+            .filter(|row| !self.changes.contains_key(&row.id()))
+            .collect();
+        let staged = self
+            .changes
+            .iter()
+            .filter_map(|(id, row)| row.as_ref().map(|row| Indexed::new(*id, row.clone())));
+        rows.extend(staged.filter(|row| index.keys_of(row).contains(key)));
+        rows.sort_by_key(|row| row.id());
+        let inserted = self
+            .inserted
+            .iter()
+            .rev()
+            .map(|(id, row)| Indexed::new(*id, row.clone()));
+        rows.extend(inserted.filter(|row| index.keys_of(row).contains(key)));
+        rows
+    }
+
+    pub fn insert(&mut self, row: RowT) -> RowId {
+        let id = self.next_insert;
+        self.inserted.insert(id, row);
+        self.next_insert = id.prev().expect("branch ran out of row ids");
+        id
+    }
+
+    pub fn delete(&mut self, id: RowId) -> Option<RowT> {
+        if self.is_inserted_id(id) {
+            return self.inserted.remove(&id);
+        }
+        let row = self.by_id(id)?;
+        self.stage(id, None);
+        Some(row)
+    }
+
+    pub fn replace(&mut self, id: RowId, row: RowT) {
+        if self.is_inserted_id(id) {
+            self.inserted.insert(id, row);
+        } else {
+            self.stage(id, Some(row));
+        }
+    }
+
+    fn stage(&mut self, id: RowId, row: Option<RowT>) {
+        if !self.bases.contains_key(&id) {
+            self.bases.insert(id, self.parent.by_id(id));
+        }
+        self.changes.insert(id, row);
+    }
+}
+
+impl<RowT: Clone + PartialEq> Branch<RowT> {
+    pub fn merge_into_parent<'a>(
+        self,
+        parent: &mut HashSync<'a, RowT>,
+        policy: ConflictPolicy,
+    ) -> Result<IdMapping, MergeError>
+    where
+        RowT: 'a,
+    {
+        // A conflict is a row the branch changed that the parent also changed
+        // after the branch first wrote to it
+        let mut conflicts: Vec<RowId> = self
+            .bases
+            .iter()
+            .filter(|(id, base)| parent.by_id(**id) != **base)
+            .map(|(id, _base)| *id)
+            .collect();
+        conflicts.sort();
+        if !conflicts.is_empty() && policy == ConflictPolicy::Abort {
+            return Err(MergeError::Conflict(MergeConflict::new(conflicts)));
+        }
+
+        let mut changes: Vec<(RowId, Option<RowT>)> = self.changes.into_iter().collect();
+        changes.sort_by_key(|(id, _row)| *id);
+        let mut mapping = IdMapping::new();
+        let merged: Result<(), TransactionError<Infallible>> = parent.transaction(|tx| {
+            for (id, row) in changes {
+                if policy == ConflictPolicy::PreferParent && conflicts.binary_search(&id).is_ok() {
+                    continue;
+                }
+                match row {
+                    Some(row) => tx.replace(id, row),
+                    None => {
+                        tx.delete(id);
+                    }
+                }
+            }
+            // Inserted ids count down, so walking them backwards keeps the
+            // branch's insertion order
+            for (id, row) in self.inserted.into_iter().rev() {
+                mapping.insert(id, tx.insert(row));
+            }
+            Ok(())
+        });
+        merged.map_err(|err| MergeError::Unique(err.into()))?;
+        Ok(mapping)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn branch_changes_are_isolated_until_merge() {
+        let mut hs = HashSync::new();
+        let row_to_delete = hs.insert((1, 2));
+        let row_to_replace = hs.insert((1, 3));
+        let index = hs.index(|&(a, _b)| a);
+
+        let mut branch = hs.branch();
+        branch.delete(row_to_delete);
+        branch.replace(row_to_replace, (2, 3));
+        let inserted = branch.insert((2, 4));
+
+        assert_eq!(branch.by_id(row_to_delete), None);
+        assert_eq!(branch.by_id(inserted), Some((2, 4)));
+        assert_eq!(branch.keys().len(), 2);
+        assert_eq!(index.get_values(&1).len(), 2);
+        assert!(index.get_values(&2).is_empty());
+
+        branch
+            .merge_into_parent(&mut hs, ConflictPolicy::Abort)
+            .unwrap();
+        assert_eq!(hs.by_id(row_to_delete), None);
+        assert!(index.get_values(&1).is_empty());
+        let rows = index.get_values(&2);
+        assert_eq!(rows.len(), 2);
+        assert!(rows.contains(&(2, 3)));
+        assert!(rows.contains(&(2, 4)));
+    }
+
+    #[test]
+    fn merge_conflicts() {
+        let mut hs = HashSync::new();
+        let row_id = hs.insert((1, 2));
+
+        let mut branch = hs.branch();
+        branch.replace(row_id, (1, 3));
+        hs.replace(row_id, (1, 4));
+
+        let err = branch
+            .merge_into_parent(&mut hs, ConflictPolicy::Abort)
+            .unwrap_err();
+        assert_eq!(err, MergeError::Conflict(MergeConflict::new(vec![row_id])));
+        assert_eq!(hs.by_id(row_id), Some((1, 4)));
+
+        let mut branch = hs.branch();
+        branch.replace(row_id, (1, 5));
+        let inserted = branch.insert((2, 2));
+        hs.replace(row_id, (1, 6));
+        let mapping = branch
+            .merge_into_parent(&mut hs, ConflictPolicy::PreferParent)
+            .unwrap();
+        assert_eq!(hs.by_id(row_id), Some((1, 6)));
+        assert_eq!(hs.by_id(mapping.get(inserted).unwrap()), Some((2, 2)));
+
+        let mut branch = hs.branch();
+        branch.replace(row_id, (1, 7));
+        hs.replace(row_id, (1, 8));
+        branch
+            .merge_into_parent(&mut hs, ConflictPolicy::PreferChild)
+            .unwrap();
+        assert_eq!(hs.by_id(row_id), Some((1, 7)));
+    }
+
+    #[test]
+    fn unique_violations_abort_the_merge() {
+        let mut hs = HashSync::new();
+        let row_id = hs.insert((1, 2));
+        let by_b = hs.unique_index(|&(_a, b)| b).unwrap();
+
+        let mut branch = hs.branch();
+        branch.replace(row_id, (1, 3));
+        branch.insert((2, 3));
+        let err = branch
+            .merge_into_parent(&mut hs, ConflictPolicy::Abort)
+            .unwrap_err();
+        assert!(matches!(err, MergeError::Unique(_)));
+        assert_eq!(hs.by_id(row_id), Some((1, 2)));
+        assert_eq!(by_b.get_id(&2), Some(row_id));
+        assert_eq!(hs.keys().len(), 1);
+    }
+
+    #[test]
+    fn parent_inserts_after_branching_survive_merge() {
+        let mut hs = HashSync::new();
+        let row_id = hs.insert((1, 2));
+        let index = hs.index(|&(a, _b)| a);
+
+        let mut branch = hs.branch();
+        let branch_row = branch.insert((2, 1));
+        let parent_row = hs.insert((3, 1));
+        hs.replace(row_id, (1, 3));
+        assert_ne!(branch_row, parent_row);
+        assert_eq!(branch.by_id(row_id), Some((1, 3)));
+        assert_eq!(branch.by_id(parent_row), Some((3, 1)));
+        assert_eq!(branch.keys(), vec![row_id, parent_row, branch_row]);
+        assert_eq!(branch.by_id(branch_row), Some((2, 1)));
+
+        let mapping = branch
+            .merge_into_parent(&mut hs, ConflictPolicy::Abort)
+            .unwrap();
+        let merged = mapping.get(branch_row).unwrap();
+        assert_ne!(merged, parent_row);
+        assert_eq!(hs.by_id(parent_row), Some((3, 1)));
+        assert_eq!(hs.by_id(merged), Some((2, 1)));
+        assert_eq!(hs.by_id(row_id), Some((1, 3)));
+        assert_eq!(index.get_values(&3), vec![(3, 1)]);
+
+        let mut branch = hs.branch();
+        let branch_row = branch.insert((4, 1));
+        let parent_row = hs.insert((5, 1));
+        let mapping = branch
+            .merge_into_parent(&mut hs, ConflictPolicy::PreferChild)
+            .unwrap();
+        assert_eq!(hs.by_id(parent_row), Some((5, 1)));
+        assert_eq!(hs.by_id(mapping.get(branch_row).unwrap()), Some((4, 1)));
+    }
+
+    #[test]
+    fn branch_is_a_live_view_of_the_parent() {
+        let mut hs = HashSync::new();
+        let kept = hs.insert((1, 1));
+        let staged = hs.insert((1, 2));
+        let index = hs.index(|&(a, _b)| a);
+
+        let mut branch = hs.branch();
+        branch.replace(staged, (2, 2));
+        let first = branch.insert((1, 3));
+        let second = branch.insert((1, 4));
+        let later = hs.insert((1, 5));
+        hs.delete(kept);
+
+        assert_eq!(branch.by_id(kept), None);
+        assert_eq!(branch.by_id(later), Some((1, 5)));
+        assert_eq!(branch.keys(), vec![staged, later, first, second]);
+        let ids: Vec<RowId> = branch
+            .get_by(&index, &1)
+            .into_iter()
+            .map(|row| row.id())
+            .collect();
+        assert_eq!(ids, vec![later, first, second]);
+        assert_eq!(
+            branch.get_by(&index, &2),
+            vec![Indexed::new(staged, (2, 2))]
+        );
+
+        branch.delete(later);
+        assert!(branch
+            .get_by(&index, &1)
+            .iter()
+            .all(|row| row.id() != later));
+        let mapping = branch
+            .merge_into_parent(&mut hs, ConflictPolicy::Abort)
+            .unwrap();
+        assert_eq!(hs.by_id(later), None);
+        let merged = vec![mapping.get(first).unwrap(), mapping.get(second).unwrap()];
+        assert_eq!(index.get_ids(&1), merged);
+        assert_eq!(hs.by_id(merged[0]), Some((1, 3)));
+    }
+}
@@ -0,0 +1,34 @@
+use crate::id::{Indexed, RowId};
+
+pub struct BulkLoader<RowT> {
+    next_id: RowId,
+    rows: Vec<Indexed<RowT>>,
+}
+
+impl<RowT> BulkLoader<RowT> {
+    pub(crate) fn new(next_id: RowId) -> Self {
+        BulkLoader {
+            next_id,
+            rows: Vec::new(),
+        }
+    }
+
+    pub(crate) fn into_rows(self) -> Vec<Indexed<RowT>> {
+        self.rows
+    }
+
+    pub fn insert(&mut self, row: RowT) -> RowId {
+        let id = self.next_id;
+        self.rows.push(Indexed::new(id, row));
+        self.next_id = self.next_id.next();
+        id
+    }
+
+    pub fn len(&self) -> usize {
+        self.rows.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rows.is_empty()
+    }
+}
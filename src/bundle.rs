@@ -0,0 +1,132 @@
+use std::hash::Hash;
+
+use crate::{hashsync::HashSync, index::IndexRead};
+
+pub trait IndexBundle<'a, RowT> {
+    type Reads;
+
+    fn register(self, hs: &mut HashSync<'a, RowT>) -> Self::Reads;
+}
+
+macro_rules! impl_index_bundle {
+    ($(($index_fn:ident, $key:ident)),+) => {
+        impl<'a, RowT: Clone + 'a, $($index_fn, $key),+> IndexBundle<'a, RowT> for ($($index_fn,)+)
+        where
+            $(
+                $index_fn: Fn(&RowT) -> $key + Send + Sync + 'static,
+                $key: PartialEq + Eq + Hash + Send + Sync + 'a,
+            )+
+        {
+            type Reads = ($(IndexRead<$key, RowT>,)+);
+
+            #[allow(non_snake_case)]
+            fn register(self, hs: &mut HashSync<'a, RowT>) -> Self::Reads {
+                let ($($index_fn,)+) = self;
+                hs.with_index_batch(|reg| ($(reg.index($index_fn),)+))
+            }
+        }
+    };
+}
+
+impl_index_bundle!((F1, K1));
+impl_index_bundle!((F1, K1), (F2, K2));
+impl_index_bundle!((F1, K1), (F2, K2), (F3, K3));
+impl_index_bundle!((F1, K1), (F2, K2), (F3, K3), (F4, K4));
+impl_index_bundle!((F1, K1), (F2, K2), (F3, K3), (F4, K4), (F5, K5));
+impl_index_bundle!((F1, K1), (F2, K2), (F3, K3), (F4, K4), (F5, K5), (F6, K6));
+
+pub struct IndexRegistrar<'r, 'a, RowT> {
+    hs: &'r mut HashSync<'a, RowT>,
+}
+
+impl<'a, RowT: Clone + 'a> IndexRegistrar<'_, 'a, RowT> {
+    pub fn index<IndexKeyT, IndexFn>(&mut self, index_fn: IndexFn) -> IndexRead<IndexKeyT, RowT>
+    where
+        IndexFn: Fn(&RowT) -> IndexKeyT + Send + Sync + 'static,
+        IndexKeyT: PartialEq + Eq + Hash + Send + Sync + 'a,
+    {
+        self.index_many(move |row: &RowT| vec![index_fn(row)])
+    }
+
+    pub fn index_many<IndexKeyT, IndexFn>(
+        &mut self,
+        index_fn: IndexFn,
+    ) -> IndexRead<IndexKeyT, RowT>
+    where
+        IndexFn: Fn(&RowT) -> Vec<IndexKeyT> + Send + Sync + 'static,
+        IndexKeyT: PartialEq + Eq + Hash + Send + Sync + 'a,
+    {
+        self.hs.index_many_unpopulated(index_fn)
+    }
+}
+
+impl<'a, RowT: Clone + 'a> HashSync<'a, RowT> {
+    pub fn indexes<BundleT: IndexBundle<'a, RowT>>(&mut self, bundle: BundleT) -> BundleT::Reads {
+        bundle.register(self)
+    }
+
+    pub fn with_index_batch<R, BatchFn>(&mut self, batch_fn: BatchFn) -> R
+    where
+        BatchFn: FnOnce(&mut IndexRegistrar<'_, 'a, RowT>) -> R,
+    {
+        let start = self.index_count();
+        let result = batch_fn(&mut IndexRegistrar { hs: self });
+        self.populate_indexes_from(start);
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    use super::*;
+
+    #[test]
+    fn bundle_builds_in_one_scan() {
+        let mut hs = HashSync::new();
+        hs.insert((1, "a".to_string(), true));
+        hs.insert((2, "a".to_string(), false));
+        hs.insert((2, "b".to_string(), true));
+
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counted = calls.clone();
+        let (by_number, by_name, by_flag) = hs.indexes((
+            move |row: &(i32, String, bool)| {
+                counted.fetch_add(1, Ordering::SeqCst);
+                row.0
+            },
+            |row: &(i32, String, bool)| row.1.clone(),
+            |row: &(i32, String, bool)| row.2,
+        ));
+
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+        assert_eq!(by_number.get(&2).len(), 2);
+        assert_eq!(by_name.get(&"a".to_string()).len(), 2);
+        assert_eq!(by_flag.get(&true).len(), 2);
+
+        hs.insert((1, "c".to_string(), false));
+        assert_eq!(by_number.get(&1).len(), 2);
+        hs.verify_indexes().unwrap();
+    }
+
+    #[test]
+    fn batch_registration() {
+        let mut hs = HashSync::new();
+        hs.insert_batch(vec![(1, vec!["x", "y"]), (2, vec!["y"])])
+            .unwrap();
+
+        let mut by_number = None;
+        let by_tag = hs.with_index_batch(|reg| {
+            by_number = Some(reg.index(|row: &(i32, Vec<&str>)| row.0));
+            reg.index_many(|row: &(i32, Vec<&str>)| row.1.clone())
+        });
+
+        assert_eq!(by_number.unwrap().get(&1).len(), 1);
+        assert_eq!(by_tag.get(&"y").len(), 2);
+        hs.verify_indexes().unwrap();
+    }
+}
@@ -0,0 +1,167 @@
+use std::sync::mpsc::{self, Receiver, Sender};
+
+use crate::{
+    describe::IndexDescription,
+    id::{Indexed, RowId},
+    index::{IndexId, Indexable},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RowOp {
+    Insert,
+    Delete,
+    Replace,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RowEvent<RowT> {
+    seq: u64,
+    id: RowId,
+    op: RowOp,
+    old: Option<RowT>,
+    new: Option<RowT>,
+}
+
+impl<RowT> RowEvent<RowT> {
+    pub fn seq(&self) -> u64 {
+        self.seq
+    }
+
+    pub fn id(&self) -> RowId {
+        self.id
+    }
+
+    pub fn op(&self) -> RowOp {
+        self.op
+    }
+
+    pub fn old_value(&self) -> Option<&RowT> {
+        self.old.as_ref()
+    }
+
+    pub fn new_value(&self) -> Option<&RowT> {
+        self.new.as_ref()
+    }
+}
+
+pub struct ChangeFeed<RowT> {
+    next_seq: u64,
+    senders: Vec<Sender<RowEvent<RowT>>>,
+}
+
+impl<RowT: Clone> ChangeFeed<RowT> {
+    pub fn new() -> Self {
+        ChangeFeed {
+            next_seq: 0,
+            senders: Vec::new(),
+        }
+    }
+
+    pub fn subscribe(&mut self) -> Receiver<RowEvent<RowT>> {
+        let (sender, receiver) = mpsc::channel();
+        self.senders.push(sender);
+        receiver
+    }
+
+    fn publish(&mut self, id: RowId, op: RowOp, old: Option<&RowT>, new: Option<&RowT>) {
+        if self.senders.is_empty() {
+            return;
+        }
+        let event = RowEvent {
+            seq: self.next_seq,
+            id,
+            op,
+            old: old.cloned(),
+            new: new.cloned(),
+        };
+        self.next_seq += 1;
+        self.senders
+            .retain(|sender| sender.send(event.clone()).is_ok());
+    }
+}
+
+impl<RowT: Clone> Default for ChangeFeed<RowT> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<RowT: Clone> Indexable<RowT> for ChangeFeed<RowT> {
+    fn insert(&mut self, row: &Indexed<RowT>) -> IndexId {
+        self.publish(row.id(), RowOp::Insert, None, Some(row.value()));
+        IndexId::new(0)
+    }
+
+    fn delete(&mut self, row: &Indexed<RowT>) {
+        self.publish(row.id(), RowOp::Delete, Some(row.value()), None);
+    }
+
+    fn update(&mut self, old: &Indexed<RowT>, new: &Indexed<RowT>) {
+        self.publish(
+            new.id(),
+            RowOp::Replace,
+            Some(old.value()),
+            Some(new.value()),
+        );
+    }
+
+    fn describe(&self) -> IndexDescription {
+        IndexDescription::new("changes").with_option("subscribers", self.senders.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hashsync::HashSync;
+
+    #[test]
+    fn changes_stream_every_mutation() {
+        let mut hs = HashSync::new();
+        hs.insert((1, 1));
+        let changes = hs.changes();
+        let id = hs.insert((1, 2));
+        hs.replace(id, (1, 3));
+        hs.delete(id);
+
+        let events: Vec<RowEvent<(i32, i32)>> = changes.try_iter().collect();
+        let summary: Vec<_> = events
+            .iter()
+            .map(|event| {
+                (
+                    event.seq(),
+                    event.op(),
+                    event.old_value().copied(),
+                    event.new_value().copied(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                (0, RowOp::Insert, None, Some((1, 2))),
+                (1, RowOp::Replace, Some((1, 2)), Some((1, 3))),
+                (2, RowOp::Delete, Some((1, 3)), None),
+            ]
+        );
+        assert!(events.iter().all(|event| event.id() == id));
+    }
+
+    #[test]
+    fn changes_outlive_drop_indexes() {
+        let mut hs = HashSync::new();
+        let _index = hs.index(|&(a, _b)| a);
+        let changes = hs.changes();
+        hs.insert((1, 1));
+
+        let mut hs = hs.drop_indexes();
+        let id = hs.insert((2, 2));
+        let later = hs.changes();
+        hs.delete(id);
+
+        let ops: Vec<_> = changes.try_iter().map(|event| event.op()).collect();
+        assert_eq!(ops, vec![RowOp::Insert, RowOp::Insert, RowOp::Delete]);
+        let later: Vec<_> = later.try_iter().map(|event| event.seq()).collect();
+        assert_eq!(later, vec![2]);
+    }
+}
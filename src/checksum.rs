@@ -0,0 +1,85 @@
+use std::{hash::Hash, marker::PhantomData};
+
+use fxhash::hash64;
+
+use crate::{
+    describe::IndexDescription,
+    id::Indexed,
+    index::{IndexId, Indexable},
+};
+
+pub struct Checksum<ValueT> {
+    value: u64,
+    _marker: PhantomData<fn(&ValueT)>,
+}
+
+impl<ValueT> Checksum<ValueT> {
+    pub fn value(&self) -> u64 {
+        self.value
+    }
+}
+
+impl<ValueT: Hash> Checksum<ValueT> {
+    pub fn new() -> Self {
+        Checksum {
+            value: 0,
+            _marker: PhantomData,
+        }
+    }
+
+    fn row_hash(row: &Indexed<ValueT>) -> u64 {
+        hash64(&(row.id(), row.value()))
+    }
+}
+
+impl<ValueT: Hash> Default for Checksum<ValueT> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<ValueT: Hash> Indexable<ValueT> for Checksum<ValueT> {
+    fn insert(&mut self, row: &Indexed<ValueT>) -> IndexId {
+        // Summing per-row hashes keeps the checksum independent of insertion order
+        self.value = self.value.wrapping_add(Self::row_hash(row));
+        IndexId::new(0)
+    }
+
+    fn delete(&mut self, row: &Indexed<ValueT>) {
+        self.value = self.value.wrapping_sub(Self::row_hash(row));
+    }
+
+    fn describe(&self) -> IndexDescription {
+        IndexDescription::new("checksum")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{hashsync::HashSync, id::RowId};
+
+    #[test]
+    fn checksum_is_order_independent() {
+        let mut hs1 = HashSync::new();
+        hs1.enable_checksum();
+        hs1.replace(RowId::new(0), (1, 2));
+        hs1.replace(RowId::new(1), (3, 4));
+
+        let mut hs2 = HashSync::new();
+        hs2.replace(RowId::new(1), (3, 4));
+        hs2.replace(RowId::new(0), (1, 2));
+        hs2.enable_checksum();
+
+        assert_eq!(hs1.checksum(), hs2.checksum());
+
+        hs2.replace(RowId::new(0), (1, 3));
+        assert_ne!(hs1.checksum(), hs2.checksum());
+        hs2.replace(RowId::new(0), (1, 2));
+        assert_eq!(hs1.checksum(), hs2.checksum());
+
+        hs1.delete(RowId::new(1));
+        hs1.delete(RowId::new(0));
+        assert_eq!(hs1.checksum(), Some(0));
+        assert_eq!(HashSync::<(i32, i32)>::new().checksum(), None);
+    }
+}
@@ -0,0 +1,311 @@
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, RwLock},
+    time::{Instant, SystemTime, UNIX_EPOCH},
+};
+
+use dashmap::DashMap;
+use fxhash::{FxHashMap, FxHashSet};
+
+use crate::{
+    deadline::Deadlines,
+    describe::IndexDescription,
+    id::{Indexed, RowId},
+    index::{IndexId, Indexable},
+    stamp::Stamp,
+};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Timestamp {
+    physical_ms: u64,
+    logical: u32,
+}
+
+impl Timestamp {
+    pub fn new(physical_ms: u64, logical: u32) -> Self {
+        Timestamp {
+            physical_ms,
+            logical,
+        }
+    }
+
+    pub fn physical_ms(&self) -> u64 {
+        self.physical_ms
+    }
+
+    pub fn logical(&self) -> u32 {
+        self.logical
+    }
+
+    // A logical counter that runs out borrows the next millisecond rather
+    // than wrapping back below earlier timestamps
+    fn successor(&self) -> Timestamp {
+        match self.logical.checked_add(1) {
+            Some(logical) => Timestamp::new(self.physical_ms, logical),
+            None => Timestamp::new(self.physical_ms + 1, 0),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct HybridClock {
+    last: Timestamp,
+}
+
+impl HybridClock {
+    pub fn new() -> Self {
+        HybridClock::default()
+    }
+
+    pub fn last(&self) -> Timestamp {
+        self.last
+    }
+
+    pub fn now(&mut self) -> Timestamp {
+        self.tick(wall_clock_ms())
+    }
+
+    pub fn observe(&mut self, remote: Timestamp) -> Timestamp {
+        let physical_ms = wall_clock_ms();
+        let latest = self.last.max(remote);
+        self.last = if physical_ms > latest.physical_ms {
+            Timestamp::new(physical_ms, 0)
+        } else {
+            latest.successor()
+        };
+        self.last
+    }
+
+    fn tick(&mut self, physical_ms: u64) -> Timestamp {
+        // The wall clock may stall or step backwards; the logical counter
+        // keeps timestamps strictly increasing until it catches up
+        self.last = if physical_ms > self.last.physical_ms {
+            Timestamp::new(physical_ms, 0)
+        } else {
+            self.last.successor()
+        };
+        self.last
+    }
+}
+
+fn wall_clock_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_millis() as u64)
+}
+
+// A row's timestamp marks its last write: replaces and updates re-stamp it,
+// so since() returns every row written after the given time
+pub struct TimestampIndex {
+    clock: HybridClock,
+    by_id: FxHashMap<RowId, Timestamp>,
+    by_timestamp: BTreeMap<Timestamp, RowId>,
+}
+
+impl TimestampIndex {
+    pub fn new(clock: HybridClock) -> Self {
+        TimestampIndex {
+            clock,
+            by_id: FxHashMap::default(),
+            by_timestamp: BTreeMap::new(),
+        }
+    }
+
+    pub fn get(&self, id: RowId) -> Option<Timestamp> {
+        self.by_id.get(&id).copied()
+    }
+
+    pub fn since(&self, timestamp: Timestamp) -> Vec<(RowId, Timestamp)> {
+        self.by_timestamp
+            .range(timestamp..)
+            .map(|(timestamp, id)| (*id, *timestamp))
+            .collect()
+    }
+}
+
+impl<ValueT> Indexable<ValueT> for TimestampIndex {
+    fn insert(&mut self, row: &Indexed<ValueT>) -> IndexId {
+        let timestamp = self.clock.now();
+        if let Some(previous) = self.by_id.insert(row.id(), timestamp) {
+            self.by_timestamp.remove(&previous);
+        }
+        self.by_timestamp.insert(timestamp, row.id());
+        IndexId::new(0)
+    }
+
+    fn delete(&mut self, row: &Indexed<ValueT>) {
+        if let Some(timestamp) = self.by_id.remove(&row.id()) {
+            self.by_timestamp.remove(&timestamp);
+        }
+    }
+
+    fn verify(&self, rows: &[Indexed<ValueT>]) -> (Vec<RowId>, Vec<RowId>) {
+        let row_ids: FxHashSet<RowId> = rows.iter().map(|row| row.id()).collect();
+        let mut leaked: Vec<RowId> = self
+            .by_id
+            .keys()
+            .filter(|id| !row_ids.contains(id))
+            .copied()
+            .collect();
+        let mut missing: Vec<RowId> = row_ids
+            .into_iter()
+            .filter(|id| !self.by_id.contains_key(id))
+            .collect();
+        leaked.sort();
+        missing.sort();
+        (leaked, missing)
+    }
+
+    fn describe(&self) -> IndexDescription {
+        IndexDescription::new("timestamp")
+    }
+}
+
+pub struct TimestampRead<ValueT> {
+    rows: Arc<DashMap<RowId, ValueT>>,
+    index: Arc<RwLock<TimestampIndex>>,
+    stamp: Stamp,
+    deadlines: Deadlines,
+}
+
+impl<ValueT> Clone for TimestampRead<ValueT> {
+    fn clone(&self) -> Self {
+        TimestampRead {
+            rows: self.rows.clone(),
+            index: self.index.clone(),
+            stamp: self.stamp.clone(),
+            deadlines: self.deadlines.clone(),
+        }
+    }
+}
+
+impl<ValueT: Clone> TimestampRead<ValueT> {
+    pub fn new(rows: Arc<DashMap<RowId, ValueT>>, index: Arc<RwLock<TimestampIndex>>) -> Self {
+        TimestampRead {
+            rows,
+            index,
+            stamp: Stamp::default(),
+            deadlines: Deadlines::default(),
+        }
+    }
+
+    pub(crate) fn stamped(self, stamp: Stamp, deadlines: Deadlines) -> Self {
+        TimestampRead {
+            stamp,
+            deadlines,
+            ..self
+        }
+    }
+
+    pub fn is_live(&self) -> bool {
+        self.stamp.is_current()
+    }
+
+    pub fn timestamp(&self, id: RowId) -> Option<Timestamp> {
+        self.stamp.consistent(|| {
+            if !self.is_live() || self.deadlines.is_expired(id, Instant::now()) {
+                return None;
+            }
+            self.index.read().unwrap().get(id)
+        })
+    }
+
+    pub fn since(&self, timestamp: Timestamp) -> Vec<(Indexed<ValueT>, Timestamp)> {
+        self.stamp.consistent(|| {
+            if !self.is_live() {
+                return Vec::new();
+            }
+            let entries = self.index.read().unwrap().since(timestamp);
+            let now = Instant::now();
+            entries
+                .into_iter()
+                .filter(|(id, _timestamp)| !self.deadlines.is_expired(*id, now))
+                .filter_map(|(id, timestamp)| {
+                    self.rows
+                        .get(&id)
+                        .map(|value| (Indexed::new(id, value.clone()), timestamp))
+                })
+                .collect()
+        })
+    }
+
+    pub fn last(&self) -> Timestamp {
+        self.index.read().unwrap().clock.last()
+    }
+
+    pub fn observe(&self, remote: Timestamp) -> Timestamp {
+        self.index.write().unwrap().clock.observe(remote)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hashsync::HashSync;
+
+    #[test]
+    fn clock_is_monotonic() {
+        let mut clock = HybridClock::new();
+        let first = clock.tick(100);
+        assert_eq!(first, Timestamp::new(100, 0));
+        assert_eq!(clock.tick(90), Timestamp::new(100, 1));
+        assert_eq!(clock.tick(100), Timestamp::new(100, 2));
+        assert_eq!(clock.tick(101), Timestamp::new(101, 0));
+
+        let remote = Timestamp::new(u64::MAX - 1, 5);
+        assert_eq!(clock.observe(remote), Timestamp::new(u64::MAX - 1, 6));
+        assert!(clock.now() > remote);
+    }
+
+    #[test]
+    fn logical_overflow_advances_physical() {
+        let mut clock = HybridClock::new();
+        let remote = Timestamp::new(u64::MAX - 1, u32::MAX - 1);
+        assert_eq!(
+            clock.observe(remote),
+            Timestamp::new(u64::MAX - 1, u32::MAX)
+        );
+        assert_eq!(clock.tick(0), Timestamp::new(u64::MAX, 0));
+        assert_eq!(clock.tick(0), Timestamp::new(u64::MAX, 1));
+    }
+
+    #[test]
+    fn rows_are_timestamped_on_write() {
+        let mut hs = HashSync::new();
+        let old = hs.insert((1, 2));
+        let timestamps = hs.enable_timestamps();
+        let new = hs.insert((1, 3));
+
+        let old_timestamp = timestamps.timestamp(old).unwrap();
+        let new_timestamp = timestamps.timestamp(new).unwrap();
+        assert!(new_timestamp > old_timestamp);
+
+        hs.replace(old, (1, 4));
+        let since: Vec<RowId> = timestamps
+            .since(new_timestamp)
+            .into_iter()
+            .map(|(indexed, _timestamp)| indexed.id())
+            .collect();
+        assert_eq!(since, vec![new, old]);
+        hs.delete(new);
+        assert_eq!(timestamps.timestamp(new), None);
+        assert_eq!(hs.verify_indexes(), Ok(()));
+    }
+
+    #[test]
+    fn expired_rows_have_no_timestamp() {
+        let mut hs = HashSync::new();
+        let timestamps = hs.enable_timestamps();
+        let kept = hs.insert((1, 2));
+        let expiring = hs.insert_with_ttl((1, 3), std::time::Duration::from_millis(20));
+        std::thread::sleep(std::time::Duration::from_millis(30));
+
+        assert_eq!(timestamps.timestamp(expiring), None);
+        let since: Vec<RowId> = timestamps
+            .since(Timestamp::default())
+            .into_iter()
+            .map(|(indexed, _timestamp)| indexed.id())
+            .collect();
+        assert_eq!(since, vec![kept]);
+    }
+}
@@ -0,0 +1,224 @@
+use std::{
+    collections::BTreeMap,
+    time::{Duration, Instant},
+};
+
+use fxhash::FxHashMap;
+
+use crate::{error::UniqueViolation, hashsync::HashSync, id::RowId};
+
+struct Pending<RowT> {
+    seq: u64,
+    buffered_at: Instant,
+    row: RowT,
+}
+
+// Rows are committed in the order they were first buffered, which is also
+// the order their max_delay runs out in, so order doubles as the due queue
+pub struct ReplaceCoalescer<RowT> {
+    max_delay: Duration,
+    pending: FxHashMap<RowId, Pending<RowT>>,
+    order: BTreeMap<u64, RowId>,
+    next_seq: u64,
+}
+
+impl<RowT: Clone> ReplaceCoalescer<RowT> {
+    pub fn new(max_delay: Duration) -> Self {
+        ReplaceCoalescer {
+            max_delay,
+            pending: FxHashMap::default(),
+            order: BTreeMap::new(),
+            next_seq: 0,
+        }
+    }
+
+    pub fn pending_len(&self) -> usize {
+        self.pending.len()
+    }
+
+    pub fn pending(&self, id: RowId) -> Option<&RowT> {
+        self.pending.get(&id).map(|pending| &pending.row)
+    }
+
+    pub fn replace<'a>(
+        &mut self,
+        hs: &mut HashSync<'a, RowT>,
+        id: RowId,
+        row: RowT,
+    ) -> Result<usize, UniqueViolation>
+    where
+        RowT: 'a,
+    {
+        // Keep the time of the first buffered write so a steady stream of
+        // replaces still gets committed once max_delay has passed
+        let now = Instant::now();
+        match self.pending.get_mut(&id) {
+            Some(pending) => pending.row = row,
+            None => {
+                let seq = self.next_seq;
+                self.next_seq += 1;
+                self.order.insert(seq, id);
+                self.pending.insert(
+                    id,
+                    Pending {
+                        seq,
+                        buffered_at: now,
+                        row,
+                    },
+                );
+            }
+        }
+        self.flush_due(hs, now)
+    }
+
+    // A replace a unique index rejects is dropped and its error returned,
+    // leaving the rows buffered after it pending
+    pub fn flush_due<'a>(
+        &mut self,
+        hs: &mut HashSync<'a, RowT>,
+        now: Instant,
+    ) -> Result<usize, UniqueViolation>
+    where
+        RowT: 'a,
+    {
+        let mut committed = 0;
+        while let Some((_seq, id)) = self.order.first_key_value() {
+            let pending = &self.pending[id];
+            if now.saturating_duration_since(pending.buffered_at) < self.max_delay {
+                break;
+            }
+            let id = *id;
+            committed += usize::from(self.commit_next(hs, id)?);
+        }
+        Ok(committed)
+    }
+
+    pub fn flush<'a>(&mut self, hs: &mut HashSync<'a, RowT>) -> Result<usize, UniqueViolation>
+    where
+        RowT: 'a,
+    {
+        let mut committed = 0;
+        while let Some((_seq, id)) = self.order.first_key_value() {
+            let id = *id;
+            committed += usize::from(self.commit_next(hs, id)?);
+        }
+        Ok(committed)
+    }
+
+    fn commit_next<'a>(
+        &mut self,
+        hs: &mut HashSync<'a, RowT>,
+        id: RowId,
+    ) -> Result<bool, UniqueViolation>
+    where
+        RowT: 'a,
+    {
+        let pending = self.pending.remove(&id).unwrap();
+        self.order.remove(&pending.seq);
+        commit(hs, id, pending.row)
+    }
+}
+
+// A row deleted while its replace was buffered stays deleted; replace would
+// otherwise insert it again
+fn commit<'a, RowT: Clone + 'a>(
+    hs: &mut HashSync<'a, RowT>,
+    id: RowId,
+    row: RowT,
+) -> Result<bool, UniqueViolation> {
+    hs.update_unique(id, |current| *current = row)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn coalesces_replaces_until_flushed() {
+        let mut hs = HashSync::new();
+        let row_id = hs.insert((1, 0));
+        let index = hs.index(|&(_a, b)| b);
+        let version = hs.current_version();
+
+        let mut coalescer = ReplaceCoalescer::new(Duration::from_secs(3600));
+        for i in 1..=10 {
+            assert_eq!(coalescer.replace(&mut hs, row_id, (1, i)).unwrap(), 0);
+        }
+        assert_eq!(coalescer.pending_len(), 1);
+        assert_eq!(coalescer.pending(row_id), Some(&(1, 10)));
+        assert_eq!(hs.by_id(row_id), Some((1, 0)));
+        assert_eq!(hs.current_version(), version);
+
+        assert_eq!(coalescer.flush(&mut hs).unwrap(), 1);
+        assert_eq!(hs.by_id(row_id), Some((1, 10)));
+        assert_eq!(index.get_values(&10), vec![(1, 10)]);
+        assert_eq!(hs.current_version(), version + 1);
+    }
+
+    #[test]
+    fn commits_once_max_delay_has_passed() {
+        let mut hs = HashSync::new();
+        let row_id = hs.insert((1, 0));
+
+        let mut coalescer = ReplaceCoalescer::new(Duration::ZERO);
+        assert_eq!(coalescer.replace(&mut hs, row_id, (1, 1)).unwrap(), 1);
+        assert_eq!(coalescer.pending_len(), 0);
+        assert_eq!(hs.by_id(row_id), Some((1, 1)));
+    }
+
+    #[test]
+    fn flush_skips_deleted_rows() {
+        let mut hs = HashSync::new();
+        let deleted = hs.insert((1, 0));
+        let kept = hs.insert((2, 0));
+
+        let mut coalescer = ReplaceCoalescer::new(Duration::from_secs(3600));
+        coalescer.replace(&mut hs, deleted, (1, 1)).unwrap();
+        coalescer.replace(&mut hs, kept, (2, 1)).unwrap();
+        hs.delete(deleted);
+
+        assert_eq!(coalescer.flush(&mut hs).unwrap(), 1);
+        assert_eq!(coalescer.pending_len(), 0);
+        assert_eq!(hs.by_id(deleted), None);
+        assert_eq!(hs.by_id(kept), Some((2, 1)));
+    }
+
+    #[test]
+    fn rejected_replace_stops_flush_and_keeps_the_rest_pending() {
+        let mut hs = HashSync::new();
+        let taken = hs.insert((1, 0));
+        let moved = hs.insert((2, 0));
+        let later = hs.insert((3, 0));
+        hs.unique_index(|&(a, _b)| a).unwrap();
+
+        let mut coalescer = ReplaceCoalescer::new(Duration::from_secs(3600));
+        coalescer.replace(&mut hs, moved, (1, 1)).unwrap();
+        coalescer.replace(&mut hs, later, (3, 1)).unwrap();
+
+        assert!(coalescer.flush(&mut hs).is_err());
+        assert_eq!(hs.by_id(taken), Some((1, 0)));
+        assert_eq!(hs.by_id(moved), Some((2, 0)));
+        assert_eq!(coalescer.pending_len(), 1);
+        assert_eq!(coalescer.flush(&mut hs).unwrap(), 1);
+        assert_eq!(hs.by_id(later), Some((3, 1)));
+    }
+
+    #[test]
+    fn flush_commits_in_buffered_order() {
+        let mut hs = HashSync::new();
+        let ids = hs.insert_batch((0..50).map(|i| (i, 0))).unwrap();
+        let changes = hs.changes();
+
+        let mut coalescer = ReplaceCoalescer::new(Duration::from_secs(3600));
+        for id in ids.iter().rev() {
+            coalescer.replace(&mut hs, *id, (0, 1)).unwrap();
+        }
+        coalescer.replace(&mut hs, ids[49], (0, 2)).unwrap();
+
+        assert_eq!(coalescer.flush(&mut hs).unwrap(), 50);
+        let committed: Vec<RowId> = changes.try_iter().map(|event| event.id()).collect();
+        let expected: Vec<RowId> = ids.iter().rev().copied().collect();
+        assert_eq!(committed, expected);
+        assert_eq!(hs.by_id(ids[49]), Some((0, 2)));
+    }
+}
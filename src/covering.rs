@@ -0,0 +1,218 @@
+use std::{
+    any::type_name,
+    hash::Hash,
+    sync::{Arc, RwLock},
+    time::Instant,
+};
+
+use dashmap::DashMap;
+use fxhash::FxHashMap;
+
+use crate::{
+    deadline::Deadlines,
+    describe::IndexDescription,
+    eviction::LruTracker,
+    id::{Indexed, RowId},
+    index::{hashed_entries, verify_entries, IndexId, Indexable},
+    stamp::Stamp,
+};
+
+pub type CoveringIndexFunction<KeyT, PayloadT, ValueT> =
+    Box<dyn Fn(&Indexed<ValueT>) -> Vec<(KeyT, PayloadT)> + Send + Sync>;
+
+pub struct CoveringIndex<KeyT, PayloadT, ValueT> {
+    index_function: CoveringIndexFunction<KeyT, PayloadT, ValueT>,
+    index: FxHashMap<KeyT, FxHashMap<RowId, PayloadT>>,
+}
+
+impl<KeyT: PartialEq + Eq + Hash, PayloadT, ValueT> CoveringIndex<KeyT, PayloadT, ValueT> {
+    pub fn new(index_function: CoveringIndexFunction<KeyT, PayloadT, ValueT>) -> Self {
+        CoveringIndex {
+            index_function,
+            index: FxHashMap::default(),
+        }
+    }
+
+    pub fn get_ref(&self, key: &KeyT) -> Option<&FxHashMap<RowId, PayloadT>> {
+        self.index.get(key)
+    }
+
+    pub fn payload(&self, key: &KeyT, id: RowId) -> Option<&PayloadT> {
+        self.index.get(key)?.get(&id)
+    }
+
+    pub fn key_count(&self) -> usize {
+        self.index.len()
+    }
+}
+
+impl<KeyT: PartialEq + Eq + Hash, PayloadT, ValueT> Indexable<ValueT>
+    for CoveringIndex<KeyT, PayloadT, ValueT>
+{
+    fn insert(&mut self, row: &Indexed<ValueT>) -> IndexId {
+        for (key, payload) in (self.index_function)(row) {
+            self.index.entry(key).or_default().insert(row.id(), payload);
+        }
+        IndexId::new(0)
+    }
+
+    fn delete(&mut self, row: &Indexed<ValueT>) {
+        for (key, _payload) in (self.index_function)(row) {
+            if let Some(payloads) = self.index.get_mut(&key) {
+                payloads.remove(&row.id());
+                if payloads.is_empty() {
+                    self.index.remove(&key);
+                }
+            }
+        }
+    }
+
+    fn verify(&self, rows: &[Indexed<ValueT>]) -> (Vec<RowId>, Vec<RowId>) {
+        let expected = rows
+            .iter()
+            .map(|row| {
+                let keys = (self.index_function)(row)
+                    .into_iter()
+                    .map(|(key, _payload)| key)
+                    .collect();
+                (row.id(), keys)
+            })
+            .collect();
+        let entries = self
+            .index
+            .iter()
+            .flat_map(|(key, payloads)| payloads.keys().map(move |id| (key, *id)));
+        verify_entries(expected, entries)
+    }
+
+    fn dump_entries(&self) -> Vec<(String, Vec<RowId>)> {
+        hashed_entries(
+            self.index
+                .iter()
+                .map(|(key, payloads)| (key, payloads.keys().copied().collect())),
+        )
+    }
+
+    fn describe(&self) -> IndexDescription {
+        IndexDescription::new("covering")
+            .keyed_by::<KeyT>()
+            .with_option("payload", type_name::<PayloadT>())
+    }
+}
+
+pub struct CoveringIndexRead<KeyT, PayloadT, ValueT> {
+    rows: Arc<DashMap<RowId, ValueT>>,
+    index: Arc<RwLock<CoveringIndex<KeyT, PayloadT, ValueT>>>,
+    stamp: Stamp,
+    deadlines: Deadlines,
+    lru: Option<Arc<LruTracker>>,
+}
+
+impl<KeyT, PayloadT, ValueT> Clone for CoveringIndexRead<KeyT, PayloadT, ValueT> {
+    fn clone(&self) -> Self {
+        CoveringIndexRead {
+            rows: self.rows.clone(),
+            index: self.index.clone(),
+            stamp: self.stamp.clone(),
+            deadlines: self.deadlines.clone(),
+            lru: self.lru.clone(),
+        }
+    }
+}
+
+impl<KeyT: PartialEq + Eq + Hash, PayloadT: Clone, ValueT: Clone>
+    CoveringIndexRead<KeyT, PayloadT, ValueT>
+{
+    pub fn new(
+        rows: Arc<DashMap<RowId, ValueT>>,
+        index: Arc<RwLock<CoveringIndex<KeyT, PayloadT, ValueT>>>,
+    ) -> Self {
+        CoveringIndexRead {
+            rows,
+            index,
+            stamp: Stamp::default(),
+            deadlines: Deadlines::default(),
+            lru: None,
+        }
+    }
+
+    pub(crate) fn stamped(
+        self,
+        stamp: Stamp,
+        deadlines: Deadlines,
+        lru: Option<Arc<LruTracker>>,
+    ) -> Self {
+        CoveringIndexRead {
+            stamp,
+            deadlines,
+            lru,
+            ..self
+        }
+    }
+
+    pub fn is_live(&self) -> bool {
+        self.stamp.is_current()
+    }
+
+    pub fn get_payloads(&self, key: &KeyT) -> Vec<(RowId, PayloadT)> {
+        self.stamp.consistent(|| {
+            let index_guard = self.index.read().unwrap();
+            if !self.is_live() {
+                return Vec::new();
+            }
+
+            let now = Instant::now();
+            let mut payloads: Vec<(RowId, PayloadT)> = match index_guard.get_ref(key) {
+                Some(payloads) => payloads
+                    .iter()
+                    .filter(|(id, _payload)| !self.deadlines.is_expired(**id, now))
+                    .map(|(id, payload)| (*id, payload.clone()))
+                    .collect(),
+                None => Vec::new(),
+            };
+            payloads.sort_by_key(|(id, _payload)| *id);
+            if let Some(lru) = self.lru.as_ref() {
+                for (id, _payload) in payloads.iter() {
+                    lru.touch(*id);
+                }
+            }
+            payloads
+        })
+    }
+
+    pub fn payload(&self, key: &KeyT, id: RowId) -> Option<PayloadT> {
+        self.stamp.consistent(|| {
+            let index_guard = self.index.read().unwrap();
+            if !self.is_live() || self.deadlines.is_expired(id, Instant::now()) {
+                return None;
+            }
+            let payload = index_guard.payload(key, id).cloned();
+            if let (Some(lru), Some(_payload)) = (self.lru.as_ref(), payload.as_ref()) {
+                lru.touch(id);
+            }
+            payload
+        })
+    }
+
+    pub fn get(&self, key: &KeyT) -> Vec<Indexed<ValueT>> {
+        self.get_payloads(key)
+            .into_iter()
+            .filter_map(|(id, _payload)| {
+                self.rows
+                    .get(&id)
+                    .map(|value| Indexed::new(id, value.clone()))
+            })
+            .collect()
+    }
+
+    // Payloads of expired rows keep their key counted until the next sweep
+    pub fn key_count(&self) -> usize {
+        self.stamp.consistent(|| {
+            let index_guard = self.index.read().unwrap();
+            if !self.is_live() {
+                return 0;
+            }
+            index_guard.key_count()
+        })
+    }
+}
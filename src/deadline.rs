@@ -0,0 +1,41 @@
+use std::{sync::Arc, time::Instant};
+
+use dashmap::DashMap;
+
+use crate::id::RowId;
+
+#[derive(Clone, Default)]
+pub(crate) struct Deadlines {
+    deadlines: Arc<DashMap<RowId, Instant>>,
+}
+
+impl Deadlines {
+    pub(crate) fn set(&self, id: RowId, deadline: Instant) {
+        self.deadlines.insert(id, deadline);
+    }
+
+    pub(crate) fn clear(&self, id: RowId) {
+        self.deadlines.remove(&id);
+    }
+
+    // Rows past their TTL stay in the store until the next expire_stale
+    // sweep, so every read that returns rows or ids checks here first. Key
+    // counts and aggregates are kept incrementally and only drop expired rows
+    // once they are swept
+    pub(crate) fn is_expired(&self, id: RowId, now: Instant) -> bool {
+        self.deadlines
+            .get(&id)
+            .is_some_and(|deadline| *deadline.value() <= now)
+    }
+
+    pub(crate) fn expired(&self, now: Instant) -> Vec<RowId> {
+        let mut expired: Vec<RowId> = self
+            .deadlines
+            .iter()
+            .filter(|deadline| *deadline.value() <= now)
+            .map(|deadline| *deadline.key())
+            .collect();
+        expired.sort();
+        expired
+    }
+}
@@ -0,0 +1,82 @@
+use std::any::type_name;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct IndexDescription {
+    kind: &'static str,
+    key_type: Option<&'static str>,
+    options: Vec<(&'static str, String)>,
+}
+
+impl IndexDescription {
+    pub fn new(kind: &'static str) -> Self {
+        IndexDescription {
+            kind,
+            key_type: None,
+            options: Vec::new(),
+        }
+    }
+
+    pub fn keyed_by<KeyT>(mut self) -> Self {
+        self.key_type = Some(type_name::<KeyT>());
+        self
+    }
+
+    pub fn with_option(mut self, name: &'static str, value: impl ToString) -> Self {
+        self.options.push((name, value.to_string()));
+        self
+    }
+
+    pub fn kind(&self) -> &'static str {
+        self.kind
+    }
+
+    pub fn key_type(&self) -> Option<&'static str> {
+        self.key_type
+    }
+
+    pub fn options(&self) -> &[(&'static str, String)] {
+        &self.options
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct StoreDescription {
+    row_type: &'static str,
+    rows: usize,
+    indexes: Vec<IndexDescription>,
+    features: Vec<IndexDescription>,
+}
+
+impl StoreDescription {
+    pub fn new(row_type: &'static str, rows: usize, indexes: Vec<IndexDescription>) -> Self {
+        StoreDescription {
+            row_type,
+            rows,
+            indexes,
+            features: Vec::new(),
+        }
+    }
+
+    pub fn with_features(mut self, features: Vec<IndexDescription>) -> Self {
+        self.features = features;
+        self
+    }
+
+    pub fn row_type(&self) -> &'static str {
+        self.row_type
+    }
+
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    pub fn indexes(&self) -> &[IndexDescription] {
+        &self.indexes
+    }
+
+    pub fn features(&self) -> &[IndexDescription] {
+        &self.features
+    }
+}
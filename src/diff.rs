@@ -0,0 +1,100 @@
+use std::convert::Infallible;
+
+use crate::{error::UniqueViolation, hashsync::HashSync, id::RowId};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum RowDelta<RowT> {
+    Added(RowId, RowT),
+    Removed(RowId),
+    Changed(RowId, RowT),
+}
+
+impl<RowT> RowDelta<RowT> {
+    pub fn id(&self) -> RowId {
+        match self {
+            RowDelta::Added(id, _row) | RowDelta::Changed(id, _row) => *id,
+            RowDelta::Removed(id) => *id,
+        }
+    }
+}
+
+impl<'a, RowT: Clone + 'a> HashSync<'a, RowT> {
+    pub fn diff(&self, other: &HashSync<'_, RowT>) -> Vec<RowDelta<RowT>>
+    where
+        RowT: PartialEq,
+    {
+        // Deltas turn this store into `other`, so applying them here (or to
+        // any copy of this store) reproduces its rows
+        let mut deltas: Vec<RowDelta<RowT>> = self
+            .keys()
+            .into_iter()
+            .filter(|id| other.by_id(*id).is_none())
+            .map(RowDelta::Removed)
+            .collect();
+        for id in other.keys() {
+            let theirs = match other.by_id(id) {
+                Some(theirs) => theirs,
+                None => continue,
+            };
+            match self.by_id(id) {
+                None => deltas.push(RowDelta::Added(id, theirs)),
+                Some(mine) if mine != theirs => deltas.push(RowDelta::Changed(id, theirs)),
+                Some(_mine) => {}
+            }
+        }
+        deltas.sort_by_key(|delta| delta.id());
+        deltas
+    }
+
+    pub fn apply(&mut self, deltas: Vec<RowDelta<RowT>>) -> Result<(), UniqueViolation> {
+        self.transaction(|tx| {
+            for delta in deltas {
+                match delta {
+                    RowDelta::Added(id, row) | RowDelta::Changed(id, row) => tx.replace(id, row),
+                    RowDelta::Removed(id) => {
+                        tx.delete(id);
+                    }
+                }
+            }
+            Ok::<_, Infallible>(())
+        })?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn apply_diff_reproduces_rows() {
+        let mut local = HashSync::new();
+        let kept = local.insert((1, 'a'));
+        let changed = local.insert((1, 'b'));
+        let removed = local.insert((2, 'c'));
+        let mut remote = local.extract(|_row| true);
+        let by_number = local.index(|(number, _letter)| *number);
+
+        remote.replace(changed, (2, 'b'));
+        remote.delete(removed);
+        let added = remote.insert((3, 'd'));
+
+        let deltas = local.diff(&remote);
+        assert_eq!(
+            deltas,
+            vec![
+                RowDelta::Changed(changed, (2, 'b')),
+                RowDelta::Removed(removed),
+                RowDelta::Added(added, (3, 'd')),
+            ]
+        );
+
+        local.apply(deltas).unwrap();
+        assert_eq!(local.to_snapshot().rows(), remote.to_snapshot().rows());
+        assert_eq!(by_number.get_values(&1), vec![(1, 'a')]);
+        assert_eq!(by_number.get_values(&2), vec![(2, 'b')]);
+        assert_eq!(local.by_id(kept), Some((1, 'a')));
+        assert!(local.diff(&remote).is_empty());
+    }
+}
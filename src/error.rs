@@ -0,0 +1,381 @@
+use std::{convert::Infallible, error::Error, fmt, time::Duration};
+
+use crate::id::RowId;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MultipleMatches {
+    ids: Vec<RowId>,
+}
+
+impl MultipleMatches {
+    pub fn new(ids: Vec<RowId>) -> Self {
+        MultipleMatches { ids }
+    }
+
+    pub fn ids(&self) -> &[RowId] {
+        &self.ids
+    }
+}
+
+impl fmt::Display for MultipleMatches {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "expected at most one row, found {}", self.ids.len())
+    }
+}
+
+impl Error for MultipleMatches {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IndexInconsistency {
+    position: usize,
+    leaked: Vec<RowId>,
+    missing: Vec<RowId>,
+}
+
+impl IndexInconsistency {
+    pub fn new(position: usize, leaked: Vec<RowId>, missing: Vec<RowId>) -> Self {
+        IndexInconsistency {
+            position,
+            leaked,
+            missing,
+        }
+    }
+
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    pub fn leaked(&self) -> &[RowId] {
+        &self.leaked
+    }
+
+    pub fn missing(&self) -> &[RowId] {
+        &self.missing
+    }
+}
+
+impl fmt::Display for IndexInconsistency {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "index {} is inconsistent: {} leaked rows, {} missing rows",
+            self.position,
+            self.leaked.len(),
+            self.missing.len()
+        )
+    }
+}
+
+impl Error for IndexInconsistency {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MergeConflict {
+    ids: Vec<RowId>,
+}
+
+impl MergeConflict {
+    pub fn new(ids: Vec<RowId>) -> Self {
+        MergeConflict { ids }
+    }
+
+    pub fn ids(&self) -> &[RowId] {
+        &self.ids
+    }
+}
+
+impl fmt::Display for MergeConflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} rows changed in both stores", self.ids.len())
+    }
+}
+
+impl Error for MergeConflict {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MergeError {
+    Conflict(MergeConflict),
+    Unique(UniqueViolation),
+}
+
+impl fmt::Display for MergeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MergeError::Conflict(err) => write!(f, "merge aborted: {}", err),
+            MergeError::Unique(err) => write!(f, "merge not committed: {}", err),
+        }
+    }
+}
+
+impl Error for MergeError {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RowNotFound {
+    id: RowId,
+    max_known_id: Option<RowId>,
+}
+
+impl RowNotFound {
+    pub fn new(id: RowId, max_known_id: Option<RowId>) -> Self {
+        RowNotFound { id, max_known_id }
+    }
+
+    pub fn id(&self) -> RowId {
+        self.id
+    }
+
+    pub fn max_known_id(&self) -> Option<RowId> {
+        self.max_known_id
+    }
+}
+
+impl fmt::Display for RowNotFound {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.max_known_id {
+            Some(max_known_id) => write!(
+                f,
+                "row {:?} not found (max known id is {:?})",
+                self.id, max_known_id
+            ),
+            None => write!(f, "row {:?} not found (store has no rows)", self.id),
+        }
+    }
+}
+
+impl Error for RowNotFound {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DuplicateIndex {
+    key_type: &'static str,
+}
+
+impl DuplicateIndex {
+    pub fn new(key_type: &'static str) -> Self {
+        DuplicateIndex { key_type }
+    }
+
+    pub fn key_type(&self) -> &'static str {
+        self.key_type
+    }
+}
+
+impl fmt::Display for DuplicateIndex {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "an index keyed by {} is already registered",
+            self.key_type
+        )
+    }
+}
+
+impl Error for DuplicateIndex {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Throttled {
+    retry_after: Duration,
+}
+
+impl Throttled {
+    pub fn new(retry_after: Duration) -> Self {
+        Throttled { retry_after }
+    }
+
+    pub fn retry_after(&self) -> Duration {
+        self.retry_after
+    }
+}
+
+impl fmt::Display for Throttled {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "mutation throttled, retry after {:?}", self.retry_after)
+    }
+}
+
+impl Error for Throttled {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UniqueViolation {
+    ids: Vec<RowId>,
+}
+
+impl UniqueViolation {
+    pub fn new(ids: Vec<RowId>) -> Self {
+        UniqueViolation { ids }
+    }
+
+    pub fn ids(&self) -> &[RowId] {
+        &self.ids
+    }
+}
+
+impl fmt::Display for UniqueViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "row conflicts with {} existing rows on a unique index",
+            self.ids.len()
+        )
+    }
+}
+
+impl Error for UniqueViolation {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnknownIndexKind {
+    kind: String,
+}
+
+impl UnknownIndexKind {
+    pub fn new(kind: String) -> Self {
+        UnknownIndexKind { kind }
+    }
+
+    pub fn kind(&self) -> &str {
+        &self.kind
+    }
+}
+
+impl fmt::Display for UnknownIndexKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "no index factory registered for kind {:?}", self.kind)
+    }
+}
+
+impl Error for UnknownIndexKind {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StaleIndex {
+    generation: u64,
+    current: u64,
+}
+
+impl StaleIndex {
+    pub fn new(generation: u64, current: u64) -> Self {
+        StaleIndex {
+            generation,
+            current,
+        }
+    }
+
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    pub fn current(&self) -> u64 {
+        self.current
+    }
+}
+
+impl fmt::Display for StaleIndex {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "index handle from generation {} is no longer maintained (store is at generation {})",
+            self.generation, self.current
+        )
+    }
+}
+
+impl Error for StaleIndex {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FixtureError {
+    format: &'static str,
+    message: String,
+}
+
+impl FixtureError {
+    pub fn new(format: &'static str, message: String) -> Self {
+        FixtureError { format, message }
+    }
+
+    pub fn format(&self) -> &'static str {
+        self.format
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
+
+impl fmt::Display for FixtureError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid {} fixture: {}", self.format, self.message)
+    }
+}
+
+impl Error for FixtureError {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DuplicateIndexName {
+    name: String,
+}
+
+impl DuplicateIndexName {
+    pub fn new(name: String) -> Self {
+        DuplicateIndexName { name }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+impl fmt::Display for DuplicateIndexName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "an index named {:?} is already registered", self.name)
+    }
+}
+
+impl Error for DuplicateIndexName {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WalError {
+    message: String,
+}
+
+impl WalError {
+    pub fn new(message: String) -> Self {
+        WalError { message }
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
+
+impl fmt::Display for WalError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "write-ahead log failed: {}", self.message)
+    }
+}
+
+impl Error for WalError {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TransactionError<E> {
+    Aborted(E),
+    Unique(UniqueViolation),
+}
+
+impl<E: fmt::Display> fmt::Display for TransactionError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TransactionError::Aborted(err) => write!(f, "transaction aborted: {}", err),
+            TransactionError::Unique(err) => write!(f, "transaction not committed: {}", err),
+        }
+    }
+}
+
+impl<E: fmt::Debug + fmt::Display> Error for TransactionError<E> {}
+
+// Internal transactions never abort, so the only way they fail is a unique
+// index rejecting the commit
+impl From<TransactionError<Infallible>> for UniqueViolation {
+    fn from(err: TransactionError<Infallible>) -> Self {
+        match err {
+            TransactionError::Aborted(never) => match never {},
+            TransactionError::Unique(err) => err,
+        }
+    }
+}
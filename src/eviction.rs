@@ -0,0 +1,60 @@
+use std::{collections::BTreeMap, sync::Mutex};
+
+use fxhash::FxHashMap;
+
+use crate::id::RowId;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Eviction {
+    Lru(usize),
+}
+
+struct AccessOrder {
+    ticks: FxHashMap<RowId, u64>,
+    order: BTreeMap<u64, RowId>,
+    clock: u64,
+}
+
+pub(crate) struct LruTracker {
+    capacity: usize,
+    access: Mutex<AccessOrder>,
+}
+
+impl LruTracker {
+    pub(crate) fn new(capacity: usize) -> Self {
+        LruTracker {
+            capacity,
+            access: Mutex::new(AccessOrder {
+                ticks: FxHashMap::default(),
+                order: BTreeMap::new(),
+                clock: 0,
+            }),
+        }
+    }
+
+    pub(crate) fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub(crate) fn touch(&self, id: RowId) {
+        let mut access = self.access.lock().unwrap();
+        access.clock += 1;
+        let tick = access.clock;
+        if let Some(previous) = access.ticks.insert(id, tick) {
+            access.order.remove(&previous);
+        }
+        access.order.insert(tick, id);
+    }
+
+    pub(crate) fn remove(&self, id: RowId) {
+        let mut access = self.access.lock().unwrap();
+        if let Some(previous) = access.ticks.remove(&id) {
+            access.order.remove(&previous);
+        }
+    }
+
+    pub(crate) fn least_recent(&self) -> Option<RowId> {
+        let access = self.access.lock().unwrap();
+        access.order.values().next().copied()
+    }
+}
@@ -0,0 +1,198 @@
+use std::{
+    hash::Hash,
+    sync::{Arc, RwLock},
+    time::{Duration, Instant},
+};
+
+use dashmap::DashMap;
+use fxhash::FxHashMap;
+
+use crate::{
+    deadline::Deadlines,
+    describe::IndexDescription,
+    eviction::LruTracker,
+    id::{Indexed, RowId},
+    index::{hashed_entries, verify_entries, IndexFunction, IndexId, Indexable},
+    stamp::Stamp,
+};
+
+pub struct ExpiringIndex<KeyT, ValueT> {
+    index_function: IndexFunction<KeyT, ValueT>,
+    ttl: Duration,
+    index: FxHashMap<KeyT, FxHashMap<RowId, Instant>>,
+}
+
+impl<KeyT: PartialEq + Eq + Hash, ValueT> ExpiringIndex<KeyT, ValueT> {
+    pub fn new(index_function: IndexFunction<KeyT, ValueT>, ttl: Duration) -> Self {
+        ExpiringIndex {
+            index_function,
+            ttl,
+            index: FxHashMap::default(),
+        }
+    }
+
+    pub fn get(&self, key: &KeyT, now: Instant) -> Vec<RowId> {
+        match self.index.get(key) {
+            Some(entries) => entries
+                .iter()
+                .filter(|(_id, inserted)| !self.is_expired(**inserted, now))
+                .map(|(id, _inserted)| *id)
+                .collect(),
+            None => Vec::new(),
+        }
+    }
+
+    fn is_expired(&self, inserted: Instant, now: Instant) -> bool {
+        now.saturating_duration_since(inserted) >= self.ttl
+    }
+}
+
+impl<KeyT: PartialEq + Eq + Hash, ValueT> Indexable<ValueT> for ExpiringIndex<KeyT, ValueT> {
+    fn insert(&mut self, row: &Indexed<ValueT>) -> IndexId {
+        let now = Instant::now();
+        for key in (self.index_function)(row) {
+            self.index.entry(key).or_default().insert(row.id(), now);
+        }
+        IndexId::new(0)
+    }
+
+    fn delete(&mut self, row: &Indexed<ValueT>) {
+        for key in (self.index_function)(row) {
+            if let Some(entries) = self.index.get_mut(&key) {
+                entries.remove(&row.id());
+                if entries.is_empty() {
+                    self.index.remove(&key);
+                }
+            }
+        }
+    }
+
+    fn verify(&self, rows: &[Indexed<ValueT>]) -> (Vec<RowId>, Vec<RowId>) {
+        let expected = rows
+            .iter()
+            .map(|row| (row.id(), (self.index_function)(row)))
+            .collect();
+        let entries = self
+            .index
+            .iter()
+            .flat_map(|(key, entries)| entries.keys().map(move |id| (key, *id)));
+        // Swept entries are expected to be absent, so only leaks are reported
+        let (leaked, _missing) = verify_entries(expected, entries);
+        (leaked, Vec::new())
+    }
+
+    fn dump_entries(&self) -> Vec<(String, Vec<RowId>)> {
+        hashed_entries(
+            self.index
+                .iter()
+                .map(|(key, entries)| (key, entries.keys().copied().collect())),
+        )
+    }
+
+    fn describe(&self) -> IndexDescription {
+        IndexDescription::new("expiring")
+            .keyed_by::<KeyT>()
+            .with_option("ttl_ms", self.ttl.as_millis())
+    }
+
+    fn sweep(&mut self, now: Instant) {
+        let ttl = self.ttl;
+        self.index.retain(|_key, entries| {
+            entries.retain(|_id, inserted| now.saturating_duration_since(*inserted) < ttl);
+            !entries.is_empty()
+        });
+    }
+}
+
+pub struct ExpiringIndexRead<KeyT, ValueT> {
+    rows: Arc<DashMap<RowId, ValueT>>,
+    index: Arc<RwLock<ExpiringIndex<KeyT, ValueT>>>,
+    stamp: Stamp,
+    deadlines: Deadlines,
+    lru: Option<Arc<LruTracker>>,
+}
+
+impl<KeyT, ValueT> Clone for ExpiringIndexRead<KeyT, ValueT> {
+    fn clone(&self) -> Self {
+        ExpiringIndexRead {
+            rows: self.rows.clone(),
+            index: self.index.clone(),
+            stamp: self.stamp.clone(),
+            deadlines: self.deadlines.clone(),
+            lru: self.lru.clone(),
+        }
+    }
+}
+
+impl<KeyT: PartialEq + Eq + Hash, ValueT: Clone> ExpiringIndexRead<KeyT, ValueT> {
+    pub fn new(
+        rows: Arc<DashMap<RowId, ValueT>>,
+        index: Arc<RwLock<ExpiringIndex<KeyT, ValueT>>>,
+    ) -> Self {
+        ExpiringIndexRead {
+            rows,
+            index,
+            stamp: Stamp::default(),
+            deadlines: Deadlines::default(),
+            lru: None,
+        }
+    }
+
+    pub(crate) fn stamped(
+        self,
+        stamp: Stamp,
+        deadlines: Deadlines,
+        lru: Option<Arc<LruTracker>>,
+    ) -> Self {
+        ExpiringIndexRead {
+            stamp,
+            deadlines,
+            lru,
+            ..self
+        }
+    }
+
+    pub fn is_live(&self) -> bool {
+        self.stamp.is_current()
+    }
+
+    pub fn get(&self, key: &KeyT) -> Vec<Indexed<ValueT>> {
+        self.stamp.consistent(|| {
+            let index_guard = self.index.read().unwrap();
+            if !self.is_live() {
+                return Vec::new();
+            }
+
+            let now = Instant::now();
+            index_guard
+                .get(key, now)
+                .into_iter()
+                .filter(|id| !self.deadlines.is_expired(*id, now))
+                .filter_map(|id| {
+                    let row = self.rows.get(&id)?;
+                    if let Some(lru) = self.lru.as_ref() {
+                        lru.touch(id);
+                    }
+                    Some(Indexed::new(id, row.value().clone()))
+                })
+                .collect()
+        })
+    }
+
+    pub fn get_values(&self, key: &KeyT) -> Vec<ValueT> {
+        let indexed = self.get(key);
+        indexed.into_iter().map(|i| i.into_value()).collect()
+    }
+
+    // Entries past the index ttl, or whose rows are past theirs, are counted
+    // until expire_stale sweeps them
+    pub fn key_count(&self) -> usize {
+        self.stamp.consistent(|| {
+            let index_guard = self.index.read().unwrap();
+            if !self.is_live() {
+                return 0;
+            }
+            index_guard.index.len()
+        })
+    }
+}
@@ -0,0 +1,106 @@
+use serde::{de::DeserializeOwned, Deserialize};
+
+use crate::{error::FixtureError, hashsync::HashSync};
+
+#[derive(Deserialize)]
+struct Fixture<RowT> {
+    rows: Vec<RowT>,
+}
+
+pub fn load_json<'a, RowT>(json: &str) -> Result<HashSync<'a, RowT>, FixtureError>
+where
+    RowT: Clone + DeserializeOwned + 'a,
+{
+    let fixture: Fixture<RowT> =
+        serde_json::from_str(json).map_err(|err| FixtureError::new("json", err.to_string()))?;
+    Ok(load(fixture))
+}
+
+pub fn load_toml<'a, RowT>(source: &str) -> Result<HashSync<'a, RowT>, FixtureError>
+where
+    RowT: Clone + DeserializeOwned + 'a,
+{
+    let fixture: Fixture<RowT> =
+        toml::from_str(source).map_err(|err| FixtureError::new("toml", err.to_string()))?;
+    Ok(load(fixture))
+}
+
+fn load<'a, RowT: Clone + 'a>(fixture: Fixture<RowT>) -> HashSync<'a, RowT> {
+    let mut hs = HashSync::new();
+    // A new store has no unique indexes to reject a row
+    hs.insert_batch(fixture.rows).unwrap();
+    hs
+}
+
+#[macro_export]
+macro_rules! assert_index_contains {
+    ($index:expr, $key:expr, [$($row:expr),* $(,)?]) => {{
+        let key = $key;
+        let values = $index.get_values(&key);
+        $(
+            let row = $row;
+            assert!(
+                values.contains(&row),
+                "index key {:?} is missing row {:?}, found {:?}",
+                key,
+                row,
+                values
+            );
+        )*
+    }};
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, Clone, PartialEq, Deserialize)]
+    struct Item {
+        name: String,
+        color: String,
+    }
+
+    fn item(name: &str, color: &str) -> Item {
+        Item {
+            name: name.to_string(),
+            color: color.to_string(),
+        }
+    }
+
+    #[test]
+    fn load_json_fixture() {
+        let mut hs: HashSync<Item> = load_json(
+            r#"{"rows": [
+                {"name": "apple", "color": "red"},
+                {"name": "cherry", "color": "red"},
+                {"name": "lime", "color": "green"}
+            ]}"#,
+        )
+        .unwrap();
+        let by_color = hs.index(|item: &Item| item.color.clone());
+
+        assert_index_contains!(
+            by_color,
+            "red".to_string(),
+            [item("apple", "red"), item("cherry", "red")]
+        );
+        assert_index_contains!(by_color, "green".to_string(), [item("lime", "green")]);
+    }
+
+    #[test]
+    fn load_toml_fixture() {
+        let mut hs: HashSync<Item> = load_toml(
+            r#"
+            [[rows]]
+            name = "plum"
+            color = "purple"
+            "#,
+        )
+        .unwrap();
+        let by_name = hs.index(|item: &Item| item.name.clone());
+        assert_index_contains!(by_name, "plum".to_string(), [item("plum", "purple")]);
+
+        let err = load_toml::<Item>("rows = 1").err().unwrap();
+        assert_eq!(err.format(), "toml");
+    }
+}
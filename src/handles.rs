@@ -0,0 +1,26 @@
+use crate::{
+    aggregate::AggregateIndexRead, clock::TimestampRead, covering::CoveringIndexRead,
+    expiring::ExpiringIndexRead, index::IndexRead, query::IdSet, ranked::RankedIndexRead,
+    registry::DynIndexRead, snapshot::Snapshot, sorted::SortedIndexRead, store::StoreRead,
+    text::TextIndexRead, trace::Trace, unique::UniqueIndexRead, writer::HashSyncWriter,
+};
+
+pub const fn assert_handle<T: Send + Sync + Clone + 'static>() {}
+
+const _: () = {
+    assert_handle::<IndexRead<String, String>>();
+    assert_handle::<CoveringIndexRead<String, u64, String>>();
+    assert_handle::<RankedIndexRead<String, f64, String>>();
+    assert_handle::<SortedIndexRead<String, String>>();
+    assert_handle::<UniqueIndexRead<String, String>>();
+    assert_handle::<ExpiringIndexRead<String, String>>();
+    assert_handle::<HashSyncWriter<'static, String>>();
+    assert_handle::<TimestampRead<String>>();
+    assert_handle::<DynIndexRead<String>>();
+    assert_handle::<StoreRead<String>>();
+    assert_handle::<Snapshot<String>>();
+    assert_handle::<Trace<String>>();
+    assert_handle::<IdSet<String>>();
+    assert_handle::<AggregateIndexRead<String, i64, String>>();
+    assert_handle::<TextIndexRead<String>>();
+};
@@ -1,16 +1,83 @@
-use std::{cmp::max, hash::Hash, sync::Arc};
+use std::{
+    any::{type_name, TypeId},
+    cmp::max,
+    fmt::{Debug, Write},
+    hash::Hash,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        mpsc::Receiver,
+        Arc, RwLock,
+    },
+    time::{Duration, Instant, SystemTime},
+};
 
 use dashmap::DashMap;
+use fxhash::{FxHashMap, FxHashSet};
 
+#[cfg(feature = "persist")]
+use crate::wal::WriteAheadLog;
 use crate::{
-    id::{Indexed, RowId},
-    index::{Index, IndexRead, Indexable},
+    branch::Branch,
+    bulk::BulkLoader,
+    changes::{ChangeFeed, RowEvent},
+    checksum::Checksum,
+    clock::{HybridClock, TimestampIndex, TimestampRead},
+    covering::{CoveringIndex, CoveringIndexRead},
+    deadline::Deadlines,
+    describe::StoreDescription,
+    error::{
+        DuplicateIndex, DuplicateIndexName, IndexInconsistency, RowNotFound, TransactionError,
+        UniqueViolation,
+    },
+    eviction::{Eviction, LruTracker},
+    expiring::{ExpiringIndex, ExpiringIndexRead},
+    id::{IdMapping, Indexed, RowHandle, RowId},
+    index::{Index, IndexHandle, IndexRead, IndexWrite, Indexable},
+    merkle::{MerkleProof, MerkleTree},
+    ranked::{RankedIndex, RankedIndexRead},
+    snapshot::{HashSyncSnapshot, Snapshot},
+    sorted::{SortedIndex, SortedIndexRead},
+    stamp::Stamp,
+    stats::Stats,
+    store::{self, RowRef, StoreRead},
+    transaction::Transaction,
+    unique::{UniqueIndex, UniqueIndexRead, UniquePolicy},
 };
 
+type UniqueCheck<'a, RowT> = Box<
+    dyn Fn(&[Indexed<RowT>], &FxHashSet<RowId>) -> (UniquePolicy, Vec<RowId>) + Send + Sync + 'a,
+>;
+
+// The single-row shortcuts and Extend treat a rejected unique key like an
+// out of bounds index. Every check runs before the write touches the store,
+// so the panic leaves it unchanged; the _unique variants report it instead,
+// and every other write returns it
+fn expect_unique<T>(result: Result<T, UniqueViolation>) -> T {
+    result.unwrap_or_else(|err| panic!("{}", err))
+}
+
 pub struct HashSync<'a, RowT> {
     rows: Arc<DashMap<RowId, RowT>>,
     next_id: RowId,
-    indexes: Vec<Box<dyn Indexable<RowT> + 'a>>,
+    indexes: Vec<Box<dyn Indexable<RowT> + Send + Sync + 'a>>,
+    typed_keys: FxHashSet<TypeId>,
+    unique_checks: Vec<UniqueCheck<'a, RowT>>,
+    checksum: Option<Arc<RwLock<Checksum<RowT>>>>,
+    merkle: Option<Arc<RwLock<MerkleTree<RowT>>>>,
+    change_feed: Option<Arc<RwLock<ChangeFeed<RowT>>>>,
+    version: Arc<AtomicU64>,
+    last_mutation: Option<SystemTime>,
+    replacing: Arc<AtomicU64>,
+    generation: Arc<AtomicU64>,
+    deadlines: Deadlines,
+    lru: Option<Arc<LruTracker>>,
+    generations: FxHashMap<RowId, u64>,
+    index_names: Vec<Option<String>>,
+    index_ids: Vec<u64>,
+    next_index_id: u64,
+    internal: Vec<bool>,
+    #[cfg(feature = "persist")]
+    wal: Option<Arc<RwLock<WriteAheadLog<RowT>>>>,
 }
 
 impl<'a, RowT: Clone + 'a> Default for HashSync<'a, RowT> {
@@ -19,35 +86,178 @@ impl<'a, RowT: Clone + 'a> Default for HashSync<'a, RowT> {
     }
 }
 
+impl<'a, RowT: Clone + 'a> Extend<RowT> for HashSync<'a, RowT> {
+    fn extend<RowsT: IntoIterator<Item = RowT>>(&mut self, rows: RowsT) {
+        expect_unique(self.insert_batch(rows));
+    }
+}
+
+impl<'a, RowT: 'a> HashSync<'a, RowT> {
+    pub fn get_ref(&self, id: RowId) -> Option<RowRef<'_, RowT>> {
+        if self.deadlines.is_expired(id, Instant::now()) {
+            return None;
+        }
+        let row = self.rows.get(&id)?;
+        if let Some(lru) = self.lru.as_ref() {
+            lru.touch(id);
+        }
+        Some(RowRef::new(row))
+    }
+}
+
 impl<'a, RowT: Clone + 'a> HashSync<'a, RowT> {
     pub fn new() -> Self {
         HashSync {
             rows: Arc::new(DashMap::default()),
             next_id: RowId::new(0),
             indexes: Vec::new(),
+            typed_keys: FxHashSet::default(),
+            unique_checks: Vec::new(),
+            checksum: None,
+            merkle: None,
+            change_feed: None,
+            version: Arc::new(AtomicU64::new(0)),
+            last_mutation: None,
+            replacing: Arc::new(AtomicU64::new(0)),
+            generation: Arc::new(AtomicU64::new(0)),
+            deadlines: Deadlines::default(),
+            lru: None,
+            generations: FxHashMap::default(),
+            index_names: Vec::new(),
+            index_ids: Vec::new(),
+            next_index_id: 0,
+            internal: Vec::new(),
+            #[cfg(feature = "persist")]
+            wal: None,
+        }
+    }
+
+    pub fn current_version(&self) -> u64 {
+        self.version.load(Ordering::SeqCst)
+    }
+
+    pub fn with_capacity_policy(eviction: Eviction) -> Self {
+        let mut hs = Self::new();
+        match eviction {
+            Eviction::Lru(capacity) => hs.lru = Some(Arc::new(LruTracker::new(capacity))),
         }
+        hs
+    }
+
+    fn bump_version(&mut self) {
+        self.evict_over_capacity();
+        self.version.fetch_add(1, Ordering::SeqCst);
+        self.last_mutation = Some(SystemTime::now());
+        #[cfg(feature = "persist")]
+        self.checkpoint_if_due();
+    }
+
+    pub fn stats(&self) -> Stats {
+        Stats::new(
+            self.current_version(),
+            self.rows.len(),
+            self.user_indexes().count(),
+            self.last_mutation,
+        )
+    }
+
+    pub fn describe(&self) -> StoreDescription {
+        let (internal, indexes): (Vec<_>, Vec<_>) = self
+            .indexes
+            .iter()
+            .zip(self.internal.iter())
+            .partition(|(_index, internal)| **internal);
+        StoreDescription::new(
+            type_name::<RowT>(),
+            self.rows.len(),
+            indexes.iter().map(|(index, _)| index.describe()).collect(),
+        )
+        .with_features(internal.iter().map(|(index, _)| index.describe()).collect())
     }
 
     pub fn keys(&self) -> Vec<RowId> {
-        self.rows.iter().map(|r| *r.key()).collect()
+        let now = Instant::now();
+        self.rows
+            .iter()
+            .map(|r| *r.key())
+            .filter(|id| !self.deadlines.is_expired(*id, now))
+            .collect()
     }
 
     pub fn by_id(&self, id: RowId) -> Option<RowT> {
-        self.rows.get(&id).map(|r| r.value().clone())
+        if self.deadlines.is_expired(id, Instant::now()) {
+            return None;
+        }
+        let row = self.rows.get(&id).map(|r| r.value().clone());
+        if let (Some(lru), Some(_row)) = (self.lru.as_ref(), row.as_ref()) {
+            lru.touch(id);
+        }
+        row
+    }
+
+    fn evict_over_capacity(&mut self) {
+        let lru = match self.lru.clone() {
+            Some(lru) => lru,
+            None => return,
+        };
+        while self.rows.len() > lru.capacity() {
+            match lru.least_recent() {
+                Some(id) => {
+                    lru.remove(id);
+                    self.delete_at(id);
+                }
+                None => break,
+            }
+        }
     }
 
     pub fn by_id_indexed(&self, id: RowId) -> Option<Indexed<RowT>> {
         self.by_id(id).map(|row| Indexed::new(id, row))
     }
 
+    pub fn by_id_consistent(&self, id: RowId) -> Option<RowT> {
+        if self.deadlines.is_expired(id, Instant::now()) {
+            return None;
+        }
+        store::by_id_consistent(&self.rows, &self.replacing, id)
+    }
+
+    #[cfg(feature = "rayon")]
+    pub fn par_iter(&self) -> impl rayon::iter::ParallelIterator<Item = Indexed<RowT>> + '_
+    where
+        RowT: Send + Sync,
+    {
+        use rayon::prelude::*;
+
+        let now = Instant::now();
+        self.rows
+            .par_iter()
+            .filter(move |row| !self.deadlines.is_expired(*row.key(), now))
+            .map(|row| Indexed::new(*row.key(), row.value().clone()))
+    }
+
+    pub fn reader(&self) -> StoreRead<RowT> {
+        StoreRead::new(
+            self.rows.clone(),
+            self.replacing.clone(),
+            self.version.clone(),
+        )
+        .with_deadlines(self.deadlines.clone())
+    }
+
+    pub fn by_id_or_err(&self, id: RowId) -> Result<RowT, RowNotFound> {
+        self.by_id(id)
+            .ok_or_else(|| RowNotFound::new(id, self.next_id.prev()))
+    }
+
     pub fn insert(&mut self, row: RowT) -> RowId {
-        let id = self.next_id;
-        self.insert_at(id, row);
-        self.next_id = self.next_id.next();
-        id
+        expect_unique(self.insert_unique(row))
     }
 
     fn insert_at(&mut self, id: RowId, row: RowT) {
+        if let Some(lru) = self.lru.as_ref() {
+            lru.touch(id);
+        }
         let indexed = Indexed::new(id, row);
         for index in self.indexes.iter_mut() {
             index.insert(&indexed);
@@ -55,9 +265,178 @@ impl<'a, RowT: Clone + 'a> HashSync<'a, RowT> {
         self.rows.insert(id, indexed.into_value());
     }
 
+    pub fn insert_with_ttl(&mut self, row: RowT, ttl: Duration) -> RowId {
+        let id = self.insert(row);
+        self.deadlines.set(id, Instant::now() + ttl);
+        id
+    }
+
+    pub fn insert_batch<RowsT: IntoIterator<Item = RowT>>(
+        &mut self,
+        rows: RowsT,
+    ) -> Result<Vec<RowId>, UniqueViolation> {
+        let mut next_id = self.next_id;
+        let rows: Vec<Indexed<RowT>> = rows
+            .into_iter()
+            .map(|row| {
+                let indexed = Indexed::new(next_id, row);
+                next_id = next_id.next();
+                indexed
+            })
+            .collect();
+        if rows.is_empty() {
+            return Ok(Vec::new());
+        }
+        let rows = self.admit_batch(rows)?;
+        for index in self.indexes.iter_mut() {
+            index.insert_many(&rows);
+        }
+        Ok(self.store_batch(rows))
+    }
+
+    fn admit_batch(
+        &mut self,
+        mut rows: Vec<Indexed<RowT>>,
+    ) -> Result<Vec<Indexed<RowT>>, UniqueViolation> {
+        // Later rows win a key under UniquePolicy::Replace, so the batch's own
+        // earlier duplicates are dropped along with existing owners
+        let evicted = self.unique_evictions(&rows, &[])?;
+        if !evicted.is_empty() {
+            rows.retain(|row| evicted.binary_search(&row.id()).is_err());
+            self.replacing.fetch_add(1, Ordering::SeqCst);
+            for id in evicted {
+                self.delete_at(id);
+            }
+            self.replacing.fetch_add(1, Ordering::SeqCst);
+        }
+        Ok(rows)
+    }
+
+    fn store_batch(&mut self, rows: Vec<Indexed<RowT>>) -> Vec<RowId> {
+        let ids: Vec<RowId> = rows.iter().map(|row| row.id()).collect();
+        self.replacing.fetch_add(1, Ordering::SeqCst);
+        for row in rows {
+            if let Some(lru) = self.lru.as_ref() {
+                lru.touch(row.id());
+            }
+            self.rows.insert(row.id(), row.into_value());
+        }
+        self.replacing.fetch_add(1, Ordering::SeqCst);
+        if let Some(last) = ids.last() {
+            self.next_id = max(last.next(), self.next_id);
+            self.bump_version();
+        }
+        ids
+    }
+
+    pub fn bulk_load<R, LoadFn>(&mut self, load_fn: LoadFn) -> Result<R, UniqueViolation>
+    where
+        LoadFn: FnOnce(&mut BulkLoader<RowT>) -> R,
+    {
+        let mut loader = BulkLoader::new(self.next_id);
+        let result = load_fn(&mut loader);
+        let rows = self.admit_batch(loader.into_rows())?;
+        for index in self.indexes.iter_mut() {
+            index.insert_many(&rows);
+        }
+        self.store_batch(rows);
+        Ok(result)
+    }
+
+    #[cfg(feature = "rayon")]
+    pub fn par_bulk_load<R, LoadFn>(&mut self, load_fn: LoadFn) -> Result<R, UniqueViolation>
+    where
+        LoadFn: FnOnce(&mut BulkLoader<RowT>) -> R,
+        RowT: Send + Sync,
+    {
+        use rayon::prelude::*;
+
+        let mut loader = BulkLoader::new(self.next_id);
+        let result = load_fn(&mut loader);
+        let rows = self.admit_batch(loader.into_rows())?;
+        // Each index is independent, so they are rebuilt concurrently with
+        // every index taking the whole batch in one insert_many call
+        self.indexes
+            .par_iter_mut()
+            .for_each(|index| index.insert_many(&rows));
+        self.store_batch(rows);
+        Ok(result)
+    }
+
+    pub fn insert_unique(&mut self, row: RowT) -> Result<RowId, UniqueViolation> {
+        let id = self.next_id;
+        let row = Indexed::new(id, row);
+        for evicted in self.unique_evictions(std::slice::from_ref(&row), &[])? {
+            self.delete_at(evicted);
+        }
+        self.insert_at(id, row.into_value());
+        self.next_id = self.next_id.next();
+        self.bump_version();
+        Ok(id)
+    }
+
+    pub fn replace_unique(&mut self, id: RowId, row: RowT) -> Result<(), UniqueViolation> {
+        // Conflicts are found before the replace window opens, so a rejected
+        // write never leaves readers of by_id_consistent waiting on it
+        let row = Indexed::new(id, row);
+        let evicted = self.unique_evictions(std::slice::from_ref(&row), &[])?;
+        self.replacing.fetch_add(1, Ordering::SeqCst);
+        for evicted in evicted {
+            self.delete_at(evicted);
+        }
+        self.replace_at(id, row.into_value());
+        self.replacing.fetch_add(1, Ordering::SeqCst);
+        self.next_id = max(id.next(), self.next_id);
+        self.bump_version();
+        Ok(())
+    }
+
+    fn unique_evictions(
+        &self,
+        rows: &[Indexed<RowT>],
+        deleted: &[RowId],
+    ) -> Result<Vec<RowId>, UniqueViolation> {
+        if self.unique_checks.is_empty() {
+            return Ok(Vec::new());
+        }
+        // Rows the same write rewrites or deletes give up their old keys, so
+        // they never conflict with it
+        let changing: FxHashSet<RowId> = rows
+            .iter()
+            .map(|row| row.id())
+            .chain(deleted.iter().copied())
+            .collect();
+        let mut rejected = Vec::new();
+        let mut evicted = Vec::new();
+        for check in self.unique_checks.iter() {
+            let (policy, ids) = check(rows, &changing);
+            match policy {
+                UniquePolicy::Reject => rejected.extend(ids),
+                UniquePolicy::Replace => evicted.extend(ids),
+            }
+        }
+        if !rejected.is_empty() {
+            rejected.sort();
+            rejected.dedup();
+            return Err(UniqueViolation::new(rejected));
+        }
+        evicted.sort();
+        evicted.dedup();
+        Ok(evicted)
+    }
+
     pub fn delete(&mut self, id: RowId) -> Option<RowT> {
+        let row = self.delete_at(id);
+        if row.is_some() {
+            self.bump_version();
+        }
+        row
+    }
+
+    fn delete_at(&mut self, id: RowId) -> Option<RowT> {
         let row = self.rows.remove(&id);
         if let Some(row) = row {
+            self.forget(id);
             let indexed = Indexed::new(id, row.1);
             for index in self.indexes.iter_mut() {
                 index.delete(&indexed);
@@ -67,17 +446,436 @@ impl<'a, RowT: Clone + 'a> HashSync<'a, RowT> {
         None
     }
 
+    fn forget(&mut self, id: RowId) {
+        // Bumping the generation makes handles to the deleted row stop
+        // resolving even if a later replace reuses its id
+        *self.generations.entry(id).or_default() += 1;
+        self.deadlines.clear(id);
+        if let Some(lru) = self.lru.as_ref() {
+            lru.remove(id);
+        }
+    }
+
+    pub fn handle(&self, id: RowId) -> Option<RowHandle> {
+        if !self.rows.contains_key(&id) || self.deadlines.is_expired(id, Instant::now()) {
+            return None;
+        }
+        let generation = self.generations.get(&id).copied().unwrap_or(0);
+        Some(RowHandle::new(id, generation))
+    }
+
+    pub fn by_handle(&self, handle: RowHandle) -> Option<RowT> {
+        if !self.is_current(handle) {
+            return None;
+        }
+        self.by_id(handle.id())
+    }
+
+    pub fn is_current(&self, handle: RowHandle) -> bool {
+        let generation = self.generations.get(&handle.id()).copied().unwrap_or(0);
+        generation == handle.generation()
+            && self.rows.contains_key(&handle.id())
+            && !self.deadlines.is_expired(handle.id(), Instant::now())
+    }
+
+    pub fn delete_many(&mut self, ids: &[RowId]) -> Vec<RowT> {
+        self.replacing.fetch_add(1, Ordering::SeqCst);
+        let removed = self.delete_many_indexed(ids);
+        self.replacing.fetch_add(1, Ordering::SeqCst);
+        removed.into_iter().map(|row| row.into_value()).collect()
+    }
+
+    fn delete_many_indexed(&mut self, ids: &[RowId]) -> Vec<Indexed<RowT>> {
+        let removed: Vec<Indexed<RowT>> = ids
+            .iter()
+            .filter_map(|id| self.rows.remove(id))
+            .map(|(id, row)| Indexed::new(id, row))
+            .collect();
+        for row in removed.iter() {
+            self.forget(row.id());
+        }
+        if removed.is_empty() {
+            return Vec::new();
+        }
+        for index in self.indexes.iter_mut() {
+            index.delete_many(&removed);
+        }
+        self.bump_version();
+        removed
+    }
+
+    pub fn retain<RetainFn>(&mut self, retain_fn: RetainFn)
+    where
+        RetainFn: Fn(RowId, &RowT) -> bool,
+    {
+        self.delete_where_id(|id, row| !retain_fn(id, row));
+    }
+
+    pub fn delete_where<FilterFn>(&mut self, filter: FilterFn) -> Vec<Indexed<RowT>>
+    where
+        FilterFn: Fn(&RowT) -> bool,
+    {
+        self.delete_where_id(|_id, row| filter(row))
+    }
+
+    fn delete_where_id<FilterFn>(&mut self, filter: FilterFn) -> Vec<Indexed<RowT>>
+    where
+        FilterFn: Fn(RowId, &RowT) -> bool,
+    {
+        let mut ids: Vec<RowId> = self
+            .rows
+            .iter()
+            .filter(|row| filter(*row.key(), row.value()))
+            .map(|row| *row.key())
+            .collect();
+        ids.sort();
+        self.replacing.fetch_add(1, Ordering::SeqCst);
+        let removed = self.delete_many_indexed(&ids);
+        self.replacing.fetch_add(1, Ordering::SeqCst);
+        removed
+    }
+
+    pub fn delete_by<IndexKeyT: PartialEq + Eq + Hash>(
+        &mut self,
+        index: &IndexRead<IndexKeyT, RowT>,
+        key: &IndexKeyT,
+    ) -> Vec<RowT> {
+        self.delete_many(&index.get_ids(key))
+    }
+
+    pub fn delete_by_key<IndexKeyT: PartialEq + Eq + Hash>(
+        &mut self,
+        index: &IndexRead<IndexKeyT, RowT>,
+        key: &IndexKeyT,
+    ) -> Vec<Indexed<RowT>> {
+        // The ids are read first: index reads wait out an open seqlock
+        let row_ids = index.get_ids(key);
+        self.replacing.fetch_add(1, Ordering::SeqCst);
+        let removed = self.delete_many_indexed(&row_ids);
+        self.replacing.fetch_add(1, Ordering::SeqCst);
+        removed
+    }
+
+    pub fn move_key<IndexKeyT, FilterFn, MoveFn>(
+        &mut self,
+        index: &IndexRead<IndexKeyT, RowT>,
+        from: &IndexKeyT,
+        to: &IndexKeyT,
+        filter: FilterFn,
+        mut move_fn: MoveFn,
+    ) -> Result<Vec<RowId>, UniqueViolation>
+    where
+        IndexKeyT: PartialEq + Eq + Hash,
+        FilterFn: Fn(&RowT) -> bool,
+        MoveFn: FnMut(&mut RowT),
+    {
+        let moved: Vec<(RowId, RowT)> = index
+            .get_ids(from)
+            .into_iter()
+            .filter_map(|id| self.by_id(id).map(|row| (id, row)))
+            .filter(|(_id, row)| filter(row))
+            .map(|(id, mut row)| {
+                move_fn(&mut row);
+                (id, row)
+            })
+            .collect();
+        if moved.is_empty() {
+            return Ok(Vec::new());
+        }
+        let ids: Vec<RowId> = moved.iter().map(|(id, _row)| *id).collect();
+        self.replace_many_unique(moved)?;
+        debug_assert!(
+            ids.iter()
+                .all(|id| index.get_ids(to).binary_search(id).is_ok()),
+            "move_fn must re-key every moved row to the target key"
+        );
+        Ok(ids)
+    }
+
     pub fn replace(&mut self, id: RowId, row: RowT) {
-        // TODO: Lock write guard here to prevent race conditions with reads
-        self.delete(id);
-        self.insert_at(id, row);
-        self.next_id = max(id.next(), self.next_id);
+        expect_unique(self.replace_unique(id, row))
+    }
+
+    fn replace_at(&mut self, id: RowId, row: RowT) {
+        // Each index moves the row to its new keys under a single write lock
+        // and the stored row is overwritten rather than removed, so readers
+        // never observe the row missing mid-replace
+        let old = self
+            .rows
+            .get(&id)
+            .map(|old| Indexed::new(id, old.value().clone()));
+        match old {
+            Some(old) => {
+                // A row past its TTL is still indexed until the next sweep, so
+                // it is moved like a live row but the new value starts afresh
+                if self.deadlines.is_expired(id, Instant::now()) {
+                    self.deadlines.clear(id);
+                }
+                let new = Indexed::new(id, row);
+                for index in self.indexes.iter_mut() {
+                    index.update(&old, &new);
+                }
+                self.rows.insert(id, new.into_value());
+            }
+            None => self.insert_at(id, row),
+        }
+    }
+
+    pub fn update<UpdateFn>(&mut self, id: RowId, update_fn: UpdateFn) -> bool
+    where
+        UpdateFn: FnOnce(&mut RowT),
+    {
+        expect_unique(self.update_unique(id, update_fn))
+    }
+
+    pub fn update_unique<UpdateFn>(
+        &mut self,
+        id: RowId,
+        update_fn: UpdateFn,
+    ) -> Result<bool, UniqueViolation>
+    where
+        UpdateFn: FnOnce(&mut RowT),
+    {
+        let mut row = match self.by_id(id) {
+            Some(row) => row,
+            None => return Ok(false),
+        };
+        update_fn(&mut row);
+        self.replace_unique(id, row)?;
+        Ok(true)
+    }
+
+    pub fn replace_many(&mut self, rows: Vec<(RowId, RowT)>) {
+        expect_unique(self.replace_many_unique(rows))
+    }
+
+    pub fn replace_many_unique(&mut self, rows: Vec<(RowId, RowT)>) -> Result<(), UniqueViolation> {
+        let rows = rows
+            .into_iter()
+            .map(|(id, row)| Indexed::new(id, row))
+            .collect();
+        self.write_staged(rows, Vec::new())
+    }
+
+    fn write_staged(
+        &mut self,
+        mut rows: Vec<Indexed<RowT>>,
+        mut deleted: Vec<RowId>,
+    ) -> Result<(), UniqueViolation> {
+        // Unique keys are checked against the state after the whole write, so
+        // rows can trade keys with each other
+        let evicted = self.unique_evictions(&rows, &deleted)?;
+        if !evicted.is_empty() {
+            rows.retain(|row| evicted.binary_search(&row.id()).is_err());
+            deleted.extend(evicted);
+        }
+
+        deleted.sort();
+        deleted.dedup();
+        let deleted: Vec<Indexed<RowT>> = deleted
+            .into_iter()
+            .filter_map(|id| {
+                self.rows
+                    .get(&id)
+                    .map(|row| Indexed::new(id, row.value().clone()))
+            })
+            .collect();
+        let (updated, inserted): (Vec<_>, Vec<_>) = rows
+            .into_iter()
+            .map(|row| {
+                let old = self
+                    .rows
+                    .get(&row.id())
+                    .map(|old| Indexed::new(row.id(), old.value().clone()));
+                (old, row)
+            })
+            .partition(|(old, _row)| old.is_some());
+        let updated: Vec<(Indexed<RowT>, Indexed<RowT>)> = updated
+            .into_iter()
+            .filter_map(|(old, row)| old.map(|old| (old, row)))
+            .collect();
+        let inserted: Vec<Indexed<RowT>> = inserted.into_iter().map(|(_old, row)| row).collect();
+
+        // Each index takes the whole write under one lock, and the window
+        // stays open until the rows are in, so index reads retry past it
+        self.replacing.fetch_add(1, Ordering::SeqCst);
+        for index in self.indexes.iter_mut() {
+            index.delete_many(&deleted);
+            index.update_many(&updated);
+            index.insert_many(&inserted);
+        }
+        let now = Instant::now();
+        for row in deleted {
+            self.rows.remove(&row.id());
+            self.forget(row.id());
+        }
+        for (_old, row) in updated {
+            // Matches replace_at: an expired row's new value starts afresh
+            if self.deadlines.is_expired(row.id(), now) {
+                self.deadlines.clear(row.id());
+            }
+            self.rows.insert(row.id(), row.into_value());
+        }
+        for row in inserted {
+            if let Some(lru) = self.lru.as_ref() {
+                lru.touch(row.id());
+            }
+            self.next_id = max(row.id().next(), self.next_id);
+            self.rows.insert(row.id(), row.into_value());
+        }
+        self.replacing.fetch_add(1, Ordering::SeqCst);
+        self.bump_version();
+        Ok(())
+    }
+
+    pub fn transaction<T, E, TransactionFn>(
+        &mut self,
+        transaction_fn: TransactionFn,
+    ) -> Result<T, TransactionError<E>>
+    where
+        TransactionFn: FnOnce(&mut Transaction<RowT>) -> Result<T, E>,
+    {
+        let mut tx = Transaction::new(self.reader(), self.next_id);
+        let result = transaction_fn(&mut tx).map_err(TransactionError::Aborted)?;
+        let (next_id, staged) = tx.into_staged();
+        if staged.is_empty() {
+            return Ok(result);
+        }
+
+        let mut rows = Vec::new();
+        let mut deleted = Vec::new();
+        for (id, row) in staged {
+            match row {
+                Some(row) => rows.push(Indexed::new(id, row)),
+                None => deleted.push(id),
+            }
+        }
+        // A rejected commit writes nothing, so next_id only moves once the
+        // staged rows are in
+        self.write_staged(rows, deleted)
+            .map_err(TransactionError::Unique)?;
+        self.next_id = max(next_id, self.next_id);
+        Ok(result)
+    }
+
+    pub fn swap(&mut self, id_a: RowId, id_b: RowId) -> Result<bool, UniqueViolation> {
+        match (self.by_id(id_a), self.by_id(id_b)) {
+            (Some(row_a), Some(row_b)) => {
+                self.replace_many_unique(vec![(id_a, row_b), (id_b, row_a)])?;
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    pub fn absorb(&mut self, other: HashSync<'_, RowT>) -> Result<IdMapping, UniqueViolation> {
+        let mut rows: Vec<(RowId, RowT)> = other
+            .rows
+            .iter()
+            .map(|r| (*r.key(), r.value().clone()))
+            .collect();
+        rows.sort_by_key(|(id, _row)| *id);
+        let (conflicting, preserved): (Vec<_>, Vec<_>) = rows
+            .into_iter()
+            .partition(|(id, _row)| self.rows.contains_key(id));
+
+        // Rows are admitted one at a time, so a rejected row stops the absorb
+        // with the rows before it already in
+        let mut mapping = IdMapping::new();
+        for (id, row) in preserved {
+            let row = Indexed::new(id, row);
+            for evicted in self.unique_evictions(std::slice::from_ref(&row), &[])? {
+                self.delete_at(evicted);
+            }
+            self.insert_at(id, row.into_value());
+            self.next_id = max(id.next(), self.next_id);
+            self.bump_version();
+            mapping.insert(id, id);
+        }
+        for (id, row) in conflicting {
+            mapping.insert(id, self.insert_unique(row)?);
+        }
+        Ok(mapping)
+    }
+
+    pub fn extract<FilterFn>(&self, filter: FilterFn) -> Self
+    where
+        FilterFn: Fn(&RowT) -> bool,
+    {
+        let rows: DashMap<RowId, RowT> = self
+            .rows
+            .iter()
+            .filter(|r| filter(r.value()))
+            .map(|r| (*r.key(), r.value().clone()))
+            .collect();
+        HashSync {
+            rows: Arc::new(rows),
+            next_id: self.next_id,
+            indexes: Vec::new(),
+            typed_keys: FxHashSet::default(),
+            unique_checks: Vec::new(),
+            checksum: None,
+            merkle: None,
+            change_feed: None,
+            version: Arc::new(AtomicU64::new(0)),
+            last_mutation: None,
+            replacing: Arc::new(AtomicU64::new(0)),
+            generation: Arc::new(AtomicU64::new(0)),
+            deadlines: Deadlines::default(),
+            lru: None,
+            generations: FxHashMap::default(),
+            index_names: Vec::new(),
+            index_ids: Vec::new(),
+            next_index_id: 0,
+            internal: Vec::new(),
+            #[cfg(feature = "persist")]
+            wal: None,
+        }
+    }
+
+    pub fn branch(&self) -> Branch<RowT> {
+        Branch::new(self.reader())
+    }
+
+    pub fn merge_rows<MergeFn>(
+        &mut self,
+        ids: &[RowId],
+        merge_fn: MergeFn,
+    ) -> Result<Option<RowId>, UniqueViolation>
+    where
+        MergeFn: FnOnce(Vec<RowT>) -> RowT,
+    {
+        let mut ids: Vec<RowId> = ids
+            .iter()
+            .copied()
+            .filter(|id| self.rows.contains_key(id))
+            .collect();
+        ids.sort();
+        ids.dedup();
+        let survivor = match ids.first() {
+            Some(survivor) => *survivor,
+            None => return Ok(None),
+        };
+
+        let rows = ids.iter().filter_map(|id| self.by_id(*id)).collect();
+        let merged = Indexed::new(survivor, merge_fn(rows));
+        let evicted = self.unique_evictions(std::slice::from_ref(&merged), &ids)?;
+        // The survivor is rewritten in place so its generation, and with it
+        // any RowHandle to it, outlives the merge
+        self.replacing.fetch_add(1, Ordering::SeqCst);
+        for id in ids[1..].iter().chain(evicted.iter()) {
+            self.delete_at(*id);
+        }
+        self.replace_at(survivor, merged.into_value());
+        self.replacing.fetch_add(1, Ordering::SeqCst);
+        self.bump_version();
+        Ok(Some(survivor))
     }
 
     pub fn index<IndexKeyT, IndexFn>(&mut self, index_fn: IndexFn) -> IndexRead<IndexKeyT, RowT>
     where
-        IndexFn: Fn(&RowT) -> IndexKeyT + 'static,
-        IndexKeyT: PartialEq + Eq + Hash + 'a,
+        IndexFn: Fn(&RowT) -> IndexKeyT + Send + Sync + 'static,
+        IndexKeyT: PartialEq + Eq + Hash + Send + Sync + 'a,
     {
         let index_many_fn = move |row: &RowT| vec![index_fn(row)];
         self.index_many(index_many_fn)
@@ -88,8 +886,8 @@ impl<'a, RowT: Clone + 'a> HashSync<'a, RowT> {
         index_fn: IndexFn,
     ) -> IndexRead<IndexKeyT, RowT>
     where
-        IndexFn: Fn(&RowT) -> Vec<IndexKeyT> + 'static,
-        IndexKeyT: PartialEq + Eq + Hash + 'a,
+        IndexFn: Fn(&RowT) -> Vec<IndexKeyT> + Send + Sync + 'static,
+        IndexKeyT: PartialEq + Eq + Hash + Send + Sync + 'a,
     {
         let index_id_many_fn = move |indexed: &Indexed<RowT>| index_fn(indexed.value());
         self.index_id_many(index_id_many_fn)
@@ -97,8 +895,8 @@ impl<'a, RowT: Clone + 'a> HashSync<'a, RowT> {
 
     pub fn index_id<IndexKeyT, IndexFn>(&mut self, index_fn: IndexFn) -> IndexRead<IndexKeyT, RowT>
     where
-        IndexFn: Fn(&Indexed<RowT>) -> IndexKeyT + 'static,
-        IndexKeyT: PartialEq + Eq + Hash + 'a,
+        IndexFn: Fn(&Indexed<RowT>) -> IndexKeyT + Send + Sync + 'static,
+        IndexKeyT: PartialEq + Eq + Hash + Send + Sync + 'a,
     {
         let index_many_fn = move |indexed: &Indexed<RowT>| vec![index_fn(indexed)];
         self.index_id_many(index_many_fn)
@@ -109,283 +907,2011 @@ impl<'a, RowT: Clone + 'a> HashSync<'a, RowT> {
         index_fn: IndexFn,
     ) -> IndexRead<IndexKeyT, RowT>
     where
-        IndexFn: Fn(&Indexed<RowT>) -> Vec<IndexKeyT> + 'static,
-        IndexKeyT: PartialEq + Eq + Hash + 'a,
+        IndexFn: Fn(&Indexed<RowT>) -> Vec<IndexKeyT> + Send + Sync + 'static,
+        IndexKeyT: PartialEq + Eq + Hash + Send + Sync + 'a,
     {
-        let mut index = Index::new(Box::new(index_fn));
-        for row in self.rows.iter() {
-            let indexed = Indexed::new(*row.key(), row.value().clone());
-            index.insert(&indexed);
+        let index = self.register_index(Index::new(Box::new(index_fn)));
+        IndexRead::new(self.rows.clone(), index, self.version.clone()).stamped(
+            self.stamp(),
+            self.deadlines.clone(),
+            self.lru.clone(),
+        )
+    }
+
+    pub fn typed_index<IndexKeyT, IndexFn>(
+        &mut self,
+        index_fn: IndexFn,
+    ) -> Result<IndexRead<IndexKeyT, RowT>, DuplicateIndex>
+    where
+        IndexFn: Fn(&RowT) -> IndexKeyT + Send + Sync + 'static,
+        IndexKeyT: PartialEq + Eq + Hash + Send + Sync + 'static,
+    {
+        if !self.typed_keys.insert(TypeId::of::<IndexKeyT>()) {
+            return Err(DuplicateIndex::new(type_name::<IndexKeyT>()));
+        }
+        Ok(self.index(index_fn))
+    }
+
+    pub fn index_named<IndexKeyT, IndexFn>(
+        &mut self,
+        name: &str,
+        index_fn: IndexFn,
+    ) -> Result<IndexHandle<IndexKeyT, RowT>, DuplicateIndexName>
+    where
+        IndexFn: Fn(&RowT) -> IndexKeyT + Send + Sync + 'static,
+        IndexKeyT: PartialEq + Eq + Hash + Send + Sync + 'a,
+    {
+        if self.index_names.iter().flatten().any(|other| other == name) {
+            return Err(DuplicateIndexName::new(name.to_string()));
+        }
+        let read = self.index(index_fn);
+        if let Some(last) = self.index_names.last_mut() {
+            *last = Some(name.to_string());
+        }
+        let id = *self.index_ids.last().unwrap();
+        Ok(IndexHandle::new(id, name.to_string(), read))
+    }
+
+    // Handles are matched by the id their index was registered under, so a
+    // handle from another store or from before drop_indexes drops nothing
+    pub fn drop_index<IndexKeyT>(&mut self, handle: IndexHandle<IndexKeyT, RowT>) -> bool
+    where
+        IndexKeyT: PartialEq + Eq + Hash,
+    {
+        if !handle.reader().shares_rows(&self.rows) {
+            return false;
         }
-        let (index_read, index_write) = index.into_read_write(self.rows.clone());
-        self.indexes.push(Box::new(index_write));
-        index_read
+        let position = match self.index_ids.iter().position(|id| *id == handle.id()) {
+            Some(position) => position,
+            None => return false,
+        };
+        self.indexes.remove(position);
+        self.index_names.remove(position);
+        self.index_ids.remove(position);
+        self.internal.remove(position);
+        handle.into_reader().mark_dropped();
+        true
+    }
+
+    pub fn index_tracked<IndexKeyT, IndexFn>(
+        &mut self,
+        index_fn: IndexFn,
+    ) -> IndexRead<IndexKeyT, RowT>
+    where
+        IndexFn: Fn(&RowT) -> IndexKeyT + Send + Sync + 'static,
+        IndexKeyT: PartialEq + Eq + Hash + Clone + Send + Sync + 'a,
+    {
+        let index_many_fn = move |row: &RowT| vec![index_fn(row)];
+        self.index_many_tracked(index_many_fn)
+    }
+
+    pub fn index_many_tracked<IndexKeyT, IndexFn>(
+        &mut self,
+        index_fn: IndexFn,
+    ) -> IndexRead<IndexKeyT, RowT>
+    where
+        IndexFn: Fn(&RowT) -> Vec<IndexKeyT> + Send + Sync + 'static,
+        IndexKeyT: PartialEq + Eq + Hash + Clone + Send + Sync + 'a,
+    {
+        let index_id_many_fn = move |indexed: &Indexed<RowT>| index_fn(indexed.value());
+        self.index_id_many_tracked(index_id_many_fn)
+    }
+
+    pub fn index_id_many_tracked<IndexKeyT, IndexFn>(
+        &mut self,
+        index_fn: IndexFn,
+    ) -> IndexRead<IndexKeyT, RowT>
+    where
+        IndexFn: Fn(&Indexed<RowT>) -> Vec<IndexKeyT> + Send + Sync + 'static,
+        IndexKeyT: PartialEq + Eq + Hash + Clone + Send + Sync + 'a,
+    {
+        let index = self.register_index(Index::new_tracked(Box::new(index_fn)));
+        IndexRead::new(self.rows.clone(), index, self.version.clone()).stamped(
+            self.stamp(),
+            self.deadlines.clone(),
+            self.lru.clone(),
+        )
+    }
+
+    pub fn unique_index<IndexKeyT, IndexFn>(
+        &mut self,
+        index_fn: IndexFn,
+    ) -> Result<UniqueIndexRead<IndexKeyT, RowT>, UniqueViolation>
+    where
+        IndexFn: Fn(&RowT) -> IndexKeyT + Send + Sync + 'static,
+        IndexKeyT: PartialEq + Eq + Hash + Send + Sync + 'a,
+    {
+        self.unique_index_with_policy(index_fn, UniquePolicy::Reject)
+    }
+
+    pub fn unique_index_with_policy<IndexKeyT, IndexFn>(
+        &mut self,
+        index_fn: IndexFn,
+        policy: UniquePolicy,
+    ) -> Result<UniqueIndexRead<IndexKeyT, RowT>, UniqueViolation>
+    where
+        IndexFn: Fn(&RowT) -> IndexKeyT + Send + Sync + 'static,
+        IndexKeyT: PartialEq + Eq + Hash + Send + Sync + 'a,
+    {
+        let index_id_fn = move |indexed: &Indexed<RowT>| index_fn(indexed.value());
+        let index = UniqueIndex::new(Box::new(index_id_fn), policy);
+
+        // Rows already sharing a key get the policy a write would: the index
+        // is refused, or the later row keeps the key as in insert_batch
+        let mut rows: Vec<Indexed<RowT>> = self
+            .rows
+            .iter()
+            .map(|row| Indexed::new(*row.key(), row.value().clone()))
+            .collect();
+        rows.sort_by_key(|row| row.id());
+        let mut duplicates = index.conflicts(&rows, &FxHashSet::default());
+        if !duplicates.is_empty() {
+            duplicates.sort();
+            duplicates.dedup();
+            match policy {
+                UniquePolicy::Reject => return Err(UniqueViolation::new(duplicates)),
+                UniquePolicy::Replace => {
+                    self.replacing.fetch_add(1, Ordering::SeqCst);
+                    for id in duplicates {
+                        self.delete_at(id);
+                    }
+                    self.replacing.fetch_add(1, Ordering::SeqCst);
+                    self.bump_version();
+                }
+            }
+        }
+
+        let index = self.register_index(index);
+        let check_index = index.clone();
+        self.unique_checks.push(Box::new(move |rows, changing| {
+            let index_guard = check_index.read().unwrap();
+            (index_guard.policy(), index_guard.conflicts(rows, changing))
+        }));
+        Ok(UniqueIndexRead::new(self.rows.clone(), index).stamped(
+            self.stamp(),
+            self.deadlines.clone(),
+            self.lru.clone(),
+        ))
+    }
+
+    pub fn sorted_index<IndexKeyT, IndexFn>(
+        &mut self,
+        index_fn: IndexFn,
+    ) -> SortedIndexRead<IndexKeyT, RowT>
+    where
+        IndexFn: Fn(&RowT) -> IndexKeyT + Send + Sync + 'static,
+        IndexKeyT: Ord + Send + Sync + 'a,
+    {
+        let index_id_fn = move |indexed: &Indexed<RowT>| vec![index_fn(indexed.value())];
+        let index = self.register_index(SortedIndex::new(Box::new(index_id_fn)));
+        SortedIndexRead::new(self.rows.clone(), index).stamped(
+            self.stamp(),
+            self.deadlines.clone(),
+            self.lru.clone(),
+        )
+    }
+
+    pub fn prefix_index<IndexFn>(&mut self, index_fn: IndexFn) -> SortedIndexRead<String, RowT>
+    where
+        IndexFn: Fn(&RowT) -> String + Send + Sync + 'static,
+    {
+        self.sorted_index(index_fn)
+    }
+
+    pub fn ranked_index<IndexKeyT, ScoreT, IndexFn>(
+        &mut self,
+        index_fn: IndexFn,
+    ) -> RankedIndexRead<IndexKeyT, ScoreT, RowT>
+    where
+        IndexFn: Fn(&RowT) -> Vec<(IndexKeyT, ScoreT)> + Send + Sync + 'static,
+        IndexKeyT: PartialEq + Eq + Hash + Send + Sync + 'a,
+        ScoreT: PartialOrd + Copy + Send + Sync + 'a,
+    {
+        let index_id_fn = move |indexed: &Indexed<RowT>| index_fn(indexed.value());
+        let index = self.register_index(RankedIndex::new(Box::new(index_id_fn)));
+        RankedIndexRead::new(self.rows.clone(), index).stamped(
+            self.stamp(),
+            self.deadlines.clone(),
+            self.lru.clone(),
+        )
+    }
+
+    pub fn covering_index<IndexKeyT, PayloadT, IndexFn>(
+        &mut self,
+        index_fn: IndexFn,
+    ) -> CoveringIndexRead<IndexKeyT, PayloadT, RowT>
+    where
+        IndexFn: Fn(&RowT) -> Vec<(IndexKeyT, PayloadT)> + Send + Sync + 'static,
+        IndexKeyT: PartialEq + Eq + Hash + Send + Sync + 'a,
+        PayloadT: Clone + Send + Sync + 'a,
+    {
+        let index_id_fn = move |indexed: &Indexed<RowT>| index_fn(indexed.value());
+        let index = self.register_index(CoveringIndex::new(Box::new(index_id_fn)));
+        CoveringIndexRead::new(self.rows.clone(), index).stamped(
+            self.stamp(),
+            self.deadlines.clone(),
+            self.lru.clone(),
+        )
+    }
+
+    pub fn expiring_index<IndexKeyT, IndexFn>(
+        &mut self,
+        ttl: Duration,
+        index_fn: IndexFn,
+    ) -> ExpiringIndexRead<IndexKeyT, RowT>
+    where
+        IndexFn: Fn(&RowT) -> Vec<IndexKeyT> + Send + Sync + 'static,
+        IndexKeyT: PartialEq + Eq + Hash + Send + Sync + 'a,
+    {
+        let index_id_fn = move |indexed: &Indexed<RowT>| index_fn(indexed.value());
+        let index = self.register_index(ExpiringIndex::new(Box::new(index_id_fn), ttl));
+        ExpiringIndexRead::new(self.rows.clone(), index).stamped(
+            self.stamp(),
+            self.deadlines.clone(),
+            self.lru.clone(),
+        )
+    }
+
+    pub(crate) fn register_index<IndexT>(&mut self, index: IndexT) -> Arc<RwLock<IndexT>>
+    where
+        IndexT: Indexable<RowT> + Send + Sync + 'a,
+    {
+        let start = self.indexes.len();
+        let index = self.attach_index(index);
+        self.populate_indexes_from(start);
+        index
+    }
+
+    pub(crate) fn attach_index<IndexT>(&mut self, index: IndexT) -> Arc<RwLock<IndexT>>
+    where
+        IndexT: Indexable<RowT> + Send + Sync + 'a,
+    {
+        let index = Arc::new(RwLock::new(index));
+        self.indexes.push(Box::new(IndexWrite::new(index.clone())));
+        self.index_names.push(None);
+        self.index_ids.push(self.next_index_id);
+        self.next_index_id += 1;
+        self.internal.push(false);
+        index
+    }
+
+    // Checksums, logs and feeds ride on the index machinery to see every
+    // write, but they aren't indexes the caller registered, so stats and
+    // describe report them apart
+    pub(crate) fn register_internal<IndexT>(&mut self, index: IndexT) -> Arc<RwLock<IndexT>>
+    where
+        IndexT: Indexable<RowT> + Send + Sync + 'a,
+    {
+        let index = self.register_index(index);
+        if let Some(last) = self.internal.last_mut() {
+            *last = true;
+        }
+        index
+    }
+
+    fn user_indexes(&self) -> impl Iterator<Item = &(dyn Indexable<RowT> + Send + Sync + 'a)> {
+        self.indexes
+            .iter()
+            .zip(self.internal.iter())
+            .filter(|(_index, internal)| !**internal)
+            .map(|(index, _internal)| index.as_ref())
+    }
+
+    #[cfg(feature = "persist")]
+    pub(crate) fn attach_wal(&mut self, wal: WriteAheadLog<RowT>) {
+        self.wal = Some(self.attach_index(wal));
+        if let Some(last) = self.internal.last_mut() {
+            *last = true;
+        }
+    }
+
+    #[cfg(feature = "persist")]
+    pub(crate) fn wal(&self) -> Option<&Arc<RwLock<WriteAheadLog<RowT>>>> {
+        self.wal.as_ref()
+    }
+
+    pub(crate) fn stamp(&self) -> Stamp {
+        Stamp::new(self.generation.clone(), self.replacing.clone())
+    }
+
+    pub(crate) fn index_count(&self) -> usize {
+        self.indexes.len()
+    }
+
+    pub(crate) fn populate_indexes_from(&mut self, start: usize) {
+        // Indexes attached since `start` are still empty, so fill them all
+        // in a single pass over the rows
+        if start >= self.indexes.len() {
+            return;
+        }
+        for row in self.rows.iter() {
+            let indexed = Indexed::new(*row.key(), row.value().clone());
+            for index in self.indexes[start..].iter_mut() {
+                index.insert(&indexed);
+            }
+        }
+    }
+
+    pub(crate) fn index_many_unpopulated<IndexKeyT, IndexFn>(
+        &mut self,
+        index_fn: IndexFn,
+    ) -> IndexRead<IndexKeyT, RowT>
+    where
+        IndexFn: Fn(&RowT) -> Vec<IndexKeyT> + Send + Sync + 'static,
+        IndexKeyT: PartialEq + Eq + Hash + Send + Sync + 'a,
+    {
+        let index_id_many_fn = move |indexed: &Indexed<RowT>| index_fn(indexed.value());
+        let index = self.attach_index(Index::new(Box::new(index_id_many_fn)));
+        IndexRead::new(self.rows.clone(), index, self.version.clone()).stamped(
+            self.stamp(),
+            self.deadlines.clone(),
+            self.lru.clone(),
+        )
+    }
+
+    pub fn verify_indexes(&self) -> Result<(), IndexInconsistency> {
+        let rows: Vec<Indexed<RowT>> = self
+            .rows
+            .iter()
+            .map(|row| Indexed::new(*row.key(), row.value().clone()))
+            .collect();
+        for (position, index) in self.indexes.iter().enumerate() {
+            let (leaked, missing) = index.verify(&rows);
+            if !leaked.is_empty() || !missing.is_empty() {
+                return Err(IndexInconsistency::new(position, leaked, missing));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn expire_stale(&mut self) {
+        let now = Instant::now();
+        let expired = self.deadlines.expired(now);
+        self.delete_many(&expired);
+        for index in self.indexes.iter_mut() {
+            index.sweep(now);
+        }
+    }
+
+    pub fn enable_timestamps(&mut self) -> TimestampRead<RowT> {
+        let index = self.register_internal(TimestampIndex::new(HybridClock::new()));
+        // The clock survives drop_indexes, so its reader gets a generation of
+        // its own that is never bumped
+        let stamp = Stamp::new(Arc::new(AtomicU64::new(0)), self.replacing.clone());
+        TimestampRead::new(self.rows.clone(), index).stamped(stamp, self.deadlines.clone())
+    }
+
+    pub fn enable_checksum(&mut self)
+    where
+        RowT: Hash,
+    {
+        if self.checksum.is_none() {
+            self.checksum = Some(self.register_internal(Checksum::new()));
+        }
+    }
+
+    pub fn checksum(&self) -> Option<u64> {
+        self.checksum
+            .as_ref()
+            .map(|checksum| checksum.read().unwrap().value())
+    }
+
+    pub fn enable_merkle(&mut self)
+    where
+        RowT: Hash,
+    {
+        if self.merkle.is_none() {
+            self.merkle = Some(self.register_internal(MerkleTree::new()));
+        }
+    }
+
+    pub fn root_hash(&self) -> Option<u64> {
+        self.merkle
+            .as_ref()
+            .map(|merkle| merkle.read().unwrap().root_hash())
+    }
+
+    pub(crate) fn merkle_tree(&self) -> Option<&Arc<RwLock<MerkleTree<RowT>>>> {
+        self.merkle.as_ref()
+    }
+
+    pub fn prove(&self, id: RowId) -> Option<MerkleProof> {
+        self.merkle.as_ref()?.read().unwrap().prove(id)
+    }
+
+    pub fn changes(&mut self) -> Receiver<RowEvent<RowT>>
+    where
+        RowT: Send,
+    {
+        let change_feed = match self.change_feed.as_ref() {
+            Some(change_feed) => change_feed.clone(),
+            None => {
+                let change_feed = self.register_internal(ChangeFeed::new());
+                self.change_feed = Some(change_feed.clone());
+                change_feed
+            }
+        };
+        let mut change_feed = change_feed.write().unwrap();
+        change_feed.subscribe()
+    }
+
+    pub fn to_snapshot(&self) -> HashSyncSnapshot<RowT> {
+        let mut rows: Vec<(RowId, RowT)> = self
+            .rows
+            .iter()
+            .map(|r| (*r.key(), r.value().clone()))
+            .collect();
+        rows.sort_by_key(|(id, _row)| *id);
+        HashSyncSnapshot::new(rows, self.next_id)
+    }
+
+    pub fn snapshot(&self) -> Snapshot<RowT> {
+        self.reader().snapshot()
+    }
+
+    pub fn from_snapshot(snapshot: HashSyncSnapshot<RowT>) -> Self {
+        let (rows, next_id) = snapshot.into_parts();
+        let mut hs = HashSync::new();
+        for (id, row) in rows {
+            hs.insert_at(id, row);
+        }
+        hs.next_id = next_id;
+        hs
+    }
+
+    pub fn canonical_dump(&self) -> String
+    where
+        RowT: Debug,
+    {
+        let mut rows: Vec<(RowId, String)> = self
+            .rows
+            .iter()
+            .map(|r| (*r.key(), format!("{:?}", r.value())))
+            .collect();
+        rows.sort();
+
+        let mut dump = String::new();
+        writeln!(dump, "next_id: {:?}", self.next_id).unwrap();
+        writeln!(dump, "indexes:").unwrap();
+        let named = self
+            .indexes
+            .iter()
+            .zip(&self.index_names)
+            .zip(&self.internal);
+        for ((index, name), _internal) in named.filter(|(_index, internal)| !**internal) {
+            match name {
+                Some(name) => writeln!(dump, "  {} {}", index.describe().kind(), name).unwrap(),
+                None => writeln!(dump, "  {}", index.describe().kind()).unwrap(),
+            }
+            for (key, ids) in index.dump_entries() {
+                writeln!(dump, "    {}: {:?}", key, ids).unwrap();
+            }
+        }
+        writeln!(dump, "rows:").unwrap();
+        for (id, row) in rows {
+            writeln!(dump, "  {:?}: {}", id, row).unwrap();
+        }
+        dump
+    }
+
+    pub fn drop_indexes(mut self) -> Self {
+        self.generation.fetch_add(1, Ordering::SeqCst);
+        // Internal indexes record or checksum mutations rather than serve
+        // user reads, so the log, feeds, checksums and timestamps carry on
+        let registered = self
+            .indexes
+            .drain(..)
+            .zip(self.index_ids.drain(..))
+            .zip(self.internal.drain(..));
+        let (indexes, index_ids): (Vec<_>, Vec<_>) = registered
+            .filter(|(_registered, internal)| *internal)
+            .map(|(registered, _internal)| registered)
+            .unzip();
+        let internal = vec![true; indexes.len()];
+        HashSync {
+            rows: self.rows,
+            next_id: self.next_id,
+            index_names: vec![None; indexes.len()],
+            indexes,
+            typed_keys: FxHashSet::default(),
+            unique_checks: Vec::new(),
+            checksum: self.checksum,
+            merkle: self.merkle,
+            change_feed: self.change_feed,
+            version: self.version,
+            last_mutation: self.last_mutation,
+            replacing: self.replacing,
+            generation: self.generation,
+            deadlines: self.deadlines,
+            lru: self.lru,
+            generations: self.generations,
+            index_ids,
+            next_index_id: self.next_index_id,
+            internal,
+            #[cfg(feature = "persist")]
+            wal: self.wal,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index::ChangeEvent;
+
+    #[test]
+    fn insert_once() {
+        let mut hs = HashSync::new();
+        hs.insert((1, 2));
+        hs.insert((1, 3));
+        hs.insert((3, 4));
+        let index = hs.index(|&(a, _b)| a);
+
+        let rows = index.get_values(&1);
+        assert_eq!(rows.len(), 2);
+        assert!(rows.contains(&(1, 2)));
+        assert!(rows.contains(&(1, 3)));
+    }
+
+    #[test]
+    fn insert_twice() {
+        let mut hs = HashSync::new();
+        hs.insert((1, 2));
+        hs.insert((1, 3));
+        hs.insert((3, 4));
+        let index = hs.index(|&(a, _b)| a);
+
+        hs.insert((1, 4));
+        let rows = index.get_values(&1);
+        assert_eq!(rows.len(), 3);
+        assert!(rows.contains(&(1, 2)));
+        assert!(rows.contains(&(1, 3)));
+        assert!(rows.contains(&(1, 4)));
+    }
+
+    #[test]
+    fn index_id() {
+        let mut hs = HashSync::new();
+        let row_id = hs.insert((1, 2));
+        hs.insert((1, 3));
+        hs.insert((3, 4));
+        let index = hs.index_id(|indexed| (indexed.id(), indexed.value().0));
+
+        let rows = index.get_values(&(row_id, 1));
+        assert_eq!(rows.len(), 1);
+        assert!(rows.contains(&(1, 2)));
+        let rows = index.get_values(&(row_id, 2));
+        assert_eq!(rows.len(), 0);
+    }
+
+    #[test]
+    fn keys() {
+        let mut hs = HashSync::new();
+        let row_to_delete = hs.insert((1, 2));
+        hs.insert((1, 3));
+        hs.insert((3, 4));
+
+        let keys = hs.keys();
+        assert_eq!(keys.len(), 3);
+        assert!(keys.contains(&RowId::new(0)));
+        assert!(keys.contains(&RowId::new(1)));
+        assert!(keys.contains(&RowId::new(2)));
+
+        hs.delete(row_to_delete);
+        assert!(!hs.keys().contains(&row_to_delete));
+    }
+
+    #[test]
+    fn by_id() {
+        let mut hs = HashSync::new();
+        let row1 = hs.insert((1, 2));
+        let row2 = hs.insert((1, 3));
+        let row3 = hs.insert((3, 4));
+
+        assert_eq!(hs.by_id(row1), Some((1, 2)));
+        assert_eq!(hs.by_id(row2), Some((1, 3)));
+        assert_eq!(hs.by_id(row3), Some((3, 4)));
+    }
+
+    #[test]
+    fn by_id_indexed() {
+        let mut hs = HashSync::new();
+        let row1 = hs.insert((1, 2));
+        let row2 = hs.insert((1, 3));
+        let row3 = hs.insert((3, 4));
+
+        assert_eq!(hs.by_id_indexed(row1), Some(Indexed::new(row1, (1, 2))));
+        assert_eq!(hs.by_id_indexed(row2), Some(Indexed::new(row2, (1, 3))));
+        assert_eq!(hs.by_id_indexed(row3), Some(Indexed::new(row3, (3, 4))));
+    }
+
+    #[test]
+    fn delete() {
+        let mut hs = HashSync::new();
+        let row_to_delete = hs.insert((1, 2));
+        hs.insert((1, 3));
+        hs.insert((3, 4));
+        let index = hs.index(|&(a, _b)| a);
+
+        hs.delete(row_to_delete);
+        let rows = index.get_values(&1);
+        assert_eq!(rows.len(), 1);
+        assert!(rows.contains(&(1, 3)));
+    }
+
+    #[test]
+    fn two_indexes() {
+        let mut hs = HashSync::new();
+        hs.insert((1, 2));
+        hs.insert((1, 3));
+        hs.insert((3, 2));
+        let index1 = hs.index(|&(a, _b)| a);
+        let index2 = hs.index(|&(_a, b)| b);
+
+        let rows1 = index1.get_values(&1);
+        assert_eq!(rows1.len(), 2);
+        assert!(rows1.contains(&(1, 2)));
+        assert!(rows1.contains(&(1, 3)));
+
+        let rows2 = index2.get_values(&2);
+        assert_eq!(rows2.len(), 2);
+        assert!(rows2.contains(&(1, 2)));
+        assert!(rows2.contains(&(3, 2)));
+    }
+
+    #[test]
+    fn two_indexes_with_delete() {
+        let mut hs = HashSync::new();
+        hs.insert((1, 2));
+        let row_to_delete = hs.insert((1, 3));
+        hs.insert((3, 2));
+        let index1 = hs.index(|&(a, _b)| a);
+        let index2 = hs.index(|&(_a, b)| b);
+
+        let rows1 = index1.get_values(&1);
+        assert_eq!(rows1.len(), 2);
+        assert!(rows1.contains(&(1, 2)));
+        assert!(rows1.contains(&(1, 3)));
+
+        let rows2 = index2.get_values(&2);
+        assert_eq!(rows2.len(), 2);
+        assert!(rows2.contains(&(1, 2)));
+        assert!(rows2.contains(&(3, 2)));
+
+        hs.delete(row_to_delete);
+
+        let rows1 = index1.get_values(&1);
+        assert_eq!(rows1.len(), 1);
+        assert!(rows1.contains(&(1, 2)));
+
+        let rows2 = index2.get_values(&2);
+        assert_eq!(rows2.len(), 2);
+        assert!(rows2.contains(&(1, 2)));
+        assert!(rows2.contains(&(3, 2)));
+    }
+
+    #[test]
+    fn replace() {
+        let mut hs = HashSync::new();
+        let row_to_replace = hs.insert((1, 2));
+        hs.insert((1, 3));
+        hs.insert((3, 2));
+        let index1 = hs.index(|&(a, _b)| a);
+        let index2 = hs.index(|&(_a, b)| b);
+
+        hs.replace(row_to_replace, (1, 4));
+
+        let rows1 = index1.get_values(&1);
+        assert_eq!(rows1.len(), 2);
+        assert!(rows1.contains(&(1, 3)));
+        assert!(rows1.contains(&(1, 4)));
+
+        let rows1_keys = index1.get(&1).iter().map(|i| i.id()).collect::<Vec<_>>();
+        assert_eq!(rows1_keys.len(), 2);
+        assert!(rows1_keys.contains(&row_to_replace));
+        assert!(rows1_keys.contains(&row_to_replace.next()));
+
+        let rows2 = index2.get_values(&2);
+        assert_eq!(rows2.len(), 1);
+        assert!(rows2.contains(&(3, 2)));
+    }
+
+    #[test]
+    fn index_many() {
+        let mut hs = HashSync::new();
+        hs.insert((1, 2));
+        hs.insert((1, 3));
+        hs.insert((3, 1));
+        let index = hs.index_many(|&(a, b)| vec![a, b]);
+
+        let rows1 = index.get_values(&1);
+        assert_eq!(rows1.len(), 3);
+        assert!(rows1.contains(&(1, 2)));
+        assert!(rows1.contains(&(1, 3)));
+        assert!(rows1.contains(&(3, 1)));
+
+        let rows2 = index.get_values(&3);
+        assert_eq!(rows2.len(), 2);
+        assert!(rows2.contains(&(1, 3)));
+        assert!(rows2.contains(&(3, 1)));
+    }
+
+    #[test]
+    fn index_many_with_removal() {
+        let mut hs = HashSync::new();
+        let row_to_delete = hs.insert((1, 2));
+        hs.insert((1, 3));
+        hs.insert((3, 1));
+        let index = hs.index_many(|&(a, b)| vec![a, b]);
+
+        hs.delete(row_to_delete);
+
+        let rows1 = index.get_values(&2);
+        assert_eq!(rows1.len(), 0);
+
+        let rows2 = index.get_values(&1);
+        assert_eq!(rows2.len(), 2);
+        assert!(rows2.contains(&(1, 3)));
+        assert!(rows2.contains(&(3, 1)));
+    }
+
+    #[test]
+    fn replace_increases_max_id() {
+        let mut hs = HashSync::new();
+        hs.replace(RowId::new(5), (1, 4));
+
+        let row_id = hs.insert((1, 2));
+        assert_eq!(row_id, RowId::new(6));
+    }
+
+    #[test]
+    fn index_keys() {
+        let mut hs = HashSync::new();
+        hs.insert((1, 2));
+        hs.insert((1, 3));
+        hs.insert((3, 1));
+        let index = hs.index(|&(a, _b)| a);
+
+        let keys = index.keys();
+        assert_eq!(keys.len(), 2);
+        assert!(keys.contains(&1));
+        assert!(keys.contains(&3));
+    }
+
+    #[test]
+    fn index_keys_after_removal() {
+        let mut hs = HashSync::new();
+        let row_to_delete = hs.insert((1, 2));
+        hs.insert((3, 1));
+        let index = hs.index(|&(a, _b)| a);
+
+        hs.delete(row_to_delete);
+
+        let keys = index.keys();
+        assert_eq!(keys.len(), 1);
+        assert!(keys.contains(&3));
+    }
+
+    #[test]
+    fn get_one() {
+        let mut hs = HashSync::new();
+        let row_id = hs.insert((1, 2));
+        hs.insert((3, 4));
+        hs.insert((3, 5));
+        let index = hs.index(|&(a, _b)| a);
+
+        assert_eq!(index.get_one(&1), Some(Indexed::new(row_id, (1, 2))));
+        assert_eq!(index.get_one(&2), None);
+        let row = index.get_one(&3).unwrap();
+        assert_eq!(row.value().0, 3);
+    }
+
+    #[test]
+    fn get_unique() {
+        let mut hs = HashSync::new();
+        let row_id = hs.insert((1, 2));
+        let dup1 = hs.insert((3, 4));
+        let dup2 = hs.insert((3, 5));
+        let index = hs.index(|&(a, _b)| a);
+
+        assert_eq!(index.get_unique(&1), Ok(Some(Indexed::new(row_id, (1, 2)))));
+        assert_eq!(index.get_unique(&2), Ok(None));
+
+        let err = index.get_unique(&3).unwrap_err();
+        assert_eq!(err.ids(), &[dup1, dup2]);
+    }
+
+    #[test]
+    fn get_after() {
+        let mut hs = HashSync::new();
+        let ids: Vec<RowId> = (0..5).map(|i| hs.insert((1, i))).collect();
+        hs.insert((2, 0));
+        let index = hs.index(|&(a, _b)| a);
+
+        let page1 = index.get_after(&1, None, 2);
+        assert_eq!(page1.iter().map(|i| i.id()).collect::<Vec<_>>(), &ids[0..2]);
+
+        let cursor = page1.last().map(|i| i.id());
+        let page2 = index.get_after(&1, cursor, 2);
+        assert_eq!(page2.iter().map(|i| i.id()).collect::<Vec<_>>(), &ids[2..4]);
+
+        let cursor = page2.last().map(|i| i.id());
+        let page3 = index.get_after(&1, cursor, 2);
+        assert_eq!(page3.iter().map(|i| i.id()).collect::<Vec<_>>(), &ids[4..5]);
+
+        assert!(index.get_after(&3, None, 2).is_empty());
+    }
+
+    #[test]
+    fn index_tracked_deletes_without_rerunning_index_fn() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let calls = Arc::new(AtomicUsize::new(0));
+        let calls_in_fn = calls.clone();
+        let mut hs = HashSync::new();
+        let row_to_replace = hs.insert((1, 2));
+        hs.insert((1, 3));
+        let index = hs.index_tracked(move |&(a, _b)| {
+            calls_in_fn.fetch_add(1, Ordering::SeqCst);
+            a
+        });
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+
+        hs.replace(row_to_replace, (2, 2));
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+        assert_eq!(index.get_values(&1), vec![(1, 3)]);
+        assert_eq!(index.get_values(&2), vec![(2, 2)]);
+
+        hs.delete(row_to_replace);
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+        assert!(index.get_values(&2).is_empty());
+    }
+
+    #[test]
+    fn verify_indexes_detects_leaks_from_impure_index_fn() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let offset = Arc::new(AtomicUsize::new(0));
+        let offset_in_fn = offset.clone();
+        let mut hs = HashSync::new();
+        let row_to_delete = hs.insert((1, 2));
+        hs.insert((1, 3));
+        let _index = hs.index(move |&(a, _b)| a + offset_in_fn.load(Ordering::SeqCst));
+        assert_eq!(hs.verify_indexes(), Ok(()));
+
+        offset.store(10, Ordering::SeqCst);
+        hs.delete(row_to_delete);
+        let err = hs.verify_indexes().unwrap_err();
+        assert_eq!(err.position(), 0);
+        assert!(err.leaked().contains(&row_to_delete));
+    }
+
+    #[test]
+    fn verify_indexes_tracked_index_does_not_leak() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let offset = Arc::new(AtomicUsize::new(0));
+        let offset_in_fn = offset.clone();
+        let mut hs = HashSync::new();
+        let row_to_delete = hs.insert((1, 2));
+        let index = hs.index_tracked(move |&(a, _b)| a + offset_in_fn.load(Ordering::SeqCst));
+
+        offset.store(10, Ordering::SeqCst);
+        hs.delete(row_to_delete);
+        assert_eq!(hs.verify_indexes(), Ok(()));
+        assert_eq!(index.key_count(), 0);
+    }
+
+    #[test]
+    fn ranked_index() {
+        let mut hs = HashSync::new();
+        hs.insert(("apple", 0.5));
+        hs.insert(("apple", 0.9));
+        let row_to_delete = hs.insert(("apple", 0.7));
+        hs.insert(("pear", 0.1));
+        let index = hs.ranked_index(|&(name, score)| vec![(name, score)]);
+
+        assert_eq!(
+            index.get_ranked_values(&"apple"),
+            vec![("apple", 0.9), ("apple", 0.7), ("apple", 0.5)]
+        );
+        assert_eq!(index.score(&"apple", row_to_delete), Some(0.7));
+
+        hs.delete(row_to_delete);
+        assert_eq!(
+            index.get_ranked_values(&"apple"),
+            vec![("apple", 0.9), ("apple", 0.5)]
+        );
+        assert_eq!(index.score(&"apple", row_to_delete), None);
+        assert!(index.get_ranked(&"banana").is_empty());
+
+        let added = hs.insert(("apple", 0.5));
+        hs.replace(RowId::new(0), ("apple", 1.0));
+        assert_eq!(
+            index.get_ranked_values(&"apple"),
+            vec![("apple", 1.0), ("apple", 0.9), ("apple", 0.5)]
+        );
+        assert_eq!(index.get_ranked(&"apple")[2].0.id(), added);
+        assert_eq!(hs.describe().indexes()[0].kind(), "ranked");
+        hs.verify_indexes().unwrap();
+    }
+
+    #[test]
+    fn expiring_index() {
+        let mut hs = HashSync::new();
+        hs.insert((1, 2));
+        hs.insert((1, 3));
+        let expired = hs.expiring_index(Duration::ZERO, |&(a, _b)| vec![a]);
+        let live = hs.expiring_index(Duration::from_secs(3600), |&(a, _b)| vec![a]);
+
+        assert!(expired.get_values(&1).is_empty());
+        assert_eq!(expired.key_count(), 1);
+        assert_eq!(live.get_values(&1).len(), 2);
+
+        hs.expire_stale();
+        assert_eq!(expired.key_count(), 0);
+        assert_eq!(live.key_count(), 1);
+        assert_eq!(hs.keys().len(), 2);
+        assert_eq!(hs.verify_indexes(), Ok(()));
+    }
+
+    #[test]
+    fn versions() {
+        let mut hs = HashSync::new();
+        assert_eq!(hs.current_version(), 0);
+        let row_id = hs.insert((1, 2));
+        assert_eq!(hs.current_version(), 1);
+        let index = hs.index(|&(a, _b)| a);
+
+        hs.replace(row_id, (1, 3));
+        assert_eq!(hs.current_version(), 2);
+        let (version, rows) = index.get_versioned(&1);
+        assert_eq!(version, 2);
+        assert_eq!(rows, vec![Indexed::new(row_id, (1, 3))]);
+
+        hs.delete(row_id);
+        hs.delete(row_id);
+        assert_eq!(hs.current_version(), 3);
+        assert_eq!(index.get_versioned(&1), (3, Vec::new()));
+    }
+
+    #[test]
+    fn absorb() {
+        let mut hs = HashSync::new();
+        hs.insert((1, 2));
+        let index = hs.index(|&(a, _b)| a);
+
+        let mut other = HashSync::new();
+        let conflicting = other.insert((1, 3));
+        let preserved = other.insert((3, 4));
+
+        let mapping = hs.absorb(other).unwrap();
+        assert_eq!(mapping.len(), 2);
+        assert_eq!(mapping.get(preserved), Some(preserved));
+        let remapped = mapping.get(conflicting).unwrap();
+        assert_ne!(remapped, conflicting);
+        assert_eq!(hs.by_id(remapped), Some((1, 3)));
+        assert_eq!(hs.by_id(preserved), Some((3, 4)));
+        assert_eq!(index.get_values(&1).len(), 2);
+        assert_eq!(index.get_values(&3), vec![(3, 4)]);
+    }
+
+    #[test]
+    fn extract() {
+        let mut hs = HashSync::new();
+        let id1 = hs.insert((1, 2));
+        let id2 = hs.insert((1, 3));
+        let id3 = hs.insert((3, 4));
+
+        let mut extracted = hs.extract(|&(a, _b)| a == 1);
+        assert_eq!(extracted.keys().len(), 2);
+        assert_eq!(extracted.by_id(id1), Some((1, 2)));
+        assert_eq!(extracted.by_id(id2), Some((1, 3)));
+        assert_eq!(extracted.by_id(id3), None);
+
+        let index = extracted.index(|&(_a, b)| b);
+        assert_eq!(index.get_values(&3), vec![(1, 3)]);
+        let new_id = extracted.insert((1, 5));
+        assert!(new_id > id3);
+        assert_eq!(hs.keys().len(), 3);
+    }
+
+    #[test]
+    fn by_id_or_err() {
+        let mut hs = HashSync::new();
+        assert_eq!(
+            hs.by_id_or_err(RowId::new(0)),
+            Err(RowNotFound::new(RowId::new(0), None))
+        );
+
+        let row1 = hs.insert((1, 2));
+        let row2 = hs.insert((1, 3));
+        assert_eq!(hs.by_id_or_err(row1), Ok((1, 2)));
+
+        let err = hs.by_id_or_err(RowId::new(7)).unwrap_err();
+        assert_eq!(err.id(), RowId::new(7));
+        assert_eq!(err.max_known_id(), Some(row2));
+    }
+
+    #[test]
+    fn typed_index_rejects_duplicate_key_types() {
+        #[derive(Debug, PartialEq, Eq, Hash)]
+        struct FirstKey(i32);
+        #[derive(Debug, PartialEq, Eq, Hash)]
+        struct SecondKey(i32);
+
+        let mut hs = HashSync::new();
+        hs.insert((1, 2));
+        let first = hs.typed_index(|&(a, _b)| FirstKey(a)).unwrap();
+        let second = hs.typed_index(|&(_a, b)| SecondKey(b)).unwrap();
+        assert_eq!(first.get_values(&FirstKey(1)), vec![(1, 2)]);
+        assert_eq!(second.get_values(&SecondKey(2)), vec![(1, 2)]);
+
+        let err = hs.typed_index(|&(_a, b)| FirstKey(b)).err().unwrap();
+        assert!(err.key_type().ends_with("FirstKey"));
+
+        let mut hs = hs.drop_indexes();
+        assert!(hs.typed_index(|&(a, _b)| FirstKey(a)).is_ok());
+    }
+
+    #[test]
+    fn replace_many() {
+        let mut hs = HashSync::new();
+        let id1 = hs.insert((1, true));
+        let id2 = hs.insert((2, false));
+        let index = hs.index(|&(_a, primary)| primary);
+        let version = hs.current_version();
+
+        hs.replace_many(vec![(id1, (1, false)), (id2, (2, true))]);
+        assert_eq!(hs.current_version(), version + 1);
+        assert_eq!(index.get_values(&true), vec![(2, true)]);
+        assert_eq!(index.get_values(&false), vec![(1, false)]);
+    }
+
+    #[test]
+    fn swap() {
+        let mut hs = HashSync::new();
+        let id1 = hs.insert((1, 2));
+        let id2 = hs.insert((3, 4));
+        let index = hs.index(|&(a, _b)| a);
+
+        assert!(hs.swap(id1, id2).unwrap());
+        assert_eq!(hs.by_id(id1), Some((3, 4)));
+        assert_eq!(hs.by_id(id2), Some((1, 2)));
+        assert_eq!(index.get(&1), vec![Indexed::new(id2, (1, 2))]);
+
+        assert!(!hs.swap(id1, RowId::new(10)).unwrap());
+        assert_eq!(hs.by_id(id1), Some((3, 4)));
+    }
+
+    #[test]
+    fn index_reads_never_observe_half_applied_swap() {
+        use std::{sync::atomic::AtomicBool, thread};
+
+        let mut hs = HashSync::new();
+        let id1 = hs.insert((1, 2));
+        let id2 = hs.insert((3, 4));
+        let index = hs.index(|&(a, _b)| a);
+        let sorted = hs.sorted_index(|&(a, _b)| a);
+        let done = AtomicBool::new(false);
+
+        thread::scope(|scope| {
+            scope.spawn(|| {
+                while !done.load(Ordering::SeqCst) {
+                    assert_eq!(index.get(&1).len(), 1);
+                    assert_eq!(sorted.range(..).len(), 2);
+                }
+            });
+            for _ in 0..2_000 {
+                hs.swap(id1, id2).unwrap();
+            }
+            done.store(true, Ordering::SeqCst);
+        });
+
+        assert_eq!(index.get(&1), vec![Indexed::new(id1, (1, 2))]);
+    }
+
+    #[test]
+    fn index_memory_budget() {
+        use std::sync::Mutex;
+
+        let mut hs = HashSync::new();
+        let row_id = hs.insert((1, 2));
+        let index = hs.index(|&(a, _b)| a);
+        let budget = index.estimated_bytes() * 2;
+
+        let reports = Arc::new(Mutex::new(Vec::new()));
+        let reports_in_fn = reports.clone();
+        index.set_memory_budget(budget, move |bytes| {
+            reports_in_fn.lock().unwrap().push(bytes)
+        });
+
+        hs.insert((2, 2));
+        assert!(reports.lock().unwrap().is_empty());
+        hs.insert((3, 2));
+        hs.insert((4, 2));
+        assert_eq!(reports.lock().unwrap().len(), 1);
+        assert!(reports.lock().unwrap()[0] > budget);
+
+        hs.delete(row_id);
+        hs.delete(RowId::new(1));
+        hs.insert((5, 2));
+        hs.insert((6, 2));
+        assert_eq!(reports.lock().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn canonical_dump() {
+        let mut hs = HashSync::new();
+        hs.replace(RowId::new(2), (3, 1));
+        hs.insert((1, 2));
+        hs.replace(RowId::new(0), (1, 3));
+        let index = hs.index(|&(a, _b)| a);
+        hs.enable_checksum();
+        let _sorted = hs.sorted_index(|&(_a, b)| b);
+
+        let mut hashed = [
+            (
+                format!("{:016x}", fxhash::hash64(&1)),
+                "[RowId(0), RowId(3)]",
+            ),
+            (format!("{:016x}", fxhash::hash64(&3)), "[RowId(2)]"),
+        ];
+        hashed.sort();
+        let mut expected = String::from("next_id: RowId(4)\nindexes:\n  hash\n");
+        for (key, ids) in hashed {
+            expected.push_str(&format!("    {}: {}\n", key, ids));
+        }
+        expected.push_str(
+            "  sorted\n\
+             \x20   #0: [RowId(2)]\n\
+             \x20   #1: [RowId(3)]\n\
+             \x20   #2: [RowId(0)]\n\
+             rows:\n\
+             \x20 RowId(0): (1, 3)\n\
+             \x20 RowId(2): (3, 1)\n\
+             \x20 RowId(3): (1, 2)\n",
+        );
+        assert_eq!(hs.canonical_dump(), expected);
+        assert_eq!(
+            index.canonical_dump(),
+            "1: [RowId(0), RowId(3)]\n\
+             3: [RowId(2)]\n"
+        );
+    }
+
+    #[test]
+    fn stats() {
+        let mut hs = HashSync::new();
+        let stats = hs.stats();
+        assert_eq!(stats.version(), 0);
+        assert_eq!(stats.rows(), 0);
+        assert_eq!(stats.last_mutation(), None);
+
+        let row_id = hs.insert((1, 2));
+        hs.insert((1, 3));
+        hs.delete(row_id);
+        let _index = hs.index(|&(a, _b)| a);
+        hs.enable_checksum();
+        hs.enable_merkle();
+        let _changes = hs.changes();
+        let stats = hs.stats();
+        assert_eq!(stats.version(), 3);
+        assert_eq!(stats.rows(), 1);
+        assert_eq!(stats.indexes(), 1);
+        assert!(stats.last_mutation().is_some());
+    }
+
+    #[test]
+    fn covering_index() {
+        let mut hs = HashSync::new();
+        let id1 = hs.insert(("alice", 100, "a long description"));
+        let id2 = hs.insert(("alice", 200, "another long description"));
+        hs.insert(("bob", 300, "..."));
+        let index = hs.covering_index(|&(user, timestamp, _body)| vec![(user, timestamp)]);
+
+        assert_eq!(index.get_payloads(&"alice"), vec![(id1, 100), (id2, 200)]);
+        assert_eq!(index.payload(&"alice", id2), Some(200));
+
+        hs.replace(id1, ("alice", 150, "edited"));
+        assert_eq!(index.get_payloads(&"alice"), vec![(id1, 150), (id2, 200)]);
+        assert_eq!(index.get(&"alice").len(), 2);
+        assert!(index.get_payloads(&"carol").is_empty());
+        assert_eq!(hs.verify_indexes(), Ok(()));
+    }
+
+    #[test]
+    fn sorted_index_range() {
+        let mut hs = HashSync::new();
+        let index = hs.sorted_index(|&(_name, age)| age);
+        hs.insert(("carol", 41));
+        let bob = hs.insert(("bob", 25));
+        hs.insert(("alice", 33));
+        hs.insert(("dave", 33));
+
+        assert_eq!(
+            index.range_values(30..40),
+            vec![("alice", 33), ("dave", 33)]
+        );
+        assert_eq!(index.range_values(..=25), vec![("bob", 25)]);
+        assert_eq!(index.first().map(|i| i.into_value()), Some(("bob", 25)));
+        assert_eq!(index.last().map(|i| i.into_value()), Some(("carol", 41)));
+        assert_eq!(index.keys(), vec![25, 33, 41]);
+
+        hs.replace(bob, ("bob", 50));
+        assert_eq!(index.last().map(|i| i.into_value()), Some(("bob", 50)));
+        assert_eq!(index.keys(), vec![33, 41, 50]);
+        assert_eq!(hs.verify_indexes(), Ok(()));
+    }
+
+    #[test]
+    fn unique_index_rejects_duplicates() {
+        let mut hs = HashSync::new();
+        let emails = hs.unique_index(|&(email, _name)| email).unwrap();
+        let alice = hs.insert_unique(("a@example.com", "alice")).unwrap();
+        let bob = hs.insert_unique(("b@example.com", "bob")).unwrap();
+
+        let err = hs.insert_unique(("a@example.com", "mallory")).unwrap_err();
+        assert_eq!(err.ids(), &[alice]);
+        assert_eq!(hs.keys().len(), 2);
+        assert!(hs.replace_unique(bob, ("a@example.com", "bob")).is_err());
+        hs.replace_unique(alice, ("a@example.com", "alice smith"))
+            .unwrap();
+
+        assert_eq!(
+            emails.get_one(&"a@example.com").map(|i| i.id()),
+            Some(alice)
+        );
+        assert_eq!(emails.get_one_value(&"c@example.com"), None);
+        assert_eq!(hs.verify_indexes(), Ok(()));
+    }
+
+    #[test]
+    fn unique_index_replace_policy() {
+        let mut hs = HashSync::new();
+        let names = hs
+            .unique_index_with_policy(|&(name, _score)| name, UniquePolicy::Replace)
+            .unwrap();
+        let first = hs.insert_unique(("alice", 1)).unwrap();
+        let second = hs.insert_unique(("alice", 2)).unwrap();
+
+        assert_eq!(hs.by_id(first), None);
+        assert_eq!(names.get_one(&"alice").map(|i| i.id()), Some(second));
+        assert_eq!(names.key_count(), 1);
+        assert_eq!(hs.verify_indexes(), Ok(()));
+    }
+
+    #[test]
+    fn unique_index_applies_policy_to_existing_rows() {
+        let mut hs = HashSync::new();
+        let first = hs.insert(("alice", 1));
+        let second = hs.insert(("alice", 2));
+        hs.insert(("bob", 3));
+
+        let err = hs.unique_index(|&(name, _score)| name).err().unwrap();
+        assert_eq!(err.ids(), &[first]);
+        assert_eq!(hs.keys().len(), 3);
+        assert_eq!(hs.describe().indexes().len(), 0);
+
+        let names = hs
+            .unique_index_with_policy(|&(name, _score)| name, UniquePolicy::Replace)
+            .unwrap();
+        assert_eq!(hs.by_id(first), None);
+        assert_eq!(names.get_id(&"alice"), Some(second));
+        assert_eq!(names.key_count(), 2);
+        hs.verify_indexes().unwrap();
+    }
+
+    #[test]
+    #[should_panic(expected = "unique")]
+    fn plain_insert_rejects_duplicate_unique_key() {
+        let mut hs = HashSync::new();
+        hs.unique_index(|&(email, _name)| email).unwrap();
+        hs.insert(("a@example.com", "alice"));
+        hs.insert(("a@example.com", "mallory"));
+    }
+
+    #[test]
+    fn rejected_writes_leave_store_readable() {
+        let mut hs = HashSync::new();
+        let names = hs.unique_index(|&(name, _score)| name).unwrap();
+        let alice = hs.insert(("alice", 1));
+        let bob = hs.insert(("bob", 2));
+        let reader = hs.reader();
+        let version = hs.current_version();
+
+        assert!(hs.replace_unique(bob, ("alice", 2)).is_err());
+        let err = hs.update_unique(bob, |row| row.0 = "alice").unwrap_err();
+        assert_eq!(err.ids(), &[alice]);
+        assert!(hs
+            .replace_many_unique(vec![(bob, ("carol", 2)), (alice, ("carol", 1))])
+            .is_err());
+        let result = hs.transaction(|tx| {
+            tx.insert(("alice", 3));
+            Ok::<_, ()>(())
+        });
+        assert_eq!(
+            result,
+            Err(TransactionError::Unique(UniqueViolation::new(vec![alice])))
+        );
+        assert!(hs.insert_batch(vec![("carol", 3), ("alice", 3)]).is_err());
+        assert!(hs.bulk_load(|loader| loader.insert(("bob", 3))).is_err());
+        assert!(hs.merge_rows(&[bob], |_rows| ("alice", 2)).is_err());
+        let mut other = HashSync::new();
+        other.insert(("alice", 4));
+        assert!(hs.absorb(other).is_err());
+
+        assert_eq!(hs.current_version(), version);
+        assert_eq!(reader.by_id_consistent(bob), Some(("bob", 2)));
+        assert_eq!(names.get_one_value(&"bob"), Some(("bob", 2)));
+        assert_eq!(hs.insert(("carol", 3)), bob.next());
+        hs.verify_indexes().unwrap();
+    }
+
+    #[test]
+    fn plain_writes_apply_unique_policy() {
+        let mut hs = HashSync::new();
+        let names = hs
+            .unique_index_with_policy(|&(name, _score)| name, UniquePolicy::Replace)
+            .unwrap();
+        let first = hs.insert(("alice", 1));
+        let second = hs.insert(("alice", 2));
+        assert_eq!(hs.by_id(first), None);
+
+        let bob = hs.insert(("bob", 3));
+        hs.update(bob, |row| row.0 = "alice");
+        assert_eq!(hs.by_id(second), None);
+
+        let ids = hs.insert_batch(vec![("carol", 4), ("carol", 5)]).unwrap();
+        assert_eq!(ids.len(), 1);
+        assert_eq!(hs.by_id(ids[0]), Some(("carol", 5)));
+        assert_eq!(names.get_one_value(&"alice"), Some(("alice", 3)));
+        assert_eq!(hs.keys().len(), 2);
+        assert_eq!(hs.verify_indexes(), Ok(()));
+    }
+
+    #[test]
+    fn swap_trades_unique_keys() {
+        let mut hs = HashSync::new();
+        let names = hs.unique_index(|&(name, _score)| name).unwrap();
+        let alice = hs.insert(("alice", 1));
+        let bob = hs.insert(("bob", 2));
+
+        assert!(hs.swap(alice, bob).unwrap());
+        assert_eq!(names.get_one(&"alice").map(|i| i.id()), Some(bob));
+        assert_eq!(names.get_one(&"bob").map(|i| i.id()), Some(alice));
+        assert_eq!(hs.verify_indexes(), Ok(()));
+    }
+
+    #[test]
+    fn update_in_place() {
+        let mut hs = HashSync::new();
+        let by_name = hs.index(|&(name, _visits)| name);
+        let by_visits = hs.index_tracked(|&(_name, visits)| visits);
+        let id = hs.insert(("alice", 1));
+        hs.insert(("bob", 1));
+
+        assert!(hs.update(id, |row| row.1 += 1));
+        assert_eq!(hs.by_id(id), Some(("alice", 2)));
+        assert_eq!(by_name.get_values(&"alice"), vec![("alice", 2)]);
+        assert_eq!(by_visits.get_values(&2), vec![("alice", 2)]);
+        assert_eq!(by_visits.get_values(&1), vec![("bob", 1)]);
+        assert!(!hs.update(RowId::new(10), |row| row.1 += 1));
+        assert_eq!(hs.verify_indexes(), Ok(()));
+    }
+
+    #[test]
+    fn replace_never_hides_row_from_readers() {
+        let mut hs = HashSync::new();
+        let index = hs.index(|&(a, _b)| a);
+        let id = hs.insert((1, 0));
+
+        let reader = index.clone();
+        let reads = std::thread::spawn(move || (0..10_000).all(|_| reader.get(&1).len() == 1));
+        for b in 1..10_000 {
+            hs.replace(id, (1, b));
+        }
+        assert!(reads.join().unwrap());
+        assert_eq!(hs.verify_indexes(), Ok(()));
+    }
+
+    #[test]
+    fn describe_lists_indexes() {
+        let mut hs: HashSync<(u32, &str)> = HashSync::new();
+        hs.insert((1, "a"));
+        hs.index_tracked(|&(a, _b)| a);
+        hs.unique_index(|&(_a, b)| b).unwrap();
+        hs.enable_checksum();
+
+        let description = hs.describe();
+        assert_eq!(description.row_type(), "(u32, &str)");
+        assert_eq!(description.rows(), 1);
+        let kinds: Vec<&str> = description.indexes().iter().map(|i| i.kind()).collect();
+        assert_eq!(kinds, vec!["hash", "unique"]);
+        let features: Vec<&str> = description.features().iter().map(|i| i.kind()).collect();
+        assert_eq!(features, vec!["checksum"]);
+        assert_eq!(description.indexes()[0].key_type(), Some("u32"));
+        assert_eq!(
+            description.indexes()[0].options(),
+            &[("tracked", "true".to_string())]
+        );
+        assert_eq!(
+            description.indexes()[1].options(),
+            &[("policy", "Reject".to_string())]
+        );
+    }
+
+    #[test]
+    fn subscribe_to_index_key() {
+        let mut hs = HashSync::new();
+        let index = hs.index(|&(a, _b)| a);
+        let events = index.subscribe(&1);
+
+        let id = hs.insert((1, 2));
+        hs.insert((2, 2));
+        hs.replace(id, (1, 3));
+        hs.replace(id, (2, 3));
+        hs.replace(id, (1, 4));
+        hs.delete(id);
+
+        let events: Vec<ChangeEvent<(i32, i32)>> = events.try_iter().collect();
+        assert_eq!(
+            events,
+            vec![
+                ChangeEvent::Inserted(Indexed::new(id, (1, 2))),
+                ChangeEvent::Replaced {
+                    old: Indexed::new(id, (1, 2)),
+                    new: Indexed::new(id, (1, 3)),
+                },
+                ChangeEvent::Deleted(Indexed::new(id, (1, 3))),
+                ChangeEvent::Inserted(Indexed::new(id, (1, 4))),
+                ChangeEvent::Deleted(Indexed::new(id, (1, 4))),
+            ]
+        );
     }
 
-    pub fn drop_indexes(self) -> Self {
-        HashSync {
-            rows: self.rows,
-            next_id: self.next_id,
-            indexes: Vec::new(),
+    #[test]
+    fn delete_by_index_key() {
+        let mut hs = HashSync::new();
+        let by_tenant = hs.index(|&(tenant, _n)| tenant);
+        let by_n = hs.index(|&(_tenant, n)| n);
+        for n in 0..5 {
+            hs.insert(("acme", n));
+            hs.insert(("globex", n));
         }
+        let version = hs.current_version();
+
+        let deleted = hs.delete_by(&by_tenant, &"acme");
+        assert_eq!(deleted, (0..5).map(|n| ("acme", n)).collect::<Vec<_>>());
+        assert!(by_tenant.get(&"acme").is_empty());
+        assert_eq!(by_n.get_values(&3), vec![("globex", 3)]);
+        assert_eq!(hs.current_version(), version + 1);
+        assert!(hs.delete_by(&by_tenant, &"acme").is_empty());
+        assert_eq!(hs.current_version(), version + 1);
+        assert_eq!(hs.verify_indexes(), Ok(()));
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    #[test]
+    fn move_key_between_index_keys() {
+        let mut hs = HashSync::new();
+        let tag_index = hs.index(|row: &(String, u32)| row.0.clone());
+        let old_a = hs.insert(("old".to_string(), 1));
+        let old_b = hs.insert(("old".to_string(), 2));
+        let other = hs.insert(("other".to_string(), 3));
+
+        let moved = hs
+            .move_key(
+                &tag_index,
+                &"old".to_string(),
+                &"new".to_string(),
+                |row| row.1 < 2,
+                |row| row.0 = "new".to_string(),
+            )
+            .unwrap();
+
+        assert_eq!(moved, vec![old_a]);
+        assert_eq!(
+            tag_index.get_values(&"old".to_string()),
+            vec![("old".to_string(), 2)]
+        );
+        assert_eq!(
+            tag_index.get_values(&"new".to_string()),
+            vec![("new".to_string(), 1)]
+        );
+        assert_eq!(hs.by_id(other), Some(("other".to_string(), 3)));
+        assert_eq!(hs.by_id(old_b), Some(("old".to_string(), 2)));
+        hs.verify_indexes().unwrap();
+    }
 
     #[test]
-    fn insert_once() {
+    fn transaction_commits_or_rolls_back() {
         let mut hs = HashSync::new();
-        hs.insert((1, 2));
-        hs.insert((1, 3));
-        hs.insert((3, 4));
         let index = hs.index(|&(a, _b)| a);
+        let kept = hs.insert((1, 1));
+        let removed = hs.insert((1, 2));
+        let version = hs.current_version();
 
-        let rows = index.get_values(&1);
-        assert_eq!(rows.len(), 2);
-        assert!(rows.contains(&(1, 2)));
-        assert!(rows.contains(&(1, 3)));
+        let inserted = hs
+            .transaction(|tx| {
+                let inserted = tx.insert((2, 3));
+                tx.delete(removed);
+                tx.update(kept, |row| row.1 = 10);
+                assert_eq!(tx.by_id(removed), None);
+                Ok::<_, ()>(inserted)
+            })
+            .unwrap();
+
+        assert_eq!(hs.current_version(), version + 1);
+        assert_eq!(index.get_values(&1), vec![(1, 10)]);
+        assert_eq!(index.get_values(&2), vec![(2, 3)]);
+        assert_eq!(hs.insert((3, 3)), inserted.next());
+
+        let result: Result<(), _> = hs.transaction(|tx| {
+            tx.delete(kept);
+            tx.insert((4, 4));
+            Err("abort")
+        });
+        assert_eq!(result, Err(TransactionError::Aborted("abort")));
+        assert_eq!(hs.by_id(kept), Some((1, 10)));
+        assert!(index.get_values(&4).is_empty());
+        hs.verify_indexes().unwrap();
     }
 
     #[test]
-    fn insert_twice() {
+    fn insert_batch_allocates_contiguous_ids() {
         let mut hs = HashSync::new();
-        hs.insert((1, 2));
-        hs.insert((1, 3));
-        hs.insert((3, 4));
         let index = hs.index(|&(a, _b)| a);
+        let first = hs.insert((0, 0));
+        let version = hs.current_version();
 
-        hs.insert((1, 4));
-        let rows = index.get_values(&1);
-        assert_eq!(rows.len(), 3);
-        assert!(rows.contains(&(1, 2)));
-        assert!(rows.contains(&(1, 3)));
-        assert!(rows.contains(&(1, 4)));
+        let ids = hs.insert_batch(vec![(1, 1), (1, 2), (2, 3)]).unwrap();
+        assert_eq!(ids, vec![first.next(), first.next().next(), RowId::new(3)]);
+        assert_eq!(hs.current_version(), version + 1);
+        assert_eq!(index.get(&1).len(), 2);
+
+        hs.extend([(2, 4), (3, 5)]);
+        assert_eq!(index.get(&2).len(), 2);
+        assert_eq!(hs.insert((4, 6)), RowId::new(6));
+        hs.verify_indexes().unwrap();
     }
 
     #[test]
-    fn index_id() {
+    fn retain_and_delete_where() {
         let mut hs = HashSync::new();
-        let row_id = hs.insert((1, 2));
-        hs.insert((1, 3));
-        hs.insert((3, 4));
-        let index = hs.index_id(|indexed| (indexed.id(), indexed.value().0));
+        let index = hs.index(|&(a, _b)| a);
+        let ids = hs
+            .insert_batch(vec![(1, 1), (1, 2), (2, 3), (3, 4)])
+            .unwrap();
 
-        let rows = index.get_values(&(row_id, 1));
-        assert_eq!(rows.len(), 1);
-        assert!(rows.contains(&(1, 2)));
-        let rows = index.get_values(&(row_id, 2));
-        assert_eq!(rows.len(), 0);
+        let removed = hs.delete_where(|&(a, _b)| a == 1);
+        assert_eq!(
+            removed,
+            vec![Indexed::new(ids[0], (1, 1)), Indexed::new(ids[1], (1, 2))]
+        );
+        assert!(index.get(&1).is_empty());
+
+        hs.retain(|id, &(_a, b)| id == ids[2] || b > 10);
+        assert_eq!(hs.keys(), vec![ids[2]]);
+        assert!(index.get(&3).is_empty());
+        hs.verify_indexes().unwrap();
     }
 
     #[test]
-    fn keys() {
+    fn delete_by_key_removes_from_every_index() {
         let mut hs = HashSync::new();
-        let row_to_delete = hs.insert((1, 2));
-        hs.insert((1, 3));
-        hs.insert((3, 4));
+        let by_a = hs.index(|&(a, _b)| a);
+        let by_b = hs.index(|&(_a, b)| b);
+        let ids = hs.insert_batch(vec![(1, 7), (1, 8), (2, 7)]).unwrap();
 
-        let keys = hs.keys();
-        assert_eq!(keys.len(), 3);
-        assert!(keys.contains(&RowId::new(0)));
-        assert!(keys.contains(&RowId::new(1)));
-        assert!(keys.contains(&RowId::new(2)));
+        let removed = hs.delete_by_key(&by_a, &1);
+        assert_eq!(
+            removed,
+            vec![Indexed::new(ids[0], (1, 7)), Indexed::new(ids[1], (1, 8))]
+        );
+        assert_eq!(by_b.get_values(&7), vec![(2, 7)]);
+        assert!(by_b.get(&8).is_empty());
+        assert!(hs.delete_by_key(&by_a, &1).is_empty());
+        hs.verify_indexes().unwrap();
+    }
 
-        hs.delete(row_to_delete);
-        assert!(!hs.keys().contains(&row_to_delete));
+    #[test]
+    fn index_ids_and_counts() {
+        let mut hs = HashSync::new();
+        let index = hs.index(|&(a, _b)| a);
+        let ids = hs.insert_batch(vec![(1, 1), (2, 2), (1, 3)]).unwrap();
+
+        assert_eq!(index.get_ids(&1), vec![ids[0], ids[2]]);
+        assert_eq!(index.count(&1), 2);
+        assert!(index.contains_key(&2));
+
+        hs.delete(ids[1]);
+        assert_eq!(index.count(&2), 0);
+        assert!(!index.contains_key(&2));
+        assert!(index.get_ids(&3).is_empty());
     }
 
     #[test]
-    fn by_id() {
+    fn result_version_tracks_key_changes() {
         let mut hs = HashSync::new();
-        let row1 = hs.insert((1, 2));
-        let row2 = hs.insert((1, 3));
-        let row3 = hs.insert((3, 4));
+        let index = hs.index(|&(a, _b)| a);
+        assert_eq!(index.result_version(&1), 0);
 
-        assert_eq!(hs.by_id(row1), Some((1, 2)));
-        assert_eq!(hs.by_id(row2), Some((1, 3)));
-        assert_eq!(hs.by_id(row3), Some((3, 4)));
+        let id = hs.insert((1, 1));
+        let inserted = index.result_version(&1);
+        hs.insert((2, 2));
+        assert_eq!(index.result_version(&1), inserted);
+
+        hs.replace(id, (1, 5));
+        let replaced = index.result_version(&1);
+        assert_ne!(replaced, inserted);
+
+        hs.replace(id, (3, 5));
+        assert_eq!(index.result_version(&1), 0);
+        hs.replace(id, (1, 5));
+        assert_ne!(index.result_version(&1), replaced);
+        assert_ne!(index.result_version(&1), 0);
     }
 
     #[test]
-    fn by_id_indexed() {
+    fn result_version_is_per_key_despite_hash_collisions() {
+        #[derive(PartialEq, Eq)]
+        struct Colliding(i32);
+        impl Hash for Colliding {
+            fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+                0.hash(state);
+            }
+        }
+
         let mut hs = HashSync::new();
-        let row1 = hs.insert((1, 2));
-        let row2 = hs.insert((1, 3));
-        let row3 = hs.insert((3, 4));
+        let index = hs.index(|&(a, _b)| Colliding(a));
+        let id1 = hs.insert((1, 1));
+        hs.insert((2, 2));
+        let second = index.result_version(&Colliding(2));
 
-        assert_eq!(hs.by_id_indexed(row1), Some(Indexed::new(row1, (1, 2))));
-        assert_eq!(hs.by_id_indexed(row2), Some(Indexed::new(row2, (1, 3))));
-        assert_eq!(hs.by_id_indexed(row3), Some(Indexed::new(row3, (3, 4))));
+        hs.delete(id1);
+        assert_eq!(index.result_version(&Colliding(1)), 0);
+        assert_eq!(index.result_version(&Colliding(2)), second);
     }
 
     #[test]
-    fn delete() {
+    fn prefix_index_starts_with() {
         let mut hs = HashSync::new();
-        let row_to_delete = hs.insert((1, 2));
-        hs.insert((1, 3));
-        hs.insert((3, 4));
-        let index = hs.index(|&(a, _b)| a);
+        let names = hs.prefix_index(|row: &(String, u32)| row.0.clone());
+        hs.insert_batch(vec![
+            ("abby".to_string(), 1),
+            ("abe".to_string(), 2),
+            ("ada".to_string(), 3),
+            ("ab".to_string(), 4),
+        ])
+        .unwrap();
 
-        hs.delete(row_to_delete);
-        let rows = index.get_values(&1);
-        assert_eq!(rows.len(), 1);
-        assert!(rows.contains(&(1, 3)));
+        let ab: Vec<u32> = names
+            .starts_with_values("ab")
+            .into_iter()
+            .map(|row| row.1)
+            .collect();
+        assert_eq!(ab, vec![4, 1, 2]);
+        assert_eq!(names.starts_with("a").len(), 4);
+        assert!(names.starts_with("b").is_empty());
+        assert_eq!(names.starts_with("").len(), 4);
     }
 
     #[test]
-    fn two_indexes() {
+    fn ttl_rows_hidden_then_swept() {
         let mut hs = HashSync::new();
-        hs.insert((1, 2));
-        hs.insert((1, 3));
-        hs.insert((3, 2));
-        let index1 = hs.index(|&(a, _b)| a);
-        let index2 = hs.index(|&(_a, b)| b);
+        let index = hs.index(|&(a, _b)| a);
+        let kept = hs.insert((1, 1));
+        let expiring = hs.insert_with_ttl((1, 2), Duration::from_millis(20));
+        assert_eq!(index.get(&1).len(), 2);
 
-        let rows1 = index1.get_values(&1);
-        assert_eq!(rows1.len(), 2);
-        assert!(rows1.contains(&(1, 2)));
-        assert!(rows1.contains(&(1, 3)));
+        std::thread::sleep(Duration::from_millis(30));
+        assert_eq!(index.get_values(&1), vec![(1, 1)]);
+        assert_eq!(index.count(&1), 1);
+        assert_eq!(index.get_ids(&1), vec![kept]);
+        assert_eq!(hs.by_id(expiring), None);
+        assert_eq!(hs.keys(), vec![kept]);
 
-        let rows2 = index2.get_values(&2);
-        assert_eq!(rows2.len(), 2);
-        assert!(rows2.contains(&(1, 2)));
-        assert!(rows2.contains(&(3, 2)));
+        hs.expire_stale();
+        assert_eq!(hs.keys(), vec![kept]);
+        assert_eq!(hs.by_id(expiring), None);
+        assert_eq!(index.count(&1), 1);
+        hs.verify_indexes().unwrap();
     }
 
     #[test]
-    fn two_indexes_with_delete() {
+    fn replace_revives_expired_row() {
         let mut hs = HashSync::new();
-        hs.insert((1, 2));
-        let row_to_delete = hs.insert((1, 3));
-        hs.insert((3, 2));
-        let index1 = hs.index(|&(a, _b)| a);
-        let index2 = hs.index(|&(_a, b)| b);
+        let index = hs.index(|&(a, _b)| a);
+        let id = hs.insert_with_ttl((1, 1), Duration::from_millis(20));
+        std::thread::sleep(Duration::from_millis(30));
 
-        let rows1 = index1.get_values(&1);
-        assert_eq!(rows1.len(), 2);
-        assert!(rows1.contains(&(1, 2)));
-        assert!(rows1.contains(&(1, 3)));
+        hs.replace(id, (2, 2));
+        hs.verify_indexes().unwrap();
+        assert!(index.get(&1).is_empty());
 
-        let rows2 = index2.get_values(&2);
-        assert_eq!(rows2.len(), 2);
-        assert!(rows2.contains(&(1, 2)));
-        assert!(rows2.contains(&(3, 2)));
+        hs.expire_stale();
+        assert_eq!(hs.by_id(id), Some((2, 2)));
+        assert_eq!(index.get_ids(&2), vec![id]);
+        hs.verify_indexes().unwrap();
+    }
 
-        hs.delete(row_to_delete);
+    #[test]
+    fn ttl_rows_hidden_from_every_handle() {
+        let mut hs: HashSync<(&str, u32)> = HashSync::new();
+        let names = hs.unique_index(|&(name, _score)| name).unwrap();
+        let scores = hs.sorted_index(|&(_name, score)| score);
+        let ranked = hs.ranked_index(|&(name, score)| vec![(name.len(), score)]);
+        let by_length = hs.index(|&(name, _score)| name.len());
+        hs.insert(("alice", 1));
+        let bob = hs.insert_with_ttl(("bobby", 2), Duration::from_millis(20));
+        let query = by_length.ids(&5);
 
-        let rows1 = index1.get_values(&1);
-        assert_eq!(rows1.len(), 1);
-        assert!(rows1.contains(&(1, 2)));
+        std::thread::sleep(Duration::from_millis(30));
+        assert!(!names.contains_key(&"bobby"));
+        assert_eq!(scores.keys(), vec![1]);
+        assert_eq!(
+            scores.last().map(|row| row.into_value()),
+            Some(("alice", 1))
+        );
+        assert_eq!(ranked.get_ranked_values(&5), vec![("alice", 1)]);
+        assert_eq!(ranked.score(&5, bob), None);
+        assert_eq!(query.fetch_values(), vec![("alice", 1)]);
+        assert_eq!(by_length.id_groups().concat().len(), 1);
+        assert_eq!(hs.reader().by_id(bob), None);
+        assert_eq!(hs.reader().snapshot().len(), 1);
+        assert!(hs.handle(bob).is_none());
 
-        let rows2 = index2.get_values(&2);
-        assert_eq!(rows2.len(), 2);
-        assert!(rows2.contains(&(1, 2)));
-        assert!(rows2.contains(&(3, 2)));
+        assert_eq!(names.key_count(), 2);
+        hs.expire_stale();
+        assert_eq!(names.key_count(), 1);
+        assert_eq!(by_length.key_count(), 1);
     }
 
     #[test]
-    fn replace() {
+    fn lru_evicts_least_recently_used() {
+        let mut hs = HashSync::with_capacity_policy(Eviction::Lru(2));
+        let index = hs.index(|&(a, _b)| a);
+        let first = hs.insert((1, 1));
+        let second = hs.insert((2, 2));
+
+        assert_eq!(hs.by_id(first), Some((1, 1)));
+        let third = hs.insert((3, 3));
+        assert_eq!(hs.keys().len(), 2);
+        assert_eq!(hs.by_id(second), None);
+        assert!(index.get(&2).is_empty());
+
+        assert_eq!(index.get_values(&1), vec![(1, 1)]);
+        hs.insert((4, 4));
+        assert_eq!(hs.by_id(third), None);
+        assert_eq!(hs.by_id(first), Some((1, 1)));
+
+        hs.insert_batch(vec![(5, 5), (6, 6)]).unwrap();
+        assert_eq!(hs.keys().len(), 2);
+        assert_eq!(hs.by_id(first), None);
+        hs.verify_indexes().unwrap();
+    }
+
+    #[test]
+    fn lru_counts_reads_through_every_index() {
+        let mut hs = HashSync::with_capacity_policy(Eviction::Lru(2));
+        let sorted = hs.sorted_index(|&(a, _b)| a);
+        let unique = hs.unique_index(|&(_a, b)| b).unwrap();
+        let covering = hs.covering_index(|&(a, b)| vec![(a, b)]);
+        let ranked = hs.ranked_index(|&(a, b)| vec![(a, b)]);
+        let expiring = hs.expiring_index(Duration::from_secs(60), |&(a, _b)| vec![a]);
+        let first = hs.insert((1, 1));
+        hs.insert((2, 2));
+
+        // Each read keeps the first row most recent, so every insert evicts
+        // the row added just before it
+        let reads: [&dyn Fn(); 5] = [
+            &|| assert_eq!(sorted.range(1..2).len(), 1),
+            &|| assert!(unique.get_one(&1).is_some()),
+            &|| assert_eq!(covering.get_payloads(&1).len(), 1),
+            &|| assert_eq!(ranked.get_ranked(&1).len(), 1),
+            &|| assert_eq!(expiring.get(&1).len(), 1),
+        ];
+        for (i, read) in reads.iter().enumerate() {
+            read();
+            hs.insert((i as i32 + 3, i as i32 + 3));
+            assert_eq!(hs.by_id_consistent(first), Some((1, 1)));
+            assert_eq!(hs.keys().len(), 2);
+        }
+    }
+
+    #[test]
+    fn handles_reject_reused_ids() {
         let mut hs = HashSync::new();
-        let row_to_replace = hs.insert((1, 2));
-        hs.insert((1, 3));
-        hs.insert((3, 2));
-        let index1 = hs.index(|&(a, _b)| a);
-        let index2 = hs.index(|&(_a, b)| b);
+        let id = hs.insert((1, 1));
+        let handle = hs.handle(id).unwrap();
+        assert_eq!(hs.by_handle(handle), Some((1, 1)));
 
-        hs.replace(row_to_replace, (1, 4));
+        hs.replace(id, (1, 2));
+        assert_eq!(hs.by_handle(handle), Some((1, 2)));
 
-        let rows1 = index1.get_values(&1);
-        assert_eq!(rows1.len(), 2);
-        assert!(rows1.contains(&(1, 3)));
-        assert!(rows1.contains(&(1, 4)));
+        hs.delete(id);
+        assert!(hs.handle(id).is_none());
+        hs.replace(id, (9, 9));
+        assert!(!hs.is_current(handle));
+        assert_eq!(hs.by_handle(handle), None);
 
-        let rows1_keys = index1.get(&1).iter().map(|i| i.id()).collect::<Vec<_>>();
-        assert_eq!(rows1_keys.len(), 2);
-        assert!(rows1_keys.contains(&row_to_replace));
-        assert!(rows1_keys.contains(&row_to_replace.next()));
+        let reused = hs.handle(id).unwrap();
+        assert_eq!(reused.generation(), handle.generation() + 1);
+        assert_eq!(hs.by_handle(reused), Some((9, 9)));
+    }
 
-        let rows2 = index2.get_values(&2);
-        assert_eq!(rows2.len(), 1);
-        assert!(rows2.contains(&(3, 2)));
+    #[test]
+    fn merge_rows_keeps_survivor_handle() {
+        let mut hs = HashSync::new();
+        let survivor = hs.insert((1, 2));
+        let absorbed = hs.insert((1, 3));
+        let index = hs.index(|&(a, _b)| a);
+        let survivor_handle = hs.handle(survivor).unwrap();
+        let absorbed_handle = hs.handle(absorbed).unwrap();
+
+        let merged = hs
+            .merge_rows(&[absorbed, survivor], |rows| {
+                (rows[0].0, rows.iter().map(|row| row.1).sum())
+            })
+            .unwrap();
+        assert_eq!(merged, Some(survivor));
+        assert_eq!(hs.by_handle(survivor_handle), Some((1, 5)));
+        assert_eq!(hs.by_handle(absorbed_handle), None);
+        assert_eq!(index.get_ids(&1), vec![survivor]);
+        hs.verify_indexes().unwrap();
     }
 
     #[test]
-    fn index_many() {
+    fn drop_named_index() {
         let mut hs = HashSync::new();
+        let by_a = hs.index_named("by_a", |&(a, _b)| a).unwrap();
+        let by_b = hs.index(|&(_a, b)| b);
+        assert!(hs.index_named("by_a", |&(a, _b)| a).is_err());
         hs.insert((1, 2));
+
+        let reader = by_a.reader();
+        assert_eq!(reader.get_values(&1), vec![(1, 2)]);
+        assert!(hs.drop_index(by_a));
+
+        assert!(!reader.is_live());
+        assert!(reader.get(&1).is_empty());
+        assert_eq!(hs.describe().indexes().len(), 1);
         hs.insert((1, 3));
-        hs.insert((3, 1));
-        let index = hs.index_many(|&(a, b)| vec![a, b]);
+        assert_eq!(by_b.get(&3).len(), 1);
+        hs.verify_indexes().unwrap();
 
-        let rows1 = index.get_values(&1);
-        assert_eq!(rows1.len(), 3);
-        assert!(rows1.contains(&(1, 2)));
-        assert!(rows1.contains(&(1, 3)));
-        assert!(rows1.contains(&(3, 1)));
+        let by_a = hs.index_named("by_a", |&(a, _b)| a).unwrap();
+        assert_eq!(by_a.reader().get(&1).len(), 2);
 
-        let rows2 = index.get_values(&3);
-        assert_eq!(rows2.len(), 2);
-        assert!(rows2.contains(&(1, 3)));
-        assert!(rows2.contains(&(3, 1)));
+        // A same-named index elsewhere, or one re-created after drop_indexes,
+        // is a different index
+        let mut other = HashSync::new();
+        let foreign = other.index_named("by_a", |&(a, _b)| a).unwrap();
+        assert!(!hs.drop_index(foreign));
+        let mut hs = hs.drop_indexes();
+        let _fresh = hs.index_named("by_a", |&(a, _b)| a).unwrap();
+        assert!(!hs.drop_index(by_a));
+        assert_eq!(hs.describe().indexes().len(), 1);
     }
 
     #[test]
-    fn index_many_with_removal() {
+    fn bulk_load_indexes_once() {
         let mut hs = HashSync::new();
-        let row_to_delete = hs.insert((1, 2));
-        hs.insert((1, 3));
-        hs.insert((3, 1));
-        let index = hs.index_many(|&(a, b)| vec![a, b]);
+        let existing = hs.insert((0, 0));
+        let index = hs.index(|&(a, _b)| a % 2);
+        let version = hs.current_version();
 
-        hs.delete(row_to_delete);
+        let loaded = hs
+            .bulk_load(|loader| {
+                for i in 1..=4 {
+                    loader.insert((i, i));
+                }
+                assert!(index.get(&1).is_empty());
+                loader.len()
+            })
+            .unwrap();
 
-        let rows1 = index.get_values(&2);
-        assert_eq!(rows1.len(), 0);
+        assert_eq!(loaded, 4);
+        assert_eq!(hs.current_version(), version + 1);
+        assert_eq!(index.get(&0).len(), 3);
+        assert_eq!(existing, RowId::new(0));
+        assert_eq!(hs.insert((5, 5)), RowId::new(5));
+        hs.verify_indexes().unwrap();
+    }
 
-        let rows2 = index.get_values(&1);
-        assert_eq!(rows2.len(), 2);
-        assert!(rows2.contains(&(1, 3)));
-        assert!(rows2.contains(&(3, 1)));
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn par_bulk_load_rebuilds_every_index() {
+        let mut hs = HashSync::new();
+        let by_a = hs.index(|&(a, _b)| a);
+        let by_b = hs.index(|&(_a, b)| b);
+        hs.par_bulk_load(|loader| {
+            for i in 0..100 {
+                loader.insert((i % 10, i % 7));
+            }
+        })
+        .unwrap();
+        assert_eq!(by_a.get(&3).len(), 10);
+        assert_eq!(by_b.get(&0).len(), 15);
+        hs.verify_indexes().unwrap();
     }
 
+    #[cfg(feature = "rayon")]
     #[test]
-    fn replace_increases_max_id() {
+    fn parallel_scans() {
+        use rayon::prelude::*;
+
         let mut hs = HashSync::new();
-        hs.replace(RowId::new(5), (1, 4));
+        let index = hs.index(|&(a, _b)| a % 3);
+        hs.insert_batch((0..300).map(|i| (i, i * 2))).unwrap();
 
-        let row_id = hs.insert((1, 2));
-        assert_eq!(row_id, RowId::new(6));
+        let total: i32 = hs.par_iter().map(|row| row.value().1).sum();
+        assert_eq!(total, (0..300).map(|i| i * 2).sum::<i32>());
+        assert_eq!(index.par_get(&1).count(), 100);
+        assert!(index.par_get(&1).all(|row| row.value().0 % 3 == 1));
     }
 
     #[test]
-    fn index_keys() {
+    fn borrowed_reads_without_clone() {
+        #[derive(Debug, PartialEq)]
+        struct Big(Vec<u8>);
+
+        impl Clone for Big {
+            fn clone(&self) -> Self {
+                panic!("borrowed reads must not clone rows")
+            }
+        }
+
+        let mut hs: HashSync<Big> = HashSync::new();
+        let id = hs.insert(Big(vec![1, 2, 3]));
+        {
+            let row = hs.get_ref(id).unwrap();
+            assert_eq!(row.id(), id);
+            assert_eq!(row.0.len(), 3);
+        }
+        assert!(hs.get_ref(id.next()).is_none());
+    }
+
+    #[test]
+    fn index_for_each_borrows_rows() {
         let mut hs = HashSync::new();
-        hs.insert((1, 2));
-        hs.insert((1, 3));
-        hs.insert((3, 1));
         let index = hs.index(|&(a, _b)| a);
+        hs.insert_batch(vec![(1, 2), (1, 3), (2, 4)]).unwrap();
 
-        let keys = index.keys();
-        assert_eq!(keys.len(), 2);
-        assert!(keys.contains(&1));
-        assert!(keys.contains(&3));
+        let mut sum = 0;
+        index.for_each(&1, |_id, row| sum += row.1);
+        assert_eq!(sum, 5);
     }
 
     #[test]
-    fn index_keys_after_removal() {
+    fn index_key_count() {
         let mut hs = HashSync::new();
         let row_to_delete = hs.insert((1, 2));
+        hs.insert((1, 3));
         hs.insert((3, 1));
-        let index = hs.index(|&(a, _b)| a);
+        let index = hs.index(|&(a, b)| a + b);
+        assert_eq!(index.key_count(), 2);
 
         hs.delete(row_to_delete);
+        assert_eq!(index.key_count(), 1);
+    }
 
-        let keys = index.keys();
-        assert_eq!(keys.len(), 1);
-        assert!(keys.contains(&3));
+    #[test]
+    fn index_for_each_key() {
+        let mut hs = HashSync::new();
+        hs.insert((1, 2));
+        hs.insert((1, 3));
+        hs.insert((3, 1));
+        let index = hs.index(|&(a, _b)| a);
+
+        let mut sum = 0;
+        let mut count = 0;
+        index.for_each_key(|key| {
+            sum += key;
+            count += 1;
+        });
+        assert_eq!(count, 2);
+        assert_eq!(sum, 4);
     }
 
     #[test]
@@ -401,4 +2927,69 @@ mod tests {
         assert_eq!(hs.by_id(id2), Some((1, 3)));
         assert_eq!(hs.by_id(id3), Some((3, 1)));
     }
+
+    #[test]
+    fn drop_indexes_invalidates_handles() {
+        let mut hs = HashSync::new();
+        hs.insert((1, 2));
+        let stale = hs.index(|&(a, _b)| a);
+        assert!(stale.is_live());
+
+        let mut hs = hs.drop_indexes();
+        hs.insert((1, 3));
+        let fresh = hs.index(|&(a, _b)| a);
+
+        assert!(!stale.is_live());
+        assert!(stale.get(&1).is_empty());
+        let err = stale.try_get(&1).unwrap_err();
+        assert_eq!((err.generation(), err.current()), (0, 1));
+        assert!(hs.delete_by(&stale, &1).is_empty());
+
+        assert!(fresh.is_live());
+        assert_eq!(fresh.try_get(&1).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn drop_indexes_invalidates_every_accessor() {
+        let mut hs = HashSync::new();
+        hs.insert((1, 2));
+        let index = hs.index(|&(a, _b)| a);
+        let unique = hs.unique_index(|&(_a, b)| b).unwrap();
+        let sorted = hs.sorted_index(|&(a, _b)| a);
+        let covering = hs.covering_index(|&(a, b)| vec![(a, b)]);
+        let ranked = hs.ranked_index(|&(a, b)| vec![(a, b)]);
+        let expiring = hs.expiring_index(Duration::from_secs(60), |&(a, _b)| vec![a]);
+        let aggregate = hs.aggregate_index(|&(a, _b)| a, |&(_a, b)| b);
+        let timestamps = hs.enable_timestamps();
+        let row_id = hs.insert((3, 4));
+        assert!(aggregate.is_live() && timestamps.is_live());
+
+        let hs = hs.drop_indexes();
+        assert_eq!(hs.keys().len(), 2);
+        assert!(index.keys().is_empty());
+        assert_eq!(index.key_count(), 0);
+        assert!(index.id_groups().is_empty());
+        assert_eq!(index.canonical_dump(), "");
+        assert_eq!(index.estimated_bytes(), 0);
+        assert!(index.subscribe(&1).recv().is_err());
+
+        assert!(!unique.is_live());
+        assert_eq!(unique.get_id(&2), None);
+        assert!(sorted.keys().is_empty());
+        assert_eq!(sorted.first(), None);
+        assert!(covering.get_payloads(&1).is_empty());
+        assert!(ranked.get_ranked(&1).is_empty());
+        assert!(expiring.get(&1).is_empty());
+        assert!(!aggregate.is_live());
+        assert_eq!(aggregate.get(&1), None);
+        assert_eq!(aggregate.key_count(), 0);
+
+        // Timestamps are internal, so they keep stamping after the drop
+        let mut hs = hs;
+        let later = hs.insert((5, 6));
+        assert!(timestamps.is_live());
+        let stamped = timestamps.timestamp(row_id).unwrap();
+        assert!(stamped < timestamps.timestamp(later).unwrap());
+        hs.verify_indexes().unwrap();
+    }
 }
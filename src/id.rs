@@ -1,4 +1,7 @@
+use fxhash::FxHashMap;
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct RowId(usize);
 
 impl RowId {
@@ -9,9 +12,34 @@ impl RowId {
     pub fn next(&self) -> Self {
         RowId(self.0 + 1)
     }
+
+    pub fn prev(&self) -> Option<Self> {
+        self.0.checked_sub(1).map(RowId)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct RowHandle {
+    id: RowId,
+    generation: u64,
+}
+
+impl RowHandle {
+    pub fn new(id: RowId, generation: u64) -> Self {
+        RowHandle { id, generation }
+    }
+
+    pub fn id(&self) -> RowId {
+        self.id
+    }
+
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Indexed<T> {
     id: RowId,
     value: T,
@@ -34,3 +62,34 @@ impl<T> Indexed<T> {
         self.value
     }
 }
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct IdMapping {
+    ids: FxHashMap<RowId, RowId>,
+}
+
+impl IdMapping {
+    pub fn new() -> Self {
+        IdMapping::default()
+    }
+
+    pub fn insert(&mut self, old: RowId, new: RowId) {
+        self.ids.insert(old, new);
+    }
+
+    pub fn get(&self, old: RowId) -> Option<RowId> {
+        self.ids.get(&old).copied()
+    }
+
+    pub fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ids.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (RowId, RowId)> + '_ {
+        self.ids.iter().map(|(old, new)| (*old, *new))
+    }
+}
@@ -1,12 +1,26 @@
 use std::{
+    fmt::{Debug, Write},
     hash::Hash,
-    sync::{Arc, RwLock},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        mpsc::{self, Receiver, Sender},
+        Arc, RwLock,
+    },
+    time::Instant,
 };
 
 use dashmap::DashMap;
-use fxhash::{FxHashMap, FxHashSet};
+use fxhash::{hash64, FxHashMap, FxHashSet};
 
-use crate::id::{Indexed, RowId};
+use crate::{
+    deadline::Deadlines,
+    describe::IndexDescription,
+    error::{MultipleMatches, StaleIndex},
+    eviction::LruTracker,
+    id::{Indexed, RowId},
+    query::IdSet,
+    stamp::Stamp,
+};
 
 pub struct IndexId(usize);
 
@@ -23,13 +37,199 @@ impl IndexId {
 pub trait Indexable<ValueT> {
     fn insert(&mut self, row: &Indexed<ValueT>) -> IndexId;
     fn delete(&mut self, row: &Indexed<ValueT>);
+    fn sweep(&mut self, _now: Instant) {}
+
+    fn verify(&self, _rows: &[Indexed<ValueT>]) -> (Vec<RowId>, Vec<RowId>) {
+        (Vec::new(), Vec::new())
+    }
+
+    fn describe(&self) -> IndexDescription {
+        IndexDescription::new("custom")
+    }
+
+    // Entries in a deterministic order, for canonical dumps of the store
+    fn dump_entries(&self) -> Vec<(String, Vec<RowId>)> {
+        Vec::new()
+    }
+
+    fn insert_many(&mut self, rows: &[Indexed<ValueT>]) {
+        for row in rows {
+            self.insert(row);
+        }
+    }
+
+    fn delete_many(&mut self, rows: &[Indexed<ValueT>]) {
+        for row in rows {
+            self.delete(row);
+        }
+    }
+
+    fn update(&mut self, old: &Indexed<ValueT>, new: &Indexed<ValueT>) {
+        self.delete(old);
+        self.insert(new);
+    }
+
+    fn update_many(&mut self, rows: &[(Indexed<ValueT>, Indexed<ValueT>)]) {
+        for (old, new) in rows {
+            self.update(old, new);
+        }
+    }
+}
+
+// Keys need not implement Debug, so dumps label each one by a stable hash of
+// its value and sort by that label
+pub(crate) fn hashed_entries<'k, KeyT: Hash + 'k>(
+    entries: impl Iterator<Item = (&'k KeyT, Vec<RowId>)>,
+) -> Vec<(String, Vec<RowId>)> {
+    let mut dump: Vec<(String, Vec<RowId>)> = entries
+        .map(|(key, mut ids)| {
+            ids.sort();
+            (format!("{:016x}", hash64(key)), ids)
+        })
+        .collect();
+    dump.sort();
+    dump
+}
+
+pub(crate) fn verify_entries<'k, KeyT: PartialEq + 'k>(
+    expected: FxHashMap<RowId, Vec<KeyT>>,
+    entries: impl Iterator<Item = (&'k KeyT, RowId)>,
+) -> (Vec<RowId>, Vec<RowId>) {
+    let mut actual: FxHashMap<RowId, Vec<&KeyT>> = FxHashMap::default();
+    for (key, id) in entries {
+        actual.entry(id).or_default().push(key);
+    }
+
+    let mut leaked: Vec<RowId> = actual
+        .iter()
+        .filter(|(id, keys)| {
+            let expected_keys = expected.get(id);
+            keys.iter()
+                .any(|key| !expected_keys.is_some_and(|expected_keys| expected_keys.contains(key)))
+        })
+        .map(|(id, _keys)| *id)
+        .collect();
+    let mut missing: Vec<RowId> = expected
+        .iter()
+        .filter(|(id, keys)| {
+            let actual_keys = actual.get(id);
+            keys.iter()
+                .any(|key| !actual_keys.is_some_and(|actual_keys| actual_keys.contains(&key)))
+        })
+        .map(|(id, _keys)| *id)
+        .collect();
+
+    leaked.sort();
+    missing.sort();
+    (leaked, missing)
+}
+
+pub type IndexFunction<KeyT, ValueT> = Box<dyn Fn(&Indexed<ValueT>) -> Vec<KeyT> + Send + Sync>;
+
+// Each key carries the version of its own last change, so keys whose hashes
+// collide never share or reset each other's version
+#[derive(Default)]
+struct KeyEntry {
+    row_ids: FxHashSet<RowId>,
+    version: u64,
 }
 
-pub type IndexFunction<KeyT, ValueT> = Box<dyn Fn(&Indexed<ValueT>) -> Vec<KeyT>>;
+struct KeyTracker<KeyT> {
+    clone_key: fn(&KeyT) -> KeyT,
+    keys: FxHashMap<RowId, Vec<KeyT>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChangeEvent<ValueT> {
+    Inserted(Indexed<ValueT>),
+    Deleted(Indexed<ValueT>),
+    Replaced {
+        old: Indexed<ValueT>,
+        new: Indexed<ValueT>,
+    },
+}
+
+enum Change<'r, ValueT> {
+    Inserted(&'r Indexed<ValueT>),
+    Deleted(&'r Indexed<ValueT>),
+    Replaced(&'r Indexed<ValueT>, &'r Indexed<ValueT>),
+}
+
+// Subscribers sit behind a trait object so that only subscribe() needs the
+// Clone and Send bounds required to hand out owned events
+trait Notify<KeyT, ValueT> {
+    fn subscribe(&mut self, key: KeyT) -> Receiver<ChangeEvent<ValueT>>;
+    fn notify(&mut self, key: &KeyT, change: Change<'_, ValueT>);
+    fn clone_key(&self, key: &KeyT) -> KeyT;
+}
+
+struct Subscribers<KeyT, ValueT> {
+    senders: FxHashMap<KeyT, Vec<Sender<ChangeEvent<ValueT>>>>,
+}
+
+impl<KeyT: PartialEq + Eq + Hash + Clone, ValueT: Clone> Notify<KeyT, ValueT>
+    for Subscribers<KeyT, ValueT>
+{
+    fn subscribe(&mut self, key: KeyT) -> Receiver<ChangeEvent<ValueT>> {
+        let (sender, receiver) = mpsc::channel();
+        self.senders.entry(key).or_default().push(sender);
+        receiver
+    }
+
+    fn notify(&mut self, key: &KeyT, change: Change<'_, ValueT>) {
+        if let Some(senders) = self.senders.get_mut(key) {
+            let event = match change {
+                Change::Inserted(row) => ChangeEvent::Inserted(row.clone()),
+                Change::Deleted(row) => ChangeEvent::Deleted(row.clone()),
+                Change::Replaced(old, new) => ChangeEvent::Replaced {
+                    old: old.clone(),
+                    new: new.clone(),
+                },
+            };
+            // Dropped receivers are pruned the next time their key changes
+            senders.retain(|sender| sender.send(event.clone()).is_ok());
+            if senders.is_empty() {
+                self.senders.remove(key);
+            }
+        }
+    }
+
+    fn clone_key(&self, key: &KeyT) -> KeyT {
+        key.clone()
+    }
+}
+
+struct MemoryBudget {
+    bytes: usize,
+    on_exceeded: Box<dyn Fn(usize) + Send + Sync>,
+    exceeded: bool,
+}
 
 pub struct Index<KeyT, ValueT> {
     index_function: IndexFunction<KeyT, ValueT>,
-    index: FxHashMap<KeyT, FxHashSet<RowId>>,
+    index: FxHashMap<KeyT, KeyEntry>,
+    tracker: Option<KeyTracker<KeyT>>,
+    entry_count: usize,
+    budget: Option<MemoryBudget>,
+    subscribers: Option<Box<dyn Notify<KeyT, ValueT> + Send + Sync>>,
+    change_count: u64,
+}
+
+impl<KeyT: PartialEq + Eq + Hash + Clone, ValueT: Clone> Index<KeyT, ValueT> {
+    pub fn new_tracked(index_function: IndexFunction<KeyT, ValueT>) -> Self {
+        Index {
+            index_function,
+            index: FxHashMap::default(),
+            tracker: Some(KeyTracker {
+                clone_key: KeyT::clone,
+                keys: FxHashMap::default(),
+            }),
+            entry_count: 0,
+            budget: None,
+            subscribers: None,
+            change_count: 0,
+        }
+    }
 }
 
 impl<KeyT: PartialEq + Eq + Hash, ValueT: Clone> Index<KeyT, ValueT> {
@@ -37,99 +237,713 @@ impl<KeyT: PartialEq + Eq + Hash, ValueT: Clone> Index<KeyT, ValueT> {
         Index {
             index_function,
             index: FxHashMap::default(),
+            tracker: None,
+            entry_count: 0,
+            budget: None,
+            subscribers: None,
+            change_count: 0,
         }
     }
 
     pub fn get(&self, key: &KeyT) -> FxHashSet<RowId> {
-        self.index.get(key).cloned().unwrap_or_default()
+        self.index
+            .get(key)
+            .map(|entry| entry.row_ids.clone())
+            .unwrap_or_default()
+    }
+
+    pub fn get_ref(&self, key: &KeyT) -> Option<&FxHashSet<RowId>> {
+        self.index.get(key).map(|entry| &entry.row_ids)
     }
 
     pub fn keys(&self) -> Vec<&KeyT> {
         self.index.keys().collect()
     }
 
+    pub fn for_each_key<F: FnMut(&KeyT)>(&self, f: F) {
+        self.index.keys().for_each(f);
+    }
+
+    pub fn key_count(&self) -> usize {
+        self.index.len()
+    }
+
+    pub fn id_groups(&self) -> Vec<Vec<RowId>> {
+        self.index
+            .values()
+            .map(|entry| entry.row_ids.iter().copied().collect())
+            .collect()
+    }
+
+    pub fn set_memory_budget(
+        &mut self,
+        bytes: usize,
+        on_exceeded: Box<dyn Fn(usize) + Send + Sync>,
+    ) {
+        self.budget = Some(MemoryBudget {
+            bytes,
+            on_exceeded,
+            exceeded: false,
+        });
+        self.check_memory_budget();
+    }
+
     pub fn into_read_write(
         self,
         rows: Arc<DashMap<RowId, ValueT>>,
-    ) -> (IndexRead<KeyT, ValueT>, IndexWrite<KeyT, ValueT>) {
+        version: Arc<AtomicU64>,
+    ) -> (IndexRead<KeyT, ValueT>, IndexWrite<Self>) {
         let index = Arc::new(RwLock::new(self));
-        (IndexRead::new(rows, index.clone()), IndexWrite::new(index))
+        (
+            IndexRead::new(rows, index.clone(), version),
+            IndexWrite::new(index),
+        )
+    }
+}
+
+impl<KeyT, ValueT> Index<KeyT, ValueT> {
+    // Shallow estimate: heap data owned by the keys themselves is not counted
+    pub fn estimated_bytes(&self) -> usize {
+        let key_bytes = size_of::<KeyT>() + size_of::<FxHashSet<RowId>>();
+        let tracked_bytes = match self.tracker.as_ref() {
+            Some(tracker) => {
+                tracker.keys.len() * (size_of::<RowId>() + size_of::<Vec<KeyT>>())
+                    + self.entry_count * size_of::<KeyT>()
+            }
+            None => 0,
+        };
+        self.index.len() * key_bytes + self.entry_count * size_of::<RowId>() + tracked_bytes
+    }
+
+    fn check_memory_budget(&mut self) {
+        let estimated_bytes = self.estimated_bytes();
+        if let Some(budget) = self.budget.as_mut() {
+            if estimated_bytes <= budget.bytes {
+                budget.exceeded = false;
+            } else if !budget.exceeded {
+                budget.exceeded = true;
+                (budget.on_exceeded)(estimated_bytes);
+            }
+        }
+    }
+}
+
+impl<KeyT, ValueT> Index<KeyT, ValueT>
+where
+    KeyT: PartialEq + Eq + Hash + Clone + Send + Sync + 'static,
+    ValueT: Clone + Send + 'static,
+{
+    pub fn subscribe(&mut self, key: KeyT) -> Receiver<ChangeEvent<ValueT>> {
+        self.subscribers
+            .get_or_insert_with(|| {
+                Box::new(Subscribers {
+                    senders: FxHashMap::default(),
+                })
+            })
+            .subscribe(key)
+    }
+}
+
+impl<KeyT: PartialEq + Eq + Hash, ValueT> Index<KeyT, ValueT> {
+    pub fn result_version(&self, key: &KeyT) -> u64 {
+        self.index.get(key).map_or(0, |entry| entry.version)
+    }
+
+    fn touch(&mut self, key: &KeyT) {
+        // Versions come from one counter shared by every key, so a key that
+        // empties and refills never repeats an earlier version
+        self.change_count += 1;
+        if let Some(entry) = self.index.get_mut(key) {
+            entry.version = self.change_count;
+        }
+    }
+
+    fn notify(&mut self, key: &KeyT, change: Change<'_, ValueT>) {
+        if let Some(subscribers) = self.subscribers.as_mut() {
+            subscribers.notify(key, change);
+        }
+    }
+
+    fn insert_keys(&mut self, id: RowId, keys: Vec<KeyT>) {
+        if let Some(tracker) = self.tracker.as_mut() {
+            let tracked = keys.iter().map(tracker.clone_key).collect();
+            tracker.keys.insert(id, tracked);
+        }
+        for key in keys {
+            self.change_count += 1;
+            let entry = self.index.entry(key).or_default();
+            if entry.row_ids.insert(id) {
+                self.entry_count += 1;
+            }
+            entry.version = self.change_count;
+        }
+        self.check_memory_budget();
     }
 }
 
 impl<KeyT: PartialEq + Eq + Hash, ValueT> Indexable<ValueT> for Index<KeyT, ValueT> {
     fn insert(&mut self, row: &Indexed<ValueT>) -> IndexId {
         let keys = (self.index_function)(row);
-        for key in keys {
-            self.index.entry(key).or_default().insert(row.id());
+        for key in keys.iter() {
+            self.notify(key, Change::Inserted(row));
         }
+        self.insert_keys(row.id(), keys);
         IndexId::new(0)
     }
 
     fn delete(&mut self, row: &Indexed<ValueT>) {
-        let keys = (self.index_function)(row);
+        // Tracked indexes delete the keys recorded at insert time instead of
+        // re-running the index function on the old value
+        let keys = match self.tracker.as_mut() {
+            Some(tracker) => tracker.keys.remove(&row.id()).unwrap_or_default(),
+            None => (self.index_function)(row),
+        };
         for key in keys {
-            if let Some(set) = self.index.get_mut(&key) {
-                set.remove(&row.id());
-                if set.is_empty() {
+            if let Some(entry) = self.index.get_mut(&key) {
+                if entry.row_ids.remove(&row.id()) {
+                    self.entry_count -= 1;
+                }
+                if entry.row_ids.is_empty() {
                     self.index.remove(&key);
                 }
             }
+            self.touch(&key);
+            self.notify(&key, Change::Deleted(row));
         }
+        self.check_memory_budget();
+    }
+
+    fn verify(&self, rows: &[Indexed<ValueT>]) -> (Vec<RowId>, Vec<RowId>) {
+        let expected = rows
+            .iter()
+            .map(|row| (row.id(), (self.index_function)(row)))
+            .collect();
+        let entries = self
+            .index
+            .iter()
+            .flat_map(|(key, entry)| entry.row_ids.iter().map(move |id| (key, *id)));
+        verify_entries(expected, entries)
+    }
+
+    fn dump_entries(&self) -> Vec<(String, Vec<RowId>)> {
+        hashed_entries(
+            self.index
+                .iter()
+                .map(|(key, entry)| (key, entry.row_ids.iter().copied().collect())),
+        )
+    }
+
+    fn update(&mut self, old: &Indexed<ValueT>, new: &Indexed<ValueT>) {
+        let new_keys = (self.index_function)(new);
+        let unchanged = match self.tracker.as_ref() {
+            Some(tracker) => tracker
+                .keys
+                .get(&old.id())
+                .is_some_and(|old_keys| *old_keys == new_keys),
+            None => (self.index_function)(old) == new_keys,
+        };
+        if unchanged {
+            for key in new_keys.iter() {
+                self.touch(key);
+                self.notify(key, Change::Replaced(old, new));
+            }
+            return;
+        }
+
+        let subscribers = match self.subscribers.take() {
+            Some(subscribers) => subscribers,
+            None => {
+                self.delete(old);
+                self.insert_keys(new.id(), new_keys);
+                return;
+            }
+        };
+        // Keys the row keeps see a single Replaced event instead of a
+        // Deleted followed by an Inserted
+        let old_keys: Vec<KeyT> = match self.tracker.as_ref() {
+            Some(tracker) => tracker
+                .keys
+                .get(&old.id())
+                .map(|keys| keys.iter().map(|key| subscribers.clone_key(key)).collect())
+                .unwrap_or_default(),
+            None => (self.index_function)(old),
+        };
+        let added: Vec<KeyT> = new_keys
+            .iter()
+            .filter(|key| !old_keys.contains(key))
+            .map(|key| subscribers.clone_key(key))
+            .collect();
+        self.delete(old);
+        let kept: Vec<bool> = old_keys.iter().map(|key| new_keys.contains(key)).collect();
+        self.insert_keys(new.id(), new_keys);
+        self.subscribers = Some(subscribers);
+
+        for (key, kept) in old_keys.iter().zip(kept) {
+            if kept {
+                self.notify(key, Change::Replaced(old, new));
+            } else {
+                self.notify(key, Change::Deleted(old));
+            }
+        }
+        for key in added.iter() {
+            self.notify(key, Change::Inserted(new));
+        }
+    }
+
+    fn describe(&self) -> IndexDescription {
+        let mut description = IndexDescription::new("hash")
+            .keyed_by::<KeyT>()
+            .with_option("tracked", self.tracker.is_some());
+        if let Some(budget) = self.budget.as_ref() {
+            description = description.with_option("memory_budget", budget.bytes);
+        }
+        description
     }
 }
 
 pub struct IndexRead<KeyT, ValueT> {
     rows: Arc<DashMap<RowId, ValueT>>,
     index: Arc<RwLock<Index<KeyT, ValueT>>>,
+    version: Arc<AtomicU64>,
+    stamp: Stamp,
+    deadlines: Deadlines,
+    lru: Option<Arc<LruTracker>>,
+    dropped: Arc<AtomicBool>,
+}
+
+impl<KeyT, ValueT> Clone for IndexRead<KeyT, ValueT> {
+    fn clone(&self) -> Self {
+        IndexRead {
+            rows: self.rows.clone(),
+            index: self.index.clone(),
+            version: self.version.clone(),
+            stamp: self.stamp.clone(),
+            deadlines: self.deadlines.clone(),
+            lru: self.lru.clone(),
+            dropped: self.dropped.clone(),
+        }
+    }
+}
+
+impl<KeyT: PartialEq + Eq + Hash, ValueT> IndexRead<KeyT, ValueT> {
+    pub fn is_live(&self) -> bool {
+        self.stamp.is_current() && !self.dropped.load(Ordering::SeqCst)
+    }
+
+    pub fn for_each<ForEachFn>(&self, key: &KeyT, mut for_each_fn: ForEachFn)
+    where
+        ForEachFn: FnMut(RowId, &ValueT),
+    {
+        // Collect the ids under the seqlock so a multi-row write can't hand the
+        // visitor a half-moved key, then visit without holding the index lock
+        let row_ids: Vec<RowId> = self.stamp.consistent(|| {
+            let index_guard = self.index.read().unwrap();
+            if !self.is_live() {
+                return Vec::new();
+            }
+            match index_guard.index.get(key) {
+                Some(entry) => entry.row_ids.iter().copied().collect(),
+                None => Vec::new(),
+            }
+        });
+        let now = Instant::now();
+        for id in row_ids {
+            if self.is_expired(id, now) {
+                continue;
+            }
+            if let Some(row) = self.rows.get(&id) {
+                if let Some(lru) = self.lru.as_ref() {
+                    lru.touch(id);
+                }
+                for_each_fn(id, row.value());
+            }
+        }
+    }
+
+    fn is_expired(&self, id: RowId, now: Instant) -> bool {
+        self.deadlines.is_expired(id, now)
+    }
+
+    fn live_ids<'g>(
+        &'g self,
+        row_ids: &'g FxHashSet<RowId>,
+        now: Instant,
+    ) -> impl Iterator<Item = RowId> + 'g {
+        row_ids
+            .iter()
+            .copied()
+            .filter(move |id| !self.is_expired(*id, now))
+    }
 }
 
 impl<KeyT: PartialEq + Eq + Hash, ValueT: Clone> IndexRead<KeyT, ValueT> {
-    pub fn new(rows: Arc<DashMap<RowId, ValueT>>, index: Arc<RwLock<Index<KeyT, ValueT>>>) -> Self {
-        IndexRead { rows, index }
+    pub fn new(
+        rows: Arc<DashMap<RowId, ValueT>>,
+        index: Arc<RwLock<Index<KeyT, ValueT>>>,
+        version: Arc<AtomicU64>,
+    ) -> Self {
+        IndexRead {
+            rows,
+            index,
+            version,
+            stamp: Stamp::default(),
+            deadlines: Deadlines::default(),
+            lru: None,
+            dropped: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    pub(crate) fn stamped(
+        self,
+        stamp: Stamp,
+        deadlines: Deadlines,
+        lru: Option<Arc<LruTracker>>,
+    ) -> Self {
+        IndexRead {
+            stamp,
+            deadlines,
+            lru,
+            ..self
+        }
+    }
+
+    fn hydrate(&self, id: RowId, now: Instant) -> Option<Indexed<ValueT>> {
+        if self.is_expired(id, now) {
+            return None;
+        }
+        let row = self
+            .rows
+            .get(&id)
+            .map(|value| Indexed::new(id, value.clone()));
+        if let (Some(lru), Some(_row)) = (self.lru.as_ref(), row.as_ref()) {
+            lru.touch(id);
+        }
+        row
+    }
+
+    pub(crate) fn shares_rows(&self, rows: &Arc<DashMap<RowId, ValueT>>) -> bool {
+        Arc::ptr_eq(&self.rows, rows)
+    }
+
+    pub(crate) fn mark_dropped(&self) {
+        self.dropped.store(true, Ordering::SeqCst);
     }
 
     pub fn get(&self, key: &KeyT) -> Vec<Indexed<ValueT>> {
-        let index_guard = self.index.read().unwrap();
+        self.stamp.consistent(|| {
+            let index_guard = self.index.read().unwrap();
+            self.get_locked(&index_guard, key)
+        })
+    }
+
+    pub fn try_get(&self, key: &KeyT) -> Result<Vec<Indexed<ValueT>>, StaleIndex> {
+        self.stamp.consistent(|| {
+            let index_guard = self.index.read().unwrap();
+            if !self.is_live() {
+                return Err(StaleIndex::new(self.stamp.stamp(), self.stamp.current()));
+            }
+            Ok(self.get_locked(&index_guard, key))
+        })
+    }
+
+    pub fn get_versioned(&self, key: &KeyT) -> (u64, Vec<Indexed<ValueT>>) {
+        self.stamp.consistent(|| {
+            let index_guard = self.index.read().unwrap();
+            // Writers bump the version after updating every index, so holding the
+            // index lock guarantees the result reflects at least this version
+            let version = self.version.load(Ordering::SeqCst);
+            (version, self.get_locked(&index_guard, key))
+        })
+    }
 
+    pub(crate) fn keys_of(&self, row: &Indexed<ValueT>) -> Vec<KeyT> {
+        (self.index.read().unwrap().index_function)(row)
+    }
+
+    pub fn get_ids(&self, key: &KeyT) -> Vec<RowId> {
+        self.stamp.consistent(|| {
+            let index_guard = self.index.read().unwrap();
+            if !self.is_live() {
+                return Vec::new();
+            }
+            let mut ids: Vec<RowId> = match index_guard.get_ref(key) {
+                Some(row_ids) => self.live_ids(row_ids, Instant::now()).collect(),
+                None => Vec::new(),
+            };
+            ids.sort();
+            ids
+        })
+    }
+
+    pub fn ids(&self, key: &KeyT) -> IdSet<ValueT> {
+        IdSet::new(self.rows.clone(), self.get_ids(key)).with_deadlines(self.deadlines.clone())
+    }
+
+    pub fn result_version(&self, key: &KeyT) -> u64 {
+        self.stamp.consistent(|| {
+            if !self.is_live() {
+                return 0;
+            }
+            self.index.read().unwrap().result_version(key)
+        })
+    }
+
+    pub fn count(&self, key: &KeyT) -> usize {
+        self.stamp.consistent(|| {
+            if !self.is_live() {
+                return 0;
+            }
+            let index_guard = self.index.read().unwrap();
+            index_guard
+                .get_ref(key)
+                .map_or(0, |ids| self.live_ids(ids, Instant::now()).count())
+        })
+    }
+
+    pub fn contains_key(&self, key: &KeyT) -> bool {
+        self.count(key) > 0
+    }
+
+    fn get_locked(&self, index_guard: &Index<KeyT, ValueT>, key: &KeyT) -> Vec<Indexed<ValueT>> {
+        if !self.is_live() {
+            return Vec::new();
+        }
+        let now = Instant::now();
         let row_ids = index_guard.get(key);
         row_ids
             .iter()
-            .filter_map(|id| {
-                let row = self.rows.get(id);
-                if let Some(value) = row {
-                    let value_clone = value.clone();
-                    return Some(Indexed::new(*id, value_clone));
-                }
-                None
-            })
+            .filter_map(|id| self.hydrate(*id, now))
             .collect()
     }
 
+    #[cfg(feature = "rayon")]
+    pub fn par_get(&self, key: &KeyT) -> impl rayon::iter::ParallelIterator<Item = Indexed<ValueT>>
+    where
+        KeyT: Send + Sync,
+        ValueT: Send + Sync,
+    {
+        use rayon::prelude::*;
+
+        let now = Instant::now();
+        let row_ids = self.get_ids(key);
+        let read = self.clone();
+        row_ids
+            .into_par_iter()
+            .filter_map(move |id| read.hydrate(id, now))
+    }
+
     pub fn get_values(&self, key: &KeyT) -> Vec<ValueT> {
         let indexed = self.get(key);
         indexed.into_iter().map(|i| i.value().clone()).collect()
     }
+
+    pub fn get_one(&self, key: &KeyT) -> Option<Indexed<ValueT>> {
+        self.stamp.consistent(|| {
+            let index_guard = self.index.read().unwrap();
+            if !self.is_live() {
+                return None;
+            }
+
+            let now = Instant::now();
+            let row_ids = index_guard.get_ref(key)?;
+            row_ids.iter().find_map(|id| self.hydrate(*id, now))
+        })
+    }
+
+    pub fn get_after(
+        &self,
+        key: &KeyT,
+        last_seen_id: Option<RowId>,
+        limit: usize,
+    ) -> Vec<Indexed<ValueT>> {
+        self.stamp.consistent(|| {
+            let index_guard = self.index.read().unwrap();
+            if !self.is_live() {
+                return Vec::new();
+            }
+
+            let mut row_ids: Vec<RowId> = match index_guard.get_ref(key) {
+                Some(row_ids) => row_ids
+                    .iter()
+                    .filter(|&&id| last_seen_id.is_none_or(|last| id > last))
+                    .copied()
+                    .collect(),
+                None => return Vec::new(),
+            };
+            row_ids.sort();
+            let now = Instant::now();
+            row_ids
+                .into_iter()
+                .filter_map(|id| self.hydrate(id, now))
+                .take(limit)
+                .collect()
+        })
+    }
+
+    pub fn get_unique(&self, key: &KeyT) -> Result<Option<Indexed<ValueT>>, MultipleMatches> {
+        let mut rows = self.get(key);
+        if rows.len() > 1 {
+            let mut ids: Vec<RowId> = rows.iter().map(|row| row.id()).collect();
+            ids.sort();
+            return Err(MultipleMatches::new(ids));
+        }
+        Ok(rows.pop())
+    }
+
+    pub fn for_each_key<F: FnMut(&KeyT)>(&self, mut f: F) {
+        let index_guard = self.index.read().unwrap();
+        if !self.is_live() {
+            return;
+        }
+        let now = Instant::now();
+        for (key, entry) in index_guard.index.iter() {
+            if self.live_ids(&entry.row_ids, now).next().is_some() {
+                f(key);
+            }
+        }
+    }
+
+    // Read straight off the map so it stays O(1); keys held only by rows
+    // past their TTL are counted until expire_stale sweeps them
+    pub fn key_count(&self) -> usize {
+        self.stamp.consistent(|| {
+            let index_guard = self.index.read().unwrap();
+            if !self.is_live() {
+                return 0;
+            }
+            index_guard.key_count()
+        })
+    }
+
+    pub fn id_groups(&self) -> Vec<Vec<RowId>> {
+        self.stamp.consistent(|| {
+            let index_guard = self.index.read().unwrap();
+            if !self.is_live() {
+                return Vec::new();
+            }
+            let now = Instant::now();
+            index_guard
+                .index
+                .values()
+                .map(|entry| self.live_ids(&entry.row_ids, now).collect::<Vec<RowId>>())
+                .filter(|row_ids| !row_ids.is_empty())
+                .collect()
+        })
+    }
+
+    pub fn estimated_bytes(&self) -> usize {
+        let index_guard = self.index.read().unwrap();
+        if !self.is_live() {
+            return 0;
+        }
+        index_guard.estimated_bytes()
+    }
+
+    pub fn set_memory_budget<BudgetFn>(&self, bytes: usize, on_exceeded: BudgetFn)
+    where
+        BudgetFn: Fn(usize) + Send + Sync + 'static,
+    {
+        // on_exceeded runs while the index is write-locked, so it must not
+        // read from this index
+        self.index
+            .write()
+            .unwrap()
+            .set_memory_budget(bytes, Box::new(on_exceeded));
+    }
+}
+
+impl<KeyT: PartialEq + Eq + Hash + Debug, ValueT: Clone> IndexRead<KeyT, ValueT> {
+    pub fn canonical_dump(&self) -> String {
+        self.stamp.consistent(|| {
+            let index_guard = self.index.read().unwrap();
+            if !self.is_live() {
+                return String::new();
+            }
+
+            let now = Instant::now();
+            let mut entries: Vec<(String, Vec<RowId>)> = index_guard
+                .index
+                .iter()
+                .filter_map(|(key, entry)| {
+                    let mut row_ids: Vec<RowId> = self.live_ids(&entry.row_ids, now).collect();
+                    row_ids.sort();
+                    (!row_ids.is_empty()).then(|| (format!("{:?}", key), row_ids))
+                })
+                .collect();
+            entries.sort();
+
+            let mut dump = String::new();
+            for (key, row_ids) in entries {
+                writeln!(dump, "{}: {:?}", key, row_ids).unwrap();
+            }
+            dump
+        })
+    }
 }
 
 impl<KeyT: PartialEq + Eq + Hash + Clone, ValueT: Clone> IndexRead<KeyT, ValueT> {
     pub fn keys(&self) -> Vec<KeyT> {
-        let index_guard = self.index.read().unwrap();
-        index_guard.keys().into_iter().cloned().collect()
+        let mut keys = Vec::new();
+        self.for_each_key(|key| keys.push(key.clone()));
+        keys
     }
 }
 
-pub struct IndexWrite<KeyT, ValueT> {
-    index: Arc<RwLock<Index<KeyT, ValueT>>>,
+pub struct IndexHandle<KeyT, ValueT> {
+    id: u64,
+    name: String,
+    read: IndexRead<KeyT, ValueT>,
+}
+
+impl<KeyT, ValueT> IndexHandle<KeyT, ValueT> {
+    pub fn new(id: u64, name: String, read: IndexRead<KeyT, ValueT>) -> Self {
+        IndexHandle { id, name, read }
+    }
+
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn reader(&self) -> IndexRead<KeyT, ValueT> {
+        self.read.clone()
+    }
+
+    pub(crate) fn into_reader(self) -> IndexRead<KeyT, ValueT> {
+        self.read
+    }
+}
+
+pub struct IndexWrite<IndexT> {
+    index: Arc<RwLock<IndexT>>,
+}
+
+impl<KeyT, ValueT> IndexRead<KeyT, ValueT>
+where
+    KeyT: PartialEq + Eq + Hash + Clone + Send + Sync + 'static,
+    ValueT: Clone + Send + 'static,
+{
+    pub fn subscribe(&self, key: &KeyT) -> Receiver<ChangeEvent<ValueT>> {
+        let mut index_guard = self.index.write().unwrap();
+        if !self.is_live() {
+            // The sender is dropped right away, so the receiver reports
+            // disconnected instead of waiting for events that never come
+            return mpsc::channel().1;
+        }
+        index_guard.subscribe(key.clone())
+    }
 }
 
-impl<KeyT: PartialEq + Eq + Hash, ValueT> IndexWrite<KeyT, ValueT> {
-    pub fn new(index: Arc<RwLock<Index<KeyT, ValueT>>>) -> Self {
+impl<IndexT> IndexWrite<IndexT> {
+    pub fn new(index: Arc<RwLock<IndexT>>) -> Self {
         IndexWrite { index }
     }
 }
 
-impl<KeyT: PartialEq + Eq + Hash, ValueT> Indexable<ValueT> for IndexWrite<KeyT, ValueT> {
+impl<ValueT, IndexT: Indexable<ValueT>> Indexable<ValueT> for IndexWrite<IndexT> {
     fn insert(&mut self, row: &Indexed<ValueT>) -> IndexId {
         self.index.write().unwrap().insert(row)
     }
@@ -137,4 +951,36 @@ impl<KeyT: PartialEq + Eq + Hash, ValueT> Indexable<ValueT> for IndexWrite<KeyT,
     fn delete(&mut self, row: &Indexed<ValueT>) {
         self.index.write().unwrap().delete(row)
     }
+
+    fn verify(&self, rows: &[Indexed<ValueT>]) -> (Vec<RowId>, Vec<RowId>) {
+        self.index.read().unwrap().verify(rows)
+    }
+
+    fn sweep(&mut self, now: Instant) {
+        self.index.write().unwrap().sweep(now)
+    }
+
+    fn update(&mut self, old: &Indexed<ValueT>, new: &Indexed<ValueT>) {
+        self.index.write().unwrap().update(old, new)
+    }
+
+    fn describe(&self) -> IndexDescription {
+        self.index.read().unwrap().describe()
+    }
+
+    fn dump_entries(&self) -> Vec<(String, Vec<RowId>)> {
+        self.index.read().unwrap().dump_entries()
+    }
+
+    fn insert_many(&mut self, rows: &[Indexed<ValueT>]) {
+        self.index.write().unwrap().insert_many(rows)
+    }
+
+    fn delete_many(&mut self, rows: &[Indexed<ValueT>]) {
+        self.index.write().unwrap().delete_many(rows)
+    }
+
+    fn update_many(&mut self, rows: &[(Indexed<ValueT>, Indexed<ValueT>)]) {
+        self.index.write().unwrap().update_many(rows)
+    }
 }
@@ -0,0 +1,196 @@
+use std::hash::Hash;
+
+use crate::{
+    error::UniqueViolation,
+    hashsync::HashSync,
+    id::{Indexed, RowId},
+    index::IndexRead,
+    unique::UniqueIndexRead,
+};
+
+// A primary key over an ordinary HashSync rather than a store keyed by KeyT:
+// rows are still stored and indexed by RowId, and the key is a unique index
+// that upsert, get and delete resolve through before touching the store
+pub struct KeyedHashSync<'a, KeyT, RowT> {
+    store: HashSync<'a, RowT>,
+    primary: UniqueIndexRead<KeyT, RowT>,
+    key_function: Box<dyn Fn(&RowT) -> KeyT + Send + Sync + 'a>,
+}
+
+impl<'a, KeyT, RowT> KeyedHashSync<'a, KeyT, RowT>
+where
+    KeyT: PartialEq + Eq + Hash + Send + Sync + 'a,
+    RowT: Clone + 'a,
+{
+    pub fn new<KeyFn>(key_fn: KeyFn) -> Self
+    where
+        KeyFn: Fn(&RowT) -> KeyT + Clone + Send + Sync + 'static,
+    {
+        let mut store = HashSync::new();
+        // A new store has no rows, so the primary key can't start out with
+        // duplicates
+        let primary = store.unique_index(key_fn.clone()).unwrap();
+        KeyedHashSync {
+            store,
+            primary,
+            key_function: Box::new(key_fn),
+        }
+    }
+
+    pub fn upsert(&mut self, row: RowT) -> RowId {
+        let key = (self.key_function)(&row);
+        match self.primary.get_id(&key) {
+            Some(id) => {
+                self.store.replace(id, row);
+                id
+            }
+            None => self.store.insert(row),
+        }
+    }
+
+    // Secondary unique indexes attached with attach can reject an upsert
+    pub fn upsert_unique(&mut self, row: RowT) -> Result<RowId, UniqueViolation> {
+        let key = (self.key_function)(&row);
+        match self.primary.get_id(&key) {
+            Some(id) => self.store.replace_unique(id, row).map(|()| id),
+            None => self.store.insert_unique(row),
+        }
+    }
+
+    pub fn get(&self, key: &KeyT) -> Option<RowT> {
+        self.primary.get_one_value(key)
+    }
+
+    pub fn get_indexed(&self, key: &KeyT) -> Option<Indexed<RowT>> {
+        self.primary.get_one(key)
+    }
+
+    pub fn id_of(&self, key: &KeyT) -> Option<RowId> {
+        self.primary.get_id(key)
+    }
+
+    pub fn contains_key(&self, key: &KeyT) -> bool {
+        self.primary.contains_key(key)
+    }
+
+    pub fn delete(&mut self, key: &KeyT) -> Option<RowT> {
+        let id = self.primary.get_id(key)?;
+        self.store.delete(id)
+    }
+
+    pub fn key_of(&self, row: &RowT) -> KeyT {
+        (self.key_function)(row)
+    }
+
+    pub fn len(&self) -> usize {
+        self.primary.key_count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn store(&self) -> &HashSync<'a, RowT> {
+        &self.store
+    }
+
+    // Writes only go through upsert and delete, so the store is never handed
+    // out mutably and secondary indexes are attached here or through attach
+    pub fn index<IndexKeyT, IndexFn>(&mut self, index_fn: IndexFn) -> IndexRead<IndexKeyT, RowT>
+    where
+        IndexFn: Fn(&RowT) -> IndexKeyT + Send + Sync + 'static,
+        IndexKeyT: PartialEq + Eq + Hash + Send + Sync + 'a,
+    {
+        self.store.index(index_fn)
+    }
+
+    // Registers any other kind of index, e.g. unique, sorted or expiring. The
+    // store is lent out only for the registration, so rows written inside it
+    // are still checked against the primary key
+    pub fn attach<ReadT, AttachFn>(&mut self, attach_fn: AttachFn) -> ReadT
+    where
+        AttachFn: FnOnce(&mut HashSync<'a, RowT>) -> ReadT,
+    {
+        attach_fn(&mut self.store)
+    }
+}
+
+impl<'a, RowT: Clone + 'a> HashSync<'a, RowT> {
+    pub fn with_keys<KeyT, KeyFn>(key_fn: KeyFn) -> KeyedHashSync<'a, KeyT, RowT>
+    where
+        KeyT: PartialEq + Eq + Hash + Send + Sync + 'a,
+        KeyFn: Fn(&RowT) -> KeyT + Clone + Send + Sync + 'static,
+    {
+        KeyedHashSync::new(key_fn)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, Clone, PartialEq)]
+    struct User {
+        uuid: u128,
+        name: &'static str,
+    }
+
+    #[test]
+    fn upsert_by_domain_key() {
+        let mut users = HashSync::with_keys(|user: &User| user.uuid);
+        let by_name = users.index(|user: &User| user.name);
+
+        let id = users.upsert(User {
+            uuid: 7,
+            name: "ann",
+        });
+        users.upsert(User {
+            uuid: 9,
+            name: "bob",
+        });
+        assert_eq!(
+            users.upsert(User {
+                uuid: 7,
+                name: "amy"
+            }),
+            id
+        );
+
+        assert_eq!(users.len(), 2);
+        assert_eq!(users.get(&7).map(|user| user.name), Some("amy"));
+        assert!(by_name.get(&"ann").is_empty());
+        assert_eq!(users.key_of(&by_name.get_values(&"bob")[0]), 9);
+
+        assert_eq!(users.delete(&9).map(|user| user.name), Some("bob"));
+        assert!(!users.contains_key(&9));
+        assert_eq!(users.id_of(&7), Some(id));
+        users.store().verify_indexes().unwrap();
+    }
+
+    #[test]
+    fn attach_other_index_kinds() {
+        let mut users = HashSync::with_keys(|user: &User| user.uuid);
+        let by_name = users.attach(|store| store.unique_index(|user: &User| user.name).unwrap());
+        let sorted = users.attach(|store| store.sorted_index(|user: &User| user.uuid));
+
+        users.upsert(User {
+            uuid: 9,
+            name: "bob",
+        });
+        let ann = users.upsert(User {
+            uuid: 7,
+            name: "ann",
+        });
+        let err = users
+            .upsert_unique(User {
+                uuid: 8,
+                name: "ann",
+            })
+            .unwrap_err();
+        assert_eq!(err.ids(), &[ann]);
+        assert_eq!(users.len(), 2);
+        assert_eq!(by_name.get_id(&"ann"), Some(ann));
+        assert_eq!(sorted.first().map(|user| user.value().uuid), Some(7));
+        users.store().verify_indexes().unwrap();
+    }
+}
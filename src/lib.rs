@@ -1,3 +1,53 @@
+pub mod admission;
+pub mod aggregate;
+pub mod branch;
+pub mod bulk;
+pub mod bundle;
+pub mod changes;
+pub mod checksum;
+pub mod clock;
+pub mod coalesce;
+pub mod covering;
+pub mod deadline;
+pub mod describe;
+pub mod diff;
+pub mod error;
+pub mod eviction;
+pub mod expiring;
+#[cfg(feature = "fixtures")]
+pub mod fixtures;
+pub mod handles;
 pub mod hashsync;
 pub mod id;
 pub mod index;
+pub mod keyed;
+pub mod merge;
+pub mod merkle;
+pub mod mirror;
+pub mod nullable;
+pub mod query;
+pub mod ranked;
+pub mod registry;
+pub mod resolve;
+pub mod row;
+pub mod shared;
+pub mod snapshot;
+pub mod sorted;
+#[cfg(feature = "geo")]
+pub mod spatial;
+pub mod stamp;
+pub mod stats;
+pub mod store;
+pub mod sync;
+pub mod text;
+pub mod trace;
+pub mod transaction;
+pub mod typed;
+pub mod unique;
+pub mod variant;
+#[cfg(feature = "persist")]
+pub mod wal;
+pub mod writer;
+
+#[cfg(feature = "derive")]
+pub use hashsync_derive::HashSyncRow;
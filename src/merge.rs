@@ -0,0 +1,86 @@
+use std::convert::Infallible;
+
+use crate::{error::UniqueViolation, hashsync::HashSync, id::RowId};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Resolution<RowT> {
+    KeepMine,
+    TakeTheirs,
+    Replace(RowT),
+    Delete,
+}
+
+impl<'a, RowT: Clone + 'a> HashSync<'a, RowT> {
+    pub fn merge<ResolveFn>(
+        &mut self,
+        other: HashSync<'_, RowT>,
+        mut resolve_fn: ResolveFn,
+    ) -> Result<(), UniqueViolation>
+    where
+        ResolveFn: FnMut(RowId, &RowT, &RowT) -> Resolution<RowT>,
+    {
+        let (rows, _next_id) = other.to_snapshot().into_parts();
+        self.transaction(|tx| {
+            for (id, theirs) in rows {
+                let mine = match tx.by_id(id) {
+                    Some(mine) => mine,
+                    None => {
+                        tx.replace(id, theirs);
+                        continue;
+                    }
+                };
+                match resolve_fn(id, &mine, &theirs) {
+                    Resolution::KeepMine => {}
+                    Resolution::TakeTheirs => tx.replace(id, theirs),
+                    Resolution::Replace(row) => tx.replace(id, row),
+                    Resolution::Delete => {
+                        tx.delete(id);
+                    }
+                }
+            }
+            Ok::<_, Infallible>(())
+        })?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn merge_resolves_collisions() {
+        let mut mine = HashSync::new();
+        let summed = mine.insert(("a", 1));
+        let kept = mine.insert(("b", 2));
+        let deleted = mine.insert(("c", 3));
+        let mut theirs = mine.extract(|_row| true);
+        let by_name = mine.index(|(name, _count)| *name);
+
+        theirs.replace(summed, ("a", 10));
+        theirs.replace(kept, ("b", 20));
+        let added = theirs.insert(("d", 4));
+
+        let mut collisions = Vec::new();
+        mine.merge(theirs, |id, mine, theirs| {
+            collisions.push(id);
+            if id == summed {
+                Resolution::Replace((mine.0, mine.1 + theirs.1))
+            } else if id == deleted {
+                Resolution::Delete
+            } else {
+                Resolution::KeepMine
+            }
+        })
+        .unwrap();
+
+        assert_eq!(collisions, vec![summed, kept, deleted]);
+        assert_eq!(mine.by_id(summed), Some(("a", 11)));
+        assert_eq!(mine.by_id(kept), Some(("b", 2)));
+        assert_eq!(mine.by_id(deleted), None);
+        assert_eq!(mine.by_id(added), Some(("d", 4)));
+        assert_eq!(by_name.get_values(&"a"), vec![("a", 11)]);
+        assert!(by_name.get_values(&"c").is_empty());
+        assert!(mine.insert(("e", 5)) > added);
+    }
+}
@@ -0,0 +1,268 @@
+use std::{hash::Hash, marker::PhantomData};
+
+use fxhash::{hash64, FxHashMap};
+
+use crate::{
+    describe::IndexDescription,
+    id::{Indexed, RowId},
+    index::{IndexId, Indexable},
+};
+
+const DEPTH: usize = 8;
+
+fn bucket_of(id: RowId) -> usize {
+    (hash64(&id) as usize) & ((1 << DEPTH) - 1)
+}
+
+fn leaf_hash<ValueT: Hash>(id: RowId, value: &ValueT) -> u64 {
+    hash64(&(id, value))
+}
+
+fn tombstone_hash(id: RowId) -> u64 {
+    hash64(&(id, "deleted"))
+}
+
+fn combine(left: u64, right: u64) -> u64 {
+    hash64(&(left, right))
+}
+
+// Rows are hashed into a fixed number of buckets whose hashes are sums of
+// their leaves, so inserts and deletes only rehash one path to the root.
+// The hashes are fxhash and not meant to resist deliberate collisions.
+// Deleted rows leave a tombstone in their bucket, so a replica that still
+// holds the row digests differently and sync can carry the delete over
+pub struct MerkleTree<ValueT> {
+    levels: Vec<Vec<u64>>,
+    leaves: FxHashMap<RowId, u64>,
+    tombstones: FxHashMap<RowId, u64>,
+    _marker: PhantomData<fn(&ValueT)>,
+}
+
+impl<ValueT> MerkleTree<ValueT> {
+    pub fn root_hash(&self) -> u64 {
+        self.levels[DEPTH][0]
+    }
+
+    pub fn bucket_hashes(&self) -> &[u64] {
+        &self.levels[0]
+    }
+
+    pub fn leaf(&self, id: RowId) -> Option<u64> {
+        self.leaves.get(&id).copied()
+    }
+
+    // The leaf of a live row or the tombstone of a deleted one
+    pub fn entry(&self, id: RowId) -> Option<u64> {
+        self.leaf(id).or_else(|| self.tombstones.get(&id).copied())
+    }
+
+    pub fn is_tombstone(&self, id: RowId) -> bool {
+        self.tombstones.contains_key(&id)
+    }
+
+    // Tombstones are listed alongside the leaves, so a replica compares both
+    pub fn leaves_in(&self, buckets: &[usize]) -> Vec<(RowId, u64)> {
+        let mut leaves: Vec<(RowId, u64)> = self
+            .leaves
+            .iter()
+            .chain(self.tombstones.iter())
+            .filter(|(id, _leaf)| buckets.contains(&bucket_of(**id)))
+            .map(|(id, leaf)| (*id, *leaf))
+            .collect();
+        leaves.sort();
+        leaves
+    }
+
+    // Records a delete that happened on another replica for a row this one
+    // never held
+    pub(crate) fn bury(&mut self, id: RowId) {
+        if !self.leaves.contains_key(&id) && !self.tombstones.contains_key(&id) {
+            let tombstone = tombstone_hash(id);
+            self.tombstones.insert(id, tombstone);
+            self.add_to_bucket(id, tombstone, false);
+        }
+    }
+
+    pub fn prove(&self, id: RowId) -> Option<MerkleProof> {
+        let leaf = *self.leaves.get(&id)?;
+        let mut position = bucket_of(id);
+        let bucket_rest = self.levels[0][position].wrapping_sub(leaf);
+        let mut siblings = Vec::with_capacity(DEPTH);
+        for level in self.levels.iter().take(DEPTH) {
+            siblings.push(level[position ^ 1]);
+            position /= 2;
+        }
+        Some(MerkleProof {
+            id,
+            bucket_rest,
+            siblings,
+        })
+    }
+
+    fn add_to_bucket(&mut self, id: RowId, delta: u64, remove: bool) {
+        let mut position = bucket_of(id);
+        let bucket = &mut self.levels[0][position];
+        *bucket = if remove {
+            bucket.wrapping_sub(delta)
+        } else {
+            bucket.wrapping_add(delta)
+        };
+        for level in 1..=DEPTH {
+            let left = self.levels[level - 1][position & !1];
+            let right = self.levels[level - 1][position | 1];
+            position /= 2;
+            self.levels[level][position] = combine(left, right);
+        }
+    }
+}
+
+impl<ValueT: Hash> MerkleTree<ValueT> {
+    pub fn new() -> Self {
+        let mut levels = vec![vec![0; 1 << DEPTH]];
+        for level in 1..=DEPTH {
+            let below = &levels[level - 1];
+            let hashes = below
+                .chunks(2)
+                .map(|pair| combine(pair[0], pair[1]))
+                .collect();
+            levels.push(hashes);
+        }
+        MerkleTree {
+            levels,
+            leaves: FxHashMap::default(),
+            tombstones: FxHashMap::default(),
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<ValueT: Hash> Default for MerkleTree<ValueT> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<ValueT: Hash> Indexable<ValueT> for MerkleTree<ValueT> {
+    fn insert(&mut self, row: &Indexed<ValueT>) -> IndexId {
+        let leaf = leaf_hash(row.id(), row.value());
+        if let Some(previous) = self.leaves.insert(row.id(), leaf) {
+            self.add_to_bucket(row.id(), previous, true);
+        }
+        if let Some(tombstone) = self.tombstones.remove(&row.id()) {
+            self.add_to_bucket(row.id(), tombstone, true);
+        }
+        self.add_to_bucket(row.id(), leaf, false);
+        IndexId::new(0)
+    }
+
+    fn delete(&mut self, row: &Indexed<ValueT>) {
+        if let Some(leaf) = self.leaves.remove(&row.id()) {
+            self.add_to_bucket(row.id(), leaf, true);
+            self.bury(row.id());
+        }
+    }
+
+    // A replace moves the leaf in place rather than passing through a
+    // tombstone
+    fn update(&mut self, _old: &Indexed<ValueT>, new: &Indexed<ValueT>) {
+        self.insert(new);
+    }
+
+    fn verify(&self, rows: &[Indexed<ValueT>]) -> (Vec<RowId>, Vec<RowId>) {
+        let expected: FxHashMap<RowId, u64> = rows
+            .iter()
+            .map(|row| (row.id(), leaf_hash(row.id(), row.value())))
+            .collect();
+        let mut leaked: Vec<RowId> = self
+            .leaves
+            .iter()
+            .filter(|(id, leaf)| expected.get(id) != Some(leaf))
+            .map(|(id, _leaf)| *id)
+            .collect();
+        let mut missing: Vec<RowId> = expected
+            .keys()
+            .filter(|id| !self.leaves.contains_key(id))
+            .copied()
+            .collect();
+        leaked.sort();
+        missing.sort();
+        (leaked, missing)
+    }
+
+    fn describe(&self) -> IndexDescription {
+        IndexDescription::new("merkle").with_option("depth", DEPTH)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MerkleProof {
+    id: RowId,
+    bucket_rest: u64,
+    siblings: Vec<u64>,
+}
+
+impl MerkleProof {
+    pub fn id(&self) -> RowId {
+        self.id
+    }
+
+    pub fn verify<RowT: Hash>(&self, row: &RowT, root_hash: u64) -> bool {
+        if self.siblings.len() != DEPTH {
+            return false;
+        }
+        let mut position = bucket_of(self.id);
+        let mut hash = leaf_hash(self.id, row).wrapping_add(self.bucket_rest);
+        for sibling in self.siblings.iter() {
+            hash = if position.is_multiple_of(2) {
+                combine(hash, *sibling)
+            } else {
+                combine(*sibling, hash)
+            };
+            position /= 2;
+        }
+        hash == root_hash
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hashsync::HashSync;
+
+    #[test]
+    fn replicas_with_same_rows_share_root() {
+        let mut hs1 = HashSync::new();
+        hs1.enable_merkle();
+        hs1.replace(RowId::new(0), (1, 2));
+        hs1.replace(RowId::new(1), (3, 4));
+
+        let mut hs2 = HashSync::new();
+        hs2.replace(RowId::new(1), (3, 4));
+        hs2.replace(RowId::new(0), (1, 2));
+        hs2.enable_merkle();
+        assert_eq!(hs1.root_hash(), hs2.root_hash());
+
+        hs2.replace(RowId::new(1), (3, 5));
+        assert_ne!(hs1.root_hash(), hs2.root_hash());
+        assert_eq!(hs2.verify_indexes(), Ok(()));
+    }
+
+    #[test]
+    fn proofs_verify_against_root() {
+        let mut hs = HashSync::new();
+        hs.enable_merkle();
+        let id = hs.insert((1, 2));
+        for n in 0..100 {
+            hs.insert((n, n));
+        }
+        let root = hs.root_hash().unwrap();
+
+        let proof = hs.prove(id).unwrap();
+        assert!(proof.verify(&(1, 2), root));
+        assert!(!proof.verify(&(1, 3), root));
+
+        hs.delete(id);
+        assert_eq!(hs.prove(id), None);
+        assert!(!proof.verify(&(1, 2), hs.root_hash().unwrap()));
+    }
+}
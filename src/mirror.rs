@@ -0,0 +1,176 @@
+use std::hash::Hash;
+
+use crate::{hashsync::HashSync, id::RowId, index::IndexRead};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Divergence {
+    operation: &'static str,
+    id: Option<RowId>,
+}
+
+impl Divergence {
+    pub fn new(operation: &'static str, id: Option<RowId>) -> Self {
+        Divergence { operation, id }
+    }
+
+    pub fn operation(&self) -> &'static str {
+        self.operation
+    }
+
+    pub fn id(&self) -> Option<RowId> {
+        self.id
+    }
+}
+
+pub type DivergenceCallback = Box<dyn Fn(&Divergence) + Send + Sync>;
+
+pub struct Mirror<'a, 'b, RowT> {
+    primary: HashSync<'a, RowT>,
+    shadow: HashSync<'b, RowT>,
+    on_divergence: DivergenceCallback,
+    divergences: usize,
+}
+
+impl<'a, 'b, RowT: Clone + PartialEq + 'a + 'b> Mirror<'a, 'b, RowT> {
+    pub fn new(
+        primary: HashSync<'a, RowT>,
+        shadow: HashSync<'b, RowT>,
+        on_divergence: DivergenceCallback,
+    ) -> Self {
+        Mirror {
+            primary,
+            shadow,
+            on_divergence,
+            divergences: 0,
+        }
+    }
+
+    pub fn primary(&self) -> &HashSync<'a, RowT> {
+        &self.primary
+    }
+
+    pub fn shadow(&self) -> &HashSync<'b, RowT> {
+        &self.shadow
+    }
+
+    pub fn primary_mut(&mut self) -> &mut HashSync<'a, RowT> {
+        &mut self.primary
+    }
+
+    pub fn shadow_mut(&mut self) -> &mut HashSync<'b, RowT> {
+        &mut self.shadow
+    }
+
+    pub fn divergences(&self) -> usize {
+        self.divergences
+    }
+
+    pub fn into_inner(self) -> (HashSync<'a, RowT>, HashSync<'b, RowT>) {
+        (self.primary, self.shadow)
+    }
+
+    pub fn insert(&mut self, row: RowT) -> RowId {
+        let id = self.primary.insert(row.clone());
+        let shadow_id = self.shadow.insert(row);
+        self.check("insert", Some(id), id == shadow_id);
+        id
+    }
+
+    pub fn delete(&mut self, id: RowId) -> Option<RowT> {
+        let row = self.primary.delete(id);
+        let shadow_row = self.shadow.delete(id);
+        self.check("delete", Some(id), row == shadow_row);
+        row
+    }
+
+    pub fn replace(&mut self, id: RowId, row: RowT) {
+        self.primary.replace(id, row.clone());
+        self.shadow.replace(id, row);
+    }
+
+    pub fn by_id(&mut self, id: RowId) -> Option<RowT> {
+        let row = self.primary.by_id(id);
+        let matches = row == self.shadow.by_id(id);
+        self.check("by_id", Some(id), matches);
+        row
+    }
+
+    pub fn keys(&mut self) -> Vec<RowId> {
+        let mut keys = self.primary.keys();
+        let mut shadow_keys = self.shadow.keys();
+        keys.sort();
+        shadow_keys.sort();
+        self.check("keys", None, keys == shadow_keys);
+        keys
+    }
+
+    // The same index has to be registered on both stores; results are
+    // compared as sorted id lists since index order is unspecified
+    pub fn get<KeyT: PartialEq + Eq + Hash>(
+        &mut self,
+        primary_index: &IndexRead<KeyT, RowT>,
+        shadow_index: &IndexRead<KeyT, RowT>,
+        key: &KeyT,
+    ) -> Vec<RowT> {
+        let mut rows = primary_index.get(key);
+        let mut shadow_rows = shadow_index.get(key);
+        rows.sort_by_key(|indexed| indexed.id());
+        shadow_rows.sort_by_key(|indexed| indexed.id());
+        let matches = rows.len() == shadow_rows.len()
+            && rows
+                .iter()
+                .zip(shadow_rows.iter())
+                .all(|(row, shadow_row)| {
+                    row.id() == shadow_row.id() && row.value() == shadow_row.value()
+                });
+        self.check("get", None, matches);
+        rows.into_iter()
+            .map(|indexed| indexed.into_value())
+            .collect()
+    }
+
+    fn check(&mut self, operation: &'static str, id: Option<RowId>, matches: bool) {
+        if !matches {
+            self.divergences += 1;
+            (self.on_divergence)(&Divergence::new(operation, id));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+
+    #[test]
+    fn mirror_reports_divergences() {
+        let reported = Arc::new(Mutex::new(Vec::new()));
+        let sink = reported.clone();
+        let mut mirror = Mirror::new(
+            HashSync::new(),
+            HashSync::new(),
+            Box::new(move |divergence: &Divergence| sink.lock().unwrap().push(*divergence)),
+        );
+        let primary_index = mirror.primary_mut().index(|&(a, _b)| a);
+        let shadow_index = mirror.shadow_mut().index(|&(a, _b)| a);
+
+        let id = mirror.insert((1, 2));
+        mirror.insert((1, 3));
+        assert_eq!(mirror.by_id(id), Some((1, 2)));
+        assert_eq!(mirror.get(&primary_index, &shadow_index, &1).len(), 2);
+        assert_eq!(mirror.divergences(), 0);
+
+        mirror.shadow_mut().replace(id, (2, 2));
+        assert_eq!(mirror.by_id(id), Some((1, 2)));
+        assert_eq!(mirror.get(&primary_index, &shadow_index, &1).len(), 2);
+        assert_eq!(mirror.divergences(), 2);
+        assert_eq!(
+            *reported.lock().unwrap(),
+            vec![
+                Divergence::new("by_id", Some(id)),
+                Divergence::new("get", None)
+            ]
+        );
+    }
+}
@@ -0,0 +1,70 @@
+use std::hash::Hash;
+
+use crate::{hashsync::HashSync, id::Indexed, index::IndexRead};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NullPolicy {
+    Bucket,
+    Skip,
+}
+
+impl<'a, RowT: Clone + 'a> HashSync<'a, RowT> {
+    pub fn index_option<IndexKeyT, IndexFn>(
+        &mut self,
+        policy: NullPolicy,
+        index_fn: IndexFn,
+    ) -> IndexRead<Option<IndexKeyT>, RowT>
+    where
+        IndexFn: Fn(&RowT) -> Option<IndexKeyT> + Send + Sync + 'static,
+        IndexKeyT: PartialEq + Eq + Hash + Send + Sync + 'a,
+    {
+        match policy {
+            NullPolicy::Bucket => self.index(index_fn),
+            NullPolicy::Skip => self.index_many(move |row: &RowT| match index_fn(row) {
+                Some(key) => vec![Some(key)],
+                None => Vec::new(),
+            }),
+        }
+    }
+}
+
+impl<KeyT: PartialEq + Eq + Hash, ValueT: Clone> IndexRead<Option<KeyT>, ValueT> {
+    pub fn get_none(&self) -> Vec<Indexed<ValueT>> {
+        self.get(&None)
+    }
+
+    pub fn get_none_values(&self) -> Vec<ValueT> {
+        self.get_values(&None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn null_bucket() {
+        let mut hs = HashSync::new();
+        hs.insert(("alice", Some("alice@example.com")));
+        let bob = hs.insert(("bob", None));
+        let by_email = hs.index_option(NullPolicy::Bucket, |&(_name, email)| email);
+
+        assert_eq!(by_email.get_none_values(), vec![("bob", None)]);
+        assert_eq!(by_email.get(&Some("alice@example.com")).len(), 1);
+
+        hs.delete(bob);
+        assert!(by_email.get_none().is_empty());
+    }
+
+    #[test]
+    fn null_skip() {
+        let mut hs = HashSync::new();
+        hs.insert(("alice", Some("alice@example.com")));
+        hs.insert(("bob", None));
+        let by_email = hs.index_option(NullPolicy::Skip, |&(_name, email)| email);
+
+        assert!(by_email.get_none().is_empty());
+        assert_eq!(by_email.key_count(), 1);
+        assert_eq!(by_email.get(&Some("alice@example.com")).len(), 1);
+    }
+}
@@ -0,0 +1,124 @@
+use std::{collections::BTreeSet, sync::Arc, time::Instant};
+
+use dashmap::DashMap;
+
+use crate::{
+    deadline::Deadlines,
+    id::{Indexed, RowId},
+};
+
+pub struct IdSet<ValueT> {
+    rows: Arc<DashMap<RowId, ValueT>>,
+    ids: BTreeSet<RowId>,
+    deadlines: Deadlines,
+}
+
+impl<ValueT> Clone for IdSet<ValueT> {
+    fn clone(&self) -> Self {
+        IdSet {
+            rows: self.rows.clone(),
+            ids: self.ids.clone(),
+            deadlines: self.deadlines.clone(),
+        }
+    }
+}
+
+impl<ValueT: Clone> IdSet<ValueT> {
+    pub fn new<IdsT: IntoIterator<Item = RowId>>(
+        rows: Arc<DashMap<RowId, ValueT>>,
+        ids: IdsT,
+    ) -> Self {
+        IdSet {
+            rows,
+            ids: ids.into_iter().collect(),
+            deadlines: Deadlines::default(),
+        }
+    }
+
+    pub(crate) fn with_deadlines(self, deadlines: Deadlines) -> Self {
+        IdSet { deadlines, ..self }
+    }
+
+    pub fn and(self, other: IdSet<ValueT>) -> Self {
+        debug_assert!(Arc::ptr_eq(&self.rows, &other.rows));
+        let ids = self.ids.intersection(&other.ids).copied().collect();
+        IdSet { ids, ..self }
+    }
+
+    pub fn or(mut self, other: IdSet<ValueT>) -> Self {
+        debug_assert!(Arc::ptr_eq(&self.rows, &other.rows));
+        self.ids.extend(other.ids);
+        self
+    }
+
+    pub fn not(self, other: IdSet<ValueT>) -> Self {
+        debug_assert!(Arc::ptr_eq(&self.rows, &other.rows));
+        let ids = self.ids.difference(&other.ids).copied().collect();
+        IdSet { ids, ..self }
+    }
+
+    pub fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ids.is_empty()
+    }
+
+    pub fn contains(&self, id: RowId) -> bool {
+        self.ids.contains(&id)
+    }
+
+    pub fn ids(&self) -> Vec<RowId> {
+        self.ids.iter().copied().collect()
+    }
+
+    pub fn fetch(&self) -> Vec<Indexed<ValueT>> {
+        let now = Instant::now();
+        self.ids
+            .iter()
+            .filter(|id| !self.deadlines.is_expired(**id, now))
+            .filter_map(|id| {
+                self.rows
+                    .get(id)
+                    .map(|value| Indexed::new(*id, value.clone()))
+            })
+            .collect()
+    }
+
+    pub fn fetch_values(&self) -> Vec<ValueT> {
+        self.fetch()
+            .into_iter()
+            .map(|row| row.into_value())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::hashsync::HashSync;
+
+    #[test]
+    fn combine_index_lookups() {
+        let mut hs = HashSync::new();
+        let by_color = hs.index(|row: &(&str, &str)| row.0);
+        let by_size = hs.index(|row: &(&str, &str)| row.1);
+        let ids = hs
+            .insert_batch(vec![("red", "large"), ("red", "small"), ("blue", "large")])
+            .unwrap();
+
+        let red_and_large = by_color.ids(&"red").and(by_size.ids(&"large"));
+        assert_eq!(red_and_large.fetch_values(), vec![("red", "large")]);
+
+        let red_or_large = by_color.ids(&"red").or(by_size.ids(&"large"));
+        assert_eq!(red_or_large.ids(), ids);
+
+        let large_not_red = by_size.ids(&"large").not(by_color.ids(&"red"));
+        assert_eq!(large_not_red.ids(), vec![ids[2]]);
+
+        let query = by_color.ids(&"red");
+        hs.delete(ids[1]);
+        assert_eq!(query.len(), 2);
+        assert_eq!(query.fetch().len(), 1);
+    }
+}
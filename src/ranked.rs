@@ -0,0 +1,261 @@
+use std::{
+    any::type_name,
+    cmp::Ordering,
+    hash::Hash,
+    sync::{Arc, RwLock},
+    time::Instant,
+};
+
+use dashmap::DashMap;
+use fxhash::FxHashMap;
+
+use crate::{
+    deadline::Deadlines,
+    describe::IndexDescription,
+    eviction::LruTracker,
+    id::{Indexed, RowId},
+    index::{hashed_entries, verify_entries, IndexId, Indexable},
+    stamp::Stamp,
+};
+
+pub type RankedIndexFunction<KeyT, ScoreT, ValueT> =
+    Box<dyn Fn(&Indexed<ValueT>) -> Vec<(KeyT, ScoreT)> + Send + Sync>;
+
+// Highest score first, ties broken by id. Scores that don't compare, such as
+// NaN, rank level with everything and so fall back to id order
+fn rank<ScoreT: PartialOrd>(a: &(ScoreT, RowId), b: &(ScoreT, RowId)) -> Ordering {
+    b.0.partial_cmp(&a.0)
+        .unwrap_or(Ordering::Equal)
+        .then(a.1.cmp(&b.1))
+}
+
+struct Ranking<ScoreT> {
+    order: Vec<(ScoreT, RowId)>,
+    scores: FxHashMap<RowId, ScoreT>,
+}
+
+impl<ScoreT: PartialOrd + Copy> Ranking<ScoreT> {
+    fn new() -> Self {
+        Ranking {
+            order: Vec::new(),
+            scores: FxHashMap::default(),
+        }
+    }
+
+    fn insert(&mut self, id: RowId, score: ScoreT) {
+        self.remove(id);
+        let entry = (score, id);
+        let position = self
+            .order
+            .partition_point(|other| rank(other, &entry) == Ordering::Less);
+        self.order.insert(position, entry);
+        self.scores.insert(id, score);
+    }
+
+    fn remove(&mut self, id: RowId) {
+        let score = match self.scores.remove(&id) {
+            Some(score) => score,
+            None => return,
+        };
+        let entry = (score, id);
+        let start = self
+            .order
+            .partition_point(|other| rank(other, &entry) == Ordering::Less);
+        // Incomparable scores can leave the entry outside the searched run
+        let position = self.order[start..]
+            .iter()
+            .position(|other| other.1 == id)
+            .map(|offset| start + offset)
+            .or_else(|| self.order.iter().position(|other| other.1 == id));
+        if let Some(position) = position {
+            self.order.remove(position);
+        }
+    }
+}
+
+pub struct RankedIndex<KeyT, ScoreT, ValueT> {
+    index_function: RankedIndexFunction<KeyT, ScoreT, ValueT>,
+    index: FxHashMap<KeyT, Ranking<ScoreT>>,
+}
+
+impl<KeyT: PartialEq + Eq + Hash, ScoreT: PartialOrd + Copy, ValueT>
+    RankedIndex<KeyT, ScoreT, ValueT>
+{
+    pub fn new(index_function: RankedIndexFunction<KeyT, ScoreT, ValueT>) -> Self {
+        RankedIndex {
+            index_function,
+            index: FxHashMap::default(),
+        }
+    }
+
+    pub fn ranked(&self, key: &KeyT) -> &[(ScoreT, RowId)] {
+        self.index
+            .get(key)
+            .map_or(&[], |ranking| ranking.order.as_slice())
+    }
+
+    pub fn score(&self, key: &KeyT, id: RowId) -> Option<ScoreT> {
+        self.index.get(key)?.scores.get(&id).copied()
+    }
+
+    pub fn key_count(&self) -> usize {
+        self.index.len()
+    }
+}
+
+impl<KeyT: PartialEq + Eq + Hash, ScoreT: PartialOrd + Copy, ValueT> Indexable<ValueT>
+    for RankedIndex<KeyT, ScoreT, ValueT>
+{
+    fn insert(&mut self, row: &Indexed<ValueT>) -> IndexId {
+        for (key, score) in (self.index_function)(row) {
+            self.index
+                .entry(key)
+                .or_insert_with(Ranking::new)
+                .insert(row.id(), score);
+        }
+        IndexId::new(0)
+    }
+
+    fn delete(&mut self, row: &Indexed<ValueT>) {
+        for (key, _score) in (self.index_function)(row) {
+            if let Some(ranking) = self.index.get_mut(&key) {
+                ranking.remove(row.id());
+                if ranking.order.is_empty() {
+                    self.index.remove(&key);
+                }
+            }
+        }
+    }
+
+    fn verify(&self, rows: &[Indexed<ValueT>]) -> (Vec<RowId>, Vec<RowId>) {
+        let expected = rows
+            .iter()
+            .map(|row| {
+                let keys = (self.index_function)(row)
+                    .into_iter()
+                    .map(|(key, _score)| key)
+                    .collect();
+                (row.id(), keys)
+            })
+            .collect();
+        let entries = self
+            .index
+            .iter()
+            .flat_map(|(key, ranking)| ranking.order.iter().map(move |(_score, id)| (key, *id)));
+        verify_entries(expected, entries)
+    }
+
+    fn dump_entries(&self) -> Vec<(String, Vec<RowId>)> {
+        hashed_entries(
+            self.index.iter().map(|(key, ranking)| {
+                (key, ranking.order.iter().map(|(_score, id)| *id).collect())
+            }),
+        )
+    }
+
+    fn describe(&self) -> IndexDescription {
+        IndexDescription::new("ranked")
+            .keyed_by::<KeyT>()
+            .with_option("score", type_name::<ScoreT>())
+    }
+}
+
+pub struct RankedIndexRead<KeyT, ScoreT, ValueT> {
+    rows: Arc<DashMap<RowId, ValueT>>,
+    index: Arc<RwLock<RankedIndex<KeyT, ScoreT, ValueT>>>,
+    stamp: Stamp,
+    deadlines: Deadlines,
+    lru: Option<Arc<LruTracker>>,
+}
+
+impl<KeyT, ScoreT, ValueT> Clone for RankedIndexRead<KeyT, ScoreT, ValueT> {
+    fn clone(&self) -> Self {
+        RankedIndexRead {
+            rows: self.rows.clone(),
+            index: self.index.clone(),
+            stamp: self.stamp.clone(),
+            deadlines: self.deadlines.clone(),
+            lru: self.lru.clone(),
+        }
+    }
+}
+
+impl<KeyT: PartialEq + Eq + Hash, ScoreT: PartialOrd + Copy, ValueT: Clone>
+    RankedIndexRead<KeyT, ScoreT, ValueT>
+{
+    pub fn new(
+        rows: Arc<DashMap<RowId, ValueT>>,
+        index: Arc<RwLock<RankedIndex<KeyT, ScoreT, ValueT>>>,
+    ) -> Self {
+        RankedIndexRead {
+            rows,
+            index,
+            stamp: Stamp::default(),
+            deadlines: Deadlines::default(),
+            lru: None,
+        }
+    }
+
+    pub(crate) fn stamped(
+        self,
+        stamp: Stamp,
+        deadlines: Deadlines,
+        lru: Option<Arc<LruTracker>>,
+    ) -> Self {
+        RankedIndexRead {
+            stamp,
+            deadlines,
+            lru,
+            ..self
+        }
+    }
+
+    pub fn is_live(&self) -> bool {
+        self.stamp.is_current()
+    }
+
+    pub fn get_ranked(&self, key: &KeyT) -> Vec<(Indexed<ValueT>, ScoreT)> {
+        self.stamp.consistent(|| {
+            let index_guard = self.index.read().unwrap();
+            if !self.is_live() {
+                return Vec::new();
+            }
+
+            let now = Instant::now();
+            index_guard
+                .ranked(key)
+                .iter()
+                .filter(|(_score, id)| !self.deadlines.is_expired(*id, now))
+                .filter_map(|(score, id)| {
+                    let row = self.rows.get(id)?;
+                    if let Some(lru) = self.lru.as_ref() {
+                        lru.touch(*id);
+                    }
+                    Some((Indexed::new(*id, row.value().clone()), *score))
+                })
+                .collect()
+        })
+    }
+
+    pub fn get_ranked_values(&self, key: &KeyT) -> Vec<ValueT> {
+        let ranked = self.get_ranked(key);
+        ranked
+            .into_iter()
+            .map(|(indexed, _score)| indexed.into_value())
+            .collect()
+    }
+
+    pub fn score(&self, key: &KeyT, id: RowId) -> Option<ScoreT> {
+        self.stamp.consistent(|| {
+            let index_guard = self.index.read().unwrap();
+            if !self.is_live() || self.deadlines.is_expired(id, Instant::now()) {
+                return None;
+            }
+            let score = index_guard.score(key, id);
+            if let (Some(lru), Some(_score)) = (self.lru.as_ref(), score.as_ref()) {
+                lru.touch(id);
+            }
+            score
+        })
+    }
+}
@@ -0,0 +1,189 @@
+use std::sync::Arc;
+
+use fxhash::FxHashMap;
+
+use crate::{
+    error::UnknownIndexKind, hashsync::HashSync, id::Indexed, index::IndexRead,
+    sorted::SortedIndexRead,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IndexSpec {
+    kind: String,
+    field: String,
+    options: Vec<(String, String)>,
+}
+
+impl IndexSpec {
+    pub fn new(kind: impl Into<String>, field: impl Into<String>) -> Self {
+        IndexSpec {
+            kind: kind.into(),
+            field: field.into(),
+            options: Vec::new(),
+        }
+    }
+
+    pub fn with_option(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.options.push((name.into(), value.into()));
+        self
+    }
+
+    pub fn kind(&self) -> &str {
+        &self.kind
+    }
+
+    pub fn field(&self) -> &str {
+        &self.field
+    }
+
+    pub fn option(&self, name: &str) -> Option<&str> {
+        self.options
+            .iter()
+            .find(|(option, _value)| option == name)
+            .map(|(_option, value)| value.as_str())
+    }
+}
+
+type DynLookup<RowT> = Arc<dyn Fn(&str) -> Vec<Indexed<RowT>> + Send + Sync>;
+
+pub struct DynIndexRead<RowT> {
+    lookup: DynLookup<RowT>,
+}
+
+impl<RowT> Clone for DynIndexRead<RowT> {
+    fn clone(&self) -> Self {
+        DynIndexRead {
+            lookup: self.lookup.clone(),
+        }
+    }
+}
+
+impl<RowT> DynIndexRead<RowT> {
+    pub fn new<LookupFn>(lookup: LookupFn) -> Self
+    where
+        LookupFn: Fn(&str) -> Vec<Indexed<RowT>> + Send + Sync + 'static,
+    {
+        DynIndexRead {
+            lookup: Arc::new(lookup),
+        }
+    }
+
+    pub fn get(&self, key: &str) -> Vec<Indexed<RowT>> {
+        (self.lookup)(key)
+    }
+
+    pub fn get_values(&self, key: &str) -> Vec<RowT> {
+        let indexed = self.get(key);
+        indexed.into_iter().map(|i| i.into_value()).collect()
+    }
+}
+
+impl<RowT: Clone + Send + Sync + 'static> From<IndexRead<String, RowT>> for DynIndexRead<RowT> {
+    fn from(index: IndexRead<String, RowT>) -> Self {
+        DynIndexRead::new(move |key| index.get(&key.to_string()))
+    }
+}
+
+impl<RowT: Clone + Send + Sync + 'static> From<SortedIndexRead<String, RowT>>
+    for DynIndexRead<RowT>
+{
+    fn from(index: SortedIndexRead<String, RowT>) -> Self {
+        DynIndexRead::new(move |key| index.get(&key.to_string()))
+    }
+}
+
+pub type IndexFactory<'a, RowT> =
+    Box<dyn Fn(&mut HashSync<'a, RowT>, &IndexSpec) -> DynIndexRead<RowT> + Send + Sync>;
+
+pub struct IndexRegistry<'a, RowT> {
+    factories: FxHashMap<String, IndexFactory<'a, RowT>>,
+}
+
+impl<'a, RowT: Clone + 'a> Default for IndexRegistry<'a, RowT> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'a, RowT: Clone + 'a> IndexRegistry<'a, RowT> {
+    pub fn new() -> Self {
+        IndexRegistry {
+            factories: FxHashMap::default(),
+        }
+    }
+
+    pub fn register<FactoryFn>(&mut self, kind: impl Into<String>, factory: FactoryFn)
+    where
+        FactoryFn:
+            Fn(&mut HashSync<'a, RowT>, &IndexSpec) -> DynIndexRead<RowT> + Send + Sync + 'static,
+    {
+        self.factories.insert(kind.into(), Box::new(factory));
+    }
+
+    pub fn kinds(&self) -> Vec<&str> {
+        let mut kinds: Vec<&str> = self.factories.keys().map(|kind| kind.as_str()).collect();
+        kinds.sort();
+        kinds
+    }
+
+    pub fn build(
+        &self,
+        hs: &mut HashSync<'a, RowT>,
+        spec: &IndexSpec,
+    ) -> Result<DynIndexRead<RowT>, UnknownIndexKind> {
+        match self.factories.get(spec.kind()) {
+            Some(factory) => Ok(factory(hs, spec)),
+            None => Err(UnknownIndexKind::new(spec.kind().to_string())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::*;
+
+    type Record = BTreeMap<String, String>;
+
+    fn record(fields: &[(&str, &str)]) -> Record {
+        fields
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn indexes_are_built_from_specs() {
+        let mut registry: IndexRegistry<Record> = IndexRegistry::new();
+        registry.register("hash", |hs, spec| {
+            let field = spec.field().to_string();
+            hs.index(move |row: &Record| row.get(&field).cloned().unwrap_or_default())
+                .into()
+        });
+        registry.register("btree", |hs, spec| {
+            let field = spec.field().to_string();
+            hs.sorted_index(move |row: &Record| row.get(&field).cloned().unwrap_or_default())
+                .into()
+        });
+        assert_eq!(registry.kinds(), vec!["btree", "hash"]);
+
+        let mut hs = HashSync::new();
+        hs.insert(record(&[("name", "alice"), ("team", "red")]));
+        hs.insert(record(&[("name", "bob"), ("team", "red")]));
+        let by_team = registry
+            .build(&mut hs, &IndexSpec::new("hash", "team"))
+            .unwrap();
+        let by_name = registry
+            .build(&mut hs, &IndexSpec::new("btree", "name"))
+            .unwrap();
+
+        assert_eq!(by_team.get("red").len(), 2);
+        assert_eq!(by_name.get_values("bob")[0].get("team").unwrap(), "red");
+        let err = registry
+            .build(&mut hs, &IndexSpec::new("spatial", "location"))
+            .err()
+            .unwrap();
+        assert_eq!(err.kind(), "spatial");
+    }
+}
@@ -0,0 +1,103 @@
+use std::hash::Hash;
+
+use fxhash::FxHashMap;
+
+use crate::{id::RowId, index::IndexRead};
+
+pub trait MatchIndex {
+    fn id_groups(&self) -> Vec<Vec<RowId>>;
+}
+
+impl<KeyT: PartialEq + Eq + Hash, ValueT: Clone> MatchIndex for IndexRead<KeyT, ValueT> {
+    fn id_groups(&self) -> Vec<Vec<RowId>> {
+        IndexRead::id_groups(self)
+    }
+}
+
+pub fn resolve(indexes: &[&dyn MatchIndex]) -> Vec<Vec<RowId>> {
+    let mut parents: FxHashMap<RowId, RowId> = FxHashMap::default();
+    for index in indexes {
+        for group in index.id_groups() {
+            let mut ids = group.into_iter();
+            if let Some(first) = ids.next() {
+                for id in ids {
+                    union(&mut parents, first, id);
+                }
+            }
+        }
+    }
+
+    let ids: Vec<RowId> = parents.keys().copied().collect();
+    let mut clusters: FxHashMap<RowId, Vec<RowId>> = FxHashMap::default();
+    for id in ids {
+        let root = find(&mut parents, id);
+        clusters.entry(root).or_default().push(id);
+    }
+
+    let mut clusters: Vec<Vec<RowId>> = clusters
+        .into_values()
+        .filter(|cluster| cluster.len() > 1)
+        .map(|mut cluster| {
+            cluster.sort();
+            cluster
+        })
+        .collect();
+    clusters.sort();
+    clusters
+}
+
+fn find(parents: &mut FxHashMap<RowId, RowId>, id: RowId) -> RowId {
+    let mut root = *parents.entry(id).or_insert(id);
+    while parents[&root] != root {
+        root = parents[&root];
+    }
+
+    let mut current = id;
+    while current != root {
+        current = parents.insert(current, root).unwrap();
+    }
+    root
+}
+
+fn union(parents: &mut FxHashMap<RowId, RowId>, a: RowId, b: RowId) {
+    let root_a = find(parents, a);
+    let root_b = find(parents, b);
+    if root_a != root_b {
+        parents.insert(root_a.max(root_b), root_a.min(root_b));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hashsync::HashSync;
+
+    #[test]
+    fn resolve_and_merge() {
+        let mut hs = HashSync::new();
+        let a = hs.insert(("a@example.com", "555-1234"));
+        let b = hs.insert(("a@example.com", "555-9999"));
+        let c = hs.insert(("c@example.com", "555-9999"));
+        let d = hs.insert(("d@example.com", "555-0000"));
+        let by_email = hs.index(|&(email, _phone)| email);
+        let by_phone = hs.index(|&(_email, phone)| phone);
+
+        let clusters = resolve(&[&by_email, &by_phone]);
+        assert_eq!(clusters, vec![vec![a, b, c]]);
+
+        let merged = hs
+            .merge_rows(&clusters[0], |rows| {
+                assert_eq!(rows.len(), 3);
+                rows[0]
+            })
+            .unwrap();
+        assert_eq!(merged, Some(a));
+        assert_eq!(hs.keys().len(), 2);
+        assert_eq!(by_phone.get_values(&"555-9999"), Vec::new());
+        assert_eq!(
+            by_phone.get_values(&"555-1234"),
+            vec![("a@example.com", "555-1234")]
+        );
+        assert_eq!(hs.by_id(d), Some(("d@example.com", "555-0000")));
+    }
+}
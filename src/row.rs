@@ -0,0 +1,15 @@
+use crate::{error::UniqueViolation, hashsync::HashSync};
+
+pub trait HashSyncRow: Clone + Sized {
+    type Indexes;
+
+    fn register_indexes<'a>(hs: &mut HashSync<'a, Self>) -> Result<Self::Indexes, UniqueViolation>
+    where
+        Self: 'a;
+}
+
+impl<'a, RowT: HashSyncRow + 'a> HashSync<'a, RowT> {
+    pub fn row_indexes(&mut self) -> Result<RowT::Indexes, UniqueViolation> {
+        RowT::register_indexes(self)
+    }
+}
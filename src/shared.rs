@@ -0,0 +1,64 @@
+use std::sync::Arc;
+
+use crate::{hashsync::HashSync, id::RowId};
+
+pub type SharedHashSync<'a, RowT> = HashSync<'a, Arc<RowT>>;
+
+impl<'a, RowT: 'a> HashSync<'a, Arc<RowT>> {
+    pub fn new_shared() -> Self {
+        HashSync::new()
+    }
+
+    pub fn insert_shared(&mut self, row: RowT) -> RowId {
+        self.insert(Arc::new(row))
+    }
+
+    pub fn replace_shared(&mut self, id: RowId, row: RowT) {
+        self.replace(id, Arc::new(row))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc::{channel, Receiver};
+
+    use super::*;
+
+    struct Worker {
+        name: &'static str,
+        inbox: Receiver<u32>,
+    }
+
+    #[test]
+    fn non_clone_rows() {
+        let (sender, inbox) = channel();
+        let mut hs: SharedHashSync<Worker> = HashSync::new();
+        let row_id = hs.insert_shared(Worker {
+            name: "alice",
+            inbox,
+        });
+        let by_name = hs.index(|worker: &Arc<Worker>| worker.name);
+
+        let worker = by_name.get_one(&"alice").unwrap().into_value();
+        sender.send(7).unwrap();
+        assert_eq!(worker.inbox.recv().unwrap(), 7);
+        assert!(Arc::ptr_eq(&worker, &hs.by_id(row_id).unwrap()));
+
+        let (_sender, inbox) = channel();
+        hs.replace_shared(row_id, Worker { name: "bob", inbox });
+        assert!(by_name.get_one(&"alice").is_none());
+        assert_eq!(by_name.get_values(&"bob").len(), 1);
+    }
+
+    #[test]
+    fn shared_values_are_not_copied() {
+        let mut hs = HashSync::new_shared();
+        hs.insert_shared(vec![0u8; 1024]);
+        let by_len = hs.index(|row: &Arc<Vec<u8>>| row.len());
+
+        let first = by_len.get_values(&1024);
+        let second = by_len.get_values(&1024);
+        assert!(Arc::ptr_eq(&first[0], &second[0]));
+        assert_eq!(Arc::strong_count(&first[0]), 3);
+    }
+}
@@ -0,0 +1,156 @@
+use std::{
+    collections::BTreeMap,
+    sync::Arc,
+    thread::{self, JoinHandle},
+};
+
+use crate::{hashsync::HashSync, id::RowId};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct HashSyncSnapshot<RowT> {
+    rows: Vec<(RowId, RowT)>,
+    next_id: RowId,
+}
+
+impl<RowT> HashSyncSnapshot<RowT> {
+    pub fn new(rows: Vec<(RowId, RowT)>, next_id: RowId) -> Self {
+        HashSyncSnapshot { rows, next_id }
+    }
+
+    pub fn rows(&self) -> &[(RowId, RowT)] {
+        &self.rows
+    }
+
+    pub fn next_id(&self) -> RowId {
+        self.next_id
+    }
+
+    pub fn into_parts(self) -> (Vec<(RowId, RowT)>, RowId) {
+        (self.rows, self.next_id)
+    }
+}
+
+pub struct Snapshot<RowT> {
+    rows: Arc<BTreeMap<RowId, RowT>>,
+    version: u64,
+}
+
+impl<RowT> Clone for Snapshot<RowT> {
+    fn clone(&self) -> Self {
+        Snapshot {
+            rows: self.rows.clone(),
+            version: self.version,
+        }
+    }
+}
+
+impl<RowT: Clone> Snapshot<RowT> {
+    pub fn new(rows: BTreeMap<RowId, RowT>, version: u64) -> Self {
+        Snapshot {
+            rows: Arc::new(rows),
+            version,
+        }
+    }
+
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+
+    pub fn len(&self) -> usize {
+        self.rows.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rows.is_empty()
+    }
+
+    pub fn keys(&self) -> Vec<RowId> {
+        self.rows.keys().copied().collect()
+    }
+
+    pub fn by_id(&self, id: RowId) -> Option<RowT> {
+        self.rows.get(&id).cloned()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (RowId, &RowT)> {
+        self.rows.iter().map(|(id, row)| (*id, row))
+    }
+}
+
+impl<'a, RowT: Clone + 'a> HashSync<'a, RowT> {
+    pub fn export_snapshot_async<ExportFn, OutputT>(
+        &self,
+        export_fn: ExportFn,
+    ) -> JoinHandle<OutputT>
+    where
+        RowT: Send + 'static,
+        ExportFn: FnOnce(HashSyncSnapshot<RowT>) -> OutputT + Send + 'static,
+        OutputT: Send + 'static,
+    {
+        // Only the row copy happens on the caller's thread; with
+        // SharedHashSync that is a refcount bump per row
+        let snapshot = self.to_snapshot();
+        thread::spawn(move || export_fn(snapshot))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn export_runs_on_captured_rows() {
+        let mut hs = HashSync::new();
+        let id = hs.insert((1, 2));
+        let (started, start) = std::sync::mpsc::channel::<()>();
+        let export = hs.export_snapshot_async(move |snapshot| {
+            start.recv().unwrap();
+            snapshot.rows().to_vec()
+        });
+
+        hs.replace(id, (1, 3));
+        hs.insert((2, 2));
+        started.send(()).unwrap();
+        assert_eq!(export.join().unwrap(), vec![(id, (1, 2))]);
+    }
+
+    #[test]
+    fn snapshot_is_frozen() {
+        let mut hs = HashSync::new();
+        let kept = hs.insert(1);
+        let deleted = hs.insert(2);
+        let snapshot = hs.snapshot();
+
+        hs.replace(kept, 10);
+        hs.delete(deleted);
+        hs.insert(3);
+
+        assert_eq!(snapshot.keys(), vec![kept, deleted]);
+        assert_eq!(snapshot.by_id(kept), Some(1));
+        assert_eq!(snapshot.by_id(deleted), Some(2));
+        assert_eq!(snapshot.version(), 2);
+        assert_eq!(hs.snapshot().len(), 2);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn snapshot_round_trips_through_json() {
+        let mut hs = HashSync::new();
+        hs.insert((1, "a".to_string()));
+        let deleted = hs.insert((2, "b".to_string()));
+        hs.insert((1, "c".to_string()));
+        hs.delete(deleted);
+
+        let json = serde_json::to_string(&hs.to_snapshot()).unwrap();
+        let snapshot: HashSyncSnapshot<(u32, String)> = serde_json::from_str(&json).unwrap();
+        let mut restored = HashSync::from_snapshot(snapshot);
+        let index = restored.index(|(a, _b)| *a);
+
+        assert_eq!(index.get(&1).len(), 2);
+        assert_eq!(restored.by_id(deleted), None);
+        assert_eq!(restored.to_snapshot(), hs.to_snapshot());
+        let id = restored.insert((3, "d".to_string()));
+        assert!(id > deleted);
+    }
+}
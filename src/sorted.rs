@@ -0,0 +1,276 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    ops::{Bound, RangeBounds},
+    sync::{Arc, RwLock},
+    time::Instant,
+};
+
+use dashmap::DashMap;
+
+use crate::{
+    deadline::Deadlines,
+    describe::IndexDescription,
+    eviction::LruTracker,
+    id::{Indexed, RowId},
+    index::{verify_entries, IndexFunction, IndexId, Indexable},
+    stamp::Stamp,
+};
+
+pub struct SortedIndex<KeyT, ValueT> {
+    index_function: IndexFunction<KeyT, ValueT>,
+    index: BTreeMap<KeyT, BTreeSet<RowId>>,
+}
+
+impl<KeyT: Ord, ValueT> SortedIndex<KeyT, ValueT> {
+    pub fn new(index_function: IndexFunction<KeyT, ValueT>) -> Self {
+        SortedIndex {
+            index_function,
+            index: BTreeMap::new(),
+        }
+    }
+
+    pub fn get_ref(&self, key: &KeyT) -> Option<&BTreeSet<RowId>> {
+        self.index.get(key)
+    }
+
+    pub fn range<R: RangeBounds<KeyT>>(&self, range: R) -> Vec<RowId> {
+        self.index
+            .range(range)
+            .flat_map(|(_key, row_ids)| row_ids.iter().copied())
+            .collect()
+    }
+
+    pub fn keys(&self) -> Vec<&KeyT> {
+        self.index.keys().collect()
+    }
+
+    pub fn key_count(&self) -> usize {
+        self.index.len()
+    }
+}
+
+impl<ValueT> SortedIndex<String, ValueT> {
+    pub fn starts_with(&self, prefix: &str) -> Vec<RowId> {
+        // Keys sharing a prefix are contiguous in sort order, so the scan can
+        // stop at the first key past it
+        self.index
+            .range::<str, _>((Bound::Included(prefix), Bound::Unbounded))
+            .take_while(|(key, _row_ids)| key.starts_with(prefix))
+            .flat_map(|(_key, row_ids)| row_ids.iter().copied())
+            .collect()
+    }
+}
+
+impl<KeyT: Ord, ValueT> Indexable<ValueT> for SortedIndex<KeyT, ValueT> {
+    fn insert(&mut self, row: &Indexed<ValueT>) -> IndexId {
+        for key in (self.index_function)(row) {
+            self.index.entry(key).or_default().insert(row.id());
+        }
+        IndexId::new(0)
+    }
+
+    fn delete(&mut self, row: &Indexed<ValueT>) {
+        for key in (self.index_function)(row) {
+            if let Some(row_ids) = self.index.get_mut(&key) {
+                row_ids.remove(&row.id());
+                if row_ids.is_empty() {
+                    self.index.remove(&key);
+                }
+            }
+        }
+    }
+
+    fn verify(&self, rows: &[Indexed<ValueT>]) -> (Vec<RowId>, Vec<RowId>) {
+        let expected = rows
+            .iter()
+            .map(|row| (row.id(), (self.index_function)(row)))
+            .collect();
+        let entries = self
+            .index
+            .iter()
+            .flat_map(|(key, row_ids)| row_ids.iter().map(move |id| (key, *id)));
+        verify_entries(expected, entries)
+    }
+
+    fn dump_entries(&self) -> Vec<(String, Vec<RowId>)> {
+        // Keys are only ordered, not hashed, so they are labelled by position
+        self.index
+            .values()
+            .enumerate()
+            .map(|(position, row_ids)| {
+                (format!("#{}", position), row_ids.iter().copied().collect())
+            })
+            .collect()
+    }
+
+    fn describe(&self) -> IndexDescription {
+        IndexDescription::new("sorted").keyed_by::<KeyT>()
+    }
+}
+
+pub struct SortedIndexRead<KeyT, ValueT> {
+    rows: Arc<DashMap<RowId, ValueT>>,
+    index: Arc<RwLock<SortedIndex<KeyT, ValueT>>>,
+    stamp: Stamp,
+    deadlines: Deadlines,
+    lru: Option<Arc<LruTracker>>,
+}
+
+impl<KeyT, ValueT> Clone for SortedIndexRead<KeyT, ValueT> {
+    fn clone(&self) -> Self {
+        SortedIndexRead {
+            rows: self.rows.clone(),
+            index: self.index.clone(),
+            stamp: self.stamp.clone(),
+            deadlines: self.deadlines.clone(),
+            lru: self.lru.clone(),
+        }
+    }
+}
+
+impl<KeyT: Ord, ValueT: Clone> SortedIndexRead<KeyT, ValueT> {
+    pub fn new(
+        rows: Arc<DashMap<RowId, ValueT>>,
+        index: Arc<RwLock<SortedIndex<KeyT, ValueT>>>,
+    ) -> Self {
+        SortedIndexRead {
+            rows,
+            index,
+            stamp: Stamp::default(),
+            deadlines: Deadlines::default(),
+            lru: None,
+        }
+    }
+
+    pub(crate) fn stamped(
+        self,
+        stamp: Stamp,
+        deadlines: Deadlines,
+        lru: Option<Arc<LruTracker>>,
+    ) -> Self {
+        SortedIndexRead {
+            stamp,
+            deadlines,
+            lru,
+            ..self
+        }
+    }
+
+    pub fn is_live(&self) -> bool {
+        self.stamp.is_current()
+    }
+
+    pub fn get(&self, key: &KeyT) -> Vec<Indexed<ValueT>> {
+        self.stamp.consistent(|| {
+            let row_ids: Vec<RowId> = match self.index.read().unwrap().get_ref(key) {
+                Some(row_ids) => row_ids.iter().copied().collect(),
+                None => Vec::new(),
+            };
+            self.hydrate(row_ids)
+        })
+    }
+
+    pub fn range<R: RangeBounds<KeyT>>(&self, range: R) -> Vec<Indexed<ValueT>> {
+        self.stamp.consistent(|| {
+            let bounds = (range.start_bound(), range.end_bound());
+            let row_ids = self.index.read().unwrap().range(bounds);
+            self.hydrate(row_ids)
+        })
+    }
+
+    pub fn range_values<R: RangeBounds<KeyT>>(&self, range: R) -> Vec<ValueT> {
+        let indexed = self.range(range);
+        indexed.into_iter().map(|i| i.into_value()).collect()
+    }
+
+    pub fn first(&self) -> Option<Indexed<ValueT>> {
+        self.stamp.consistent(|| {
+            let index_guard = self.index.read().unwrap();
+            index_guard
+                .index
+                .values()
+                .flatten()
+                .find_map(|id| self.by_id(*id))
+        })
+    }
+
+    pub fn last(&self) -> Option<Indexed<ValueT>> {
+        self.stamp.consistent(|| {
+            let index_guard = self.index.read().unwrap();
+            index_guard
+                .index
+                .values()
+                .rev()
+                .flat_map(|row_ids| row_ids.iter().rev())
+                .find_map(|id| self.by_id(*id))
+        })
+    }
+
+    // Includes keys whose rows have expired but not yet been swept
+    pub fn key_count(&self) -> usize {
+        self.stamp.consistent(|| {
+            let index_guard = self.index.read().unwrap();
+            if !self.is_live() {
+                return 0;
+            }
+            index_guard.key_count()
+        })
+    }
+
+    fn has_live_row(&self, row_ids: &BTreeSet<RowId>, now: Instant) -> bool {
+        self.is_live()
+            && row_ids
+                .iter()
+                .any(|id| !self.deadlines.is_expired(*id, now))
+    }
+
+    fn by_id(&self, id: RowId) -> Option<Indexed<ValueT>> {
+        if !self.is_live() || self.deadlines.is_expired(id, Instant::now()) {
+            return None;
+        }
+        let row = self
+            .rows
+            .get(&id)
+            .map(|value| Indexed::new(id, value.clone()));
+        if let (Some(lru), Some(_row)) = (self.lru.as_ref(), row.as_ref()) {
+            lru.touch(id);
+        }
+        row
+    }
+
+    fn hydrate(&self, row_ids: Vec<RowId>) -> Vec<Indexed<ValueT>> {
+        row_ids
+            .into_iter()
+            .filter_map(|id| self.by_id(id))
+            .collect()
+    }
+}
+
+impl<ValueT: Clone> SortedIndexRead<String, ValueT> {
+    pub fn starts_with(&self, prefix: &str) -> Vec<Indexed<ValueT>> {
+        self.stamp.consistent(|| {
+            let row_ids = self.index.read().unwrap().starts_with(prefix);
+            self.hydrate(row_ids)
+        })
+    }
+
+    pub fn starts_with_values(&self, prefix: &str) -> Vec<ValueT> {
+        let indexed = self.starts_with(prefix);
+        indexed.into_iter().map(|i| i.into_value()).collect()
+    }
+}
+
+impl<KeyT: Ord + Clone, ValueT: Clone> SortedIndexRead<KeyT, ValueT> {
+    pub fn keys(&self) -> Vec<KeyT> {
+        self.stamp.consistent(|| {
+            let index_guard = self.index.read().unwrap();
+            let now = Instant::now();
+            index_guard
+                .index
+                .iter()
+                .filter(|(_key, row_ids)| self.has_live_row(row_ids, now))
+                .map(|(key, _row_ids)| key.clone())
+                .collect()
+        })
+    }
+}
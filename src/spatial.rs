@@ -0,0 +1,357 @@
+use std::sync::{Arc, RwLock};
+
+use fxhash::FxHashMap;
+
+use crate::{
+    describe::IndexDescription,
+    hashsync::HashSync,
+    id::{Indexed, RowId},
+    index::{verify_entries, IndexId, Indexable},
+    store::StoreRead,
+};
+
+pub type Point = (f64, f64);
+
+pub type PointFunction<ValueT> = Box<dyn Fn(&ValueT) -> Point + Send + Sync>;
+
+type Cell = (i64, i64);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Rect {
+    min: Point,
+    max: Point,
+}
+
+impl Rect {
+    pub fn new(min: Point, max: Point) -> Self {
+        Rect { min, max }
+    }
+
+    pub fn contains(&self, point: Point) -> bool {
+        (self.min.0..=self.max.0).contains(&point.0) && (self.min.1..=self.max.1).contains(&point.1)
+    }
+}
+
+fn distance_squared(a: Point, b: Point) -> f64 {
+    (a.0 - b.0).powi(2) + (a.1 - b.1).powi(2)
+}
+
+pub struct SpatialIndex<ValueT> {
+    point_function: PointFunction<ValueT>,
+    cell_size: f64,
+    cells: FxHashMap<Cell, FxHashMap<RowId, Point>>,
+}
+
+impl<ValueT> SpatialIndex<ValueT> {
+    pub fn new(cell_size: f64, point_function: PointFunction<ValueT>) -> Self {
+        assert!(cell_size > 0.0, "cell size must be positive");
+        SpatialIndex {
+            point_function,
+            cell_size,
+            cells: FxHashMap::default(),
+        }
+    }
+
+    fn cell(&self, point: Point) -> Cell {
+        (
+            (point.0 / self.cell_size).floor() as i64,
+            (point.1 / self.cell_size).floor() as i64,
+        )
+    }
+
+    pub fn within_rect(&self, rect: &Rect) -> Vec<RowId> {
+        let (min_x, min_y) = self.cell(rect.min);
+        let (max_x, max_y) = self.cell(rect.max);
+        if min_x > max_x || min_y > max_y {
+            return Vec::new();
+        }
+        let covered = (u128::from(max_x.abs_diff(min_x)) + 1)
+            .saturating_mul(u128::from(max_y.abs_diff(min_y)) + 1);
+
+        // A rect covering more cells than are occupied is answered from the
+        // occupied cells instead of probing every covered one
+        let mut ids: Vec<RowId> = Vec::new();
+        let mut collect = |points: &FxHashMap<RowId, Point>| {
+            ids.extend(
+                points
+                    .iter()
+                    .filter(|(_id, point)| rect.contains(**point))
+                    .map(|(id, _point)| *id),
+            );
+        };
+        if covered > self.cells.len() as u128 {
+            for (cell, points) in self.cells.iter() {
+                if (min_x..=max_x).contains(&cell.0) && (min_y..=max_y).contains(&cell.1) {
+                    collect(points);
+                }
+            }
+        } else {
+            for x in min_x..=max_x {
+                for y in min_y..=max_y {
+                    if let Some(points) = self.cells.get(&(x, y)) {
+                        collect(points);
+                    }
+                }
+            }
+        }
+        ids.sort();
+        ids
+    }
+
+    pub fn nearest(&self, point: Point, k: usize) -> Vec<RowId> {
+        // Distances to a non-finite point are meaningless
+        if k == 0 || self.cells.is_empty() || !point.0.is_finite() || !point.1.is_finite() {
+            return Vec::new();
+        }
+        let center = self.cell(point);
+        let mut candidates: Vec<(f64, RowId)> = Vec::new();
+
+        // Rings of cells are visited outward from the query's cell. Anything
+        // beyond ring r is at least r cells away, so the search stops once
+        // the k-th closest candidate is nearer than that. Once the rings would
+        // cover more cells than are occupied, the occupied cells outside them
+        // are read directly, which also keeps cell arithmetic from overflowing
+        let mut ring: i64 = 0;
+        while ((2 * ring + 1) as u128).pow(2) <= self.cells.len() as u128 {
+            let edges = if ring == 0 {
+                vec![0]
+            } else {
+                vec![-ring, ring]
+            };
+            for dx in -ring..=ring {
+                for dy in edges.iter() {
+                    self.gather(center, (dx, *dy), point, &mut candidates);
+                }
+            }
+            for dy in (1 - ring)..ring {
+                for dx in edges.iter() {
+                    self.gather(center, (*dx, dy), point, &mut candidates);
+                }
+            }
+            if candidates.len() >= k {
+                candidates.sort_by(|a, b| a.0.total_cmp(&b.0).then(a.1.cmp(&b.1)));
+                let reach = ring as f64 * self.cell_size;
+                if candidates[k - 1].0 <= reach * reach {
+                    return candidates.into_iter().take(k).map(|(_d, id)| id).collect();
+                }
+            }
+            ring += 1;
+        }
+        for cell in self.cells.keys() {
+            if cell.0.abs_diff(center.0).max(cell.1.abs_diff(center.1)) >= ring as u64 {
+                self.gather(*cell, (0, 0), point, &mut candidates);
+            }
+        }
+        candidates.sort_by(|a, b| a.0.total_cmp(&b.0).then(a.1.cmp(&b.1)));
+        candidates.into_iter().take(k).map(|(_d, id)| id).collect()
+    }
+
+    fn gather(&self, cell: Cell, offset: Cell, point: Point, candidates: &mut Vec<(f64, RowId)>) {
+        let (Some(x), Some(y)) = (cell.0.checked_add(offset.0), cell.1.checked_add(offset.1))
+        else {
+            return;
+        };
+        if let Some(points) = self.cells.get(&(x, y)) {
+            candidates.extend(
+                points
+                    .iter()
+                    .map(|(id, other)| (distance_squared(point, *other), *id)),
+            );
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.cells.values().map(|points| points.len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cells.is_empty()
+    }
+}
+
+impl<ValueT> Indexable<ValueT> for SpatialIndex<ValueT> {
+    fn insert(&mut self, row: &Indexed<ValueT>) -> IndexId {
+        let point = (self.point_function)(row.value());
+        let cell = self.cell(point);
+        self.cells.entry(cell).or_default().insert(row.id(), point);
+        IndexId::new(0)
+    }
+
+    fn delete(&mut self, row: &Indexed<ValueT>) {
+        let cell = self.cell((self.point_function)(row.value()));
+        if let Some(points) = self.cells.get_mut(&cell) {
+            points.remove(&row.id());
+            if points.is_empty() {
+                self.cells.remove(&cell);
+            }
+        }
+    }
+
+    fn verify(&self, rows: &[Indexed<ValueT>]) -> (Vec<RowId>, Vec<RowId>) {
+        let expected = rows
+            .iter()
+            .map(|row| {
+                (
+                    row.id(),
+                    vec![self.cell((self.point_function)(row.value()))],
+                )
+            })
+            .collect();
+        let entries = self
+            .cells
+            .iter()
+            .flat_map(|(cell, points)| points.keys().map(move |id| (cell, *id)));
+        verify_entries(expected, entries)
+    }
+
+    fn dump_entries(&self) -> Vec<(String, Vec<RowId>)> {
+        let mut cells: Vec<(Cell, Vec<RowId>)> = self
+            .cells
+            .iter()
+            .map(|(cell, points)| {
+                let mut ids: Vec<RowId> = points.keys().copied().collect();
+                ids.sort();
+                (*cell, ids)
+            })
+            .collect();
+        cells.sort();
+        cells
+            .into_iter()
+            .map(|(cell, ids)| (format!("{:?}", cell), ids))
+            .collect()
+    }
+
+    fn describe(&self) -> IndexDescription {
+        IndexDescription::new("spatial").with_option("cell_size", self.cell_size)
+    }
+}
+
+pub struct SpatialIndexRead<ValueT> {
+    store: StoreRead<ValueT>,
+    index: Arc<RwLock<SpatialIndex<ValueT>>>,
+}
+
+impl<ValueT> Clone for SpatialIndexRead<ValueT> {
+    fn clone(&self) -> Self {
+        SpatialIndexRead {
+            store: self.store.clone(),
+            index: self.index.clone(),
+        }
+    }
+}
+
+impl<ValueT: Clone> SpatialIndexRead<ValueT> {
+    pub fn new(store: StoreRead<ValueT>, index: Arc<RwLock<SpatialIndex<ValueT>>>) -> Self {
+        SpatialIndexRead { store, index }
+    }
+
+    pub fn within_rect(&self, rect: &Rect) -> Vec<Indexed<ValueT>> {
+        self.store.consistent(|| {
+            let row_ids = self.index.read().unwrap().within_rect(rect);
+            self.hydrate(row_ids)
+        })
+    }
+
+    pub fn nearest(&self, point: Point, k: usize) -> Vec<Indexed<ValueT>> {
+        self.store.consistent(|| {
+            let row_ids = self.index.read().unwrap().nearest(point, k);
+            self.hydrate(row_ids)
+        })
+    }
+
+    fn hydrate(&self, row_ids: Vec<RowId>) -> Vec<Indexed<ValueT>> {
+        row_ids
+            .into_iter()
+            .filter_map(|id| self.store.by_id_indexed(id))
+            .collect()
+    }
+}
+
+impl<'a, RowT: Clone + 'a> HashSync<'a, RowT> {
+    pub fn spatial_index<PointFn>(&mut self, point_fn: PointFn) -> SpatialIndexRead<RowT>
+    where
+        PointFn: Fn(&RowT) -> Point + Send + Sync + 'static,
+        RowT: Send + Sync,
+    {
+        self.spatial_index_with_cell_size(1.0, point_fn)
+    }
+
+    pub fn spatial_index_with_cell_size<PointFn>(
+        &mut self,
+        cell_size: f64,
+        point_fn: PointFn,
+    ) -> SpatialIndexRead<RowT>
+    where
+        PointFn: Fn(&RowT) -> Point + Send + Sync + 'static,
+        RowT: Send + Sync,
+    {
+        let index = self.register_index(SpatialIndex::new(cell_size, Box::new(point_fn)));
+        SpatialIndexRead::new(self.reader(), index)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(rows: Vec<Indexed<(&'static str, Point)>>) -> Vec<&'static str> {
+        rows.into_iter().map(|row| row.value().0).collect()
+    }
+
+    #[test]
+    fn rect_and_nearest_queries() {
+        let mut hs = HashSync::new();
+        let spatial = hs.spatial_index_with_cell_size(2.0, |row: &(&str, Point)| row.1);
+        let ids = hs
+            .insert_batch(vec![
+                ("origin", (0.0, 0.0)),
+                ("near", (1.5, 0.5)),
+                ("far", (9.0, 9.0)),
+                ("negative", (-3.0, -0.5)),
+            ])
+            .unwrap();
+
+        let rect = Rect::new((-1.0, -1.0), (2.0, 1.0));
+        assert_eq!(names(spatial.within_rect(&rect)), vec!["origin", "near"]);
+        assert_eq!(
+            names(spatial.nearest((1.0, 0.0), 2)),
+            vec!["near", "origin"]
+        );
+        assert_eq!(names(spatial.nearest((8.0, 8.0), 1)), vec!["far"]);
+        assert_eq!(spatial.nearest((0.0, 0.0), 10).len(), 4);
+
+        hs.replace(ids[2], ("far", (0.5, 0.5)));
+        assert_eq!(names(spatial.nearest((0.6, 0.6), 1)), vec!["far"]);
+        hs.delete(ids[0]);
+        assert_eq!(names(spatial.within_rect(&rect)), vec!["near", "far"]);
+        hs.verify_indexes().unwrap();
+    }
+
+    #[test]
+    fn queries_survive_extreme_coordinates() {
+        let mut hs = HashSync::new();
+        let spatial = hs.spatial_index(|row: &(&str, Point)| row.1);
+        hs.insert_batch(vec![
+            ("origin", (0.0, 0.0)),
+            ("edge", (f64::MAX, f64::MAX)),
+            ("infinite", (f64::NEG_INFINITY, 0.0)),
+        ])
+        .unwrap();
+
+        let everything = Rect::new((f64::NEG_INFINITY, -1e300), (f64::INFINITY, f64::INFINITY));
+        assert_eq!(
+            names(spatial.within_rect(&everything)),
+            vec!["origin", "edge", "infinite"]
+        );
+        assert_eq!(
+            names(spatial.nearest((1.0, 1.0), 2)),
+            vec!["origin", "edge"]
+        );
+        assert_eq!(
+            names(spatial.nearest((-1e300, 0.0), 3)),
+            vec!["origin", "edge", "infinite"]
+        );
+        assert!(spatial.nearest((f64::INFINITY, 0.0), 1).is_empty());
+        assert!(spatial.nearest((f64::NAN, 0.0), 1).is_empty());
+    }
+}
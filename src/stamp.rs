@@ -0,0 +1,50 @@
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc,
+};
+
+use crate::store::read_consistent;
+
+#[derive(Clone)]
+pub(crate) struct Stamp {
+    generation: Arc<AtomicU64>,
+    replacing: Arc<AtomicU64>,
+    stamp: u64,
+}
+
+impl Default for Stamp {
+    fn default() -> Self {
+        Stamp::new(Arc::new(AtomicU64::new(0)), Arc::new(AtomicU64::new(0)))
+    }
+}
+
+impl Stamp {
+    pub(crate) fn new(generation: Arc<AtomicU64>, replacing: Arc<AtomicU64>) -> Self {
+        let stamp = generation.load(Ordering::SeqCst);
+        Stamp {
+            generation,
+            replacing,
+            stamp,
+        }
+    }
+
+    pub(crate) fn stamp(&self) -> u64 {
+        self.stamp
+    }
+
+    pub(crate) fn current(&self) -> u64 {
+        self.generation.load(Ordering::SeqCst)
+    }
+
+    // drop_indexes bumps the store's generation, so handles taken before it
+    // no longer match and read as empty
+    pub(crate) fn is_current(&self) -> bool {
+        self.current() == self.stamp
+    }
+
+    // Multi-row writes move rows through the indexes one at a time, so index
+    // reads are retried like by_id_consistent until none overlapped them
+    pub(crate) fn consistent<T, ReadFn: Fn() -> T>(&self, read_fn: ReadFn) -> T {
+        read_consistent(&self.replacing, read_fn)
+    }
+}
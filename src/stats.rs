@@ -0,0 +1,41 @@
+use std::time::SystemTime;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Stats {
+    version: u64,
+    rows: usize,
+    indexes: usize,
+    last_mutation: Option<SystemTime>,
+}
+
+impl Stats {
+    pub fn new(
+        version: u64,
+        rows: usize,
+        indexes: usize,
+        last_mutation: Option<SystemTime>,
+    ) -> Self {
+        Stats {
+            version,
+            rows,
+            indexes,
+            last_mutation,
+        }
+    }
+
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    pub fn indexes(&self) -> usize {
+        self.indexes
+    }
+
+    pub fn last_mutation(&self) -> Option<SystemTime> {
+        self.last_mutation
+    }
+}
@@ -0,0 +1,243 @@
+use std::{
+    hint::spin_loop,
+    ops::Deref,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Instant,
+};
+
+use dashmap::{mapref::one::Ref, DashMap};
+
+use crate::{
+    deadline::Deadlines,
+    id::{Indexed, RowId},
+    snapshot::Snapshot,
+};
+
+pub struct RowRef<'r, RowT> {
+    guard: Ref<'r, RowId, RowT>,
+}
+
+impl<'r, RowT> RowRef<'r, RowT> {
+    pub fn new(guard: Ref<'r, RowId, RowT>) -> Self {
+        RowRef { guard }
+    }
+
+    pub fn id(&self) -> RowId {
+        *self.guard.key()
+    }
+}
+
+impl<RowT> Deref for RowRef<'_, RowT> {
+    type Target = RowT;
+
+    fn deref(&self) -> &RowT {
+        self.guard.value()
+    }
+}
+
+pub struct StoreRead<RowT> {
+    rows: Arc<DashMap<RowId, RowT>>,
+    replacing: Arc<AtomicU64>,
+    version: Arc<AtomicU64>,
+    deadlines: Deadlines,
+}
+
+impl<RowT> Clone for StoreRead<RowT> {
+    fn clone(&self) -> Self {
+        StoreRead {
+            rows: self.rows.clone(),
+            replacing: self.replacing.clone(),
+            version: self.version.clone(),
+            deadlines: self.deadlines.clone(),
+        }
+    }
+}
+
+impl<RowT: Clone> StoreRead<RowT> {
+    pub fn new(
+        rows: Arc<DashMap<RowId, RowT>>,
+        replacing: Arc<AtomicU64>,
+        version: Arc<AtomicU64>,
+    ) -> Self {
+        StoreRead {
+            rows,
+            replacing,
+            version,
+            deadlines: Deadlines::default(),
+        }
+    }
+
+    pub(crate) fn with_deadlines(self, deadlines: Deadlines) -> Self {
+        StoreRead { deadlines, ..self }
+    }
+
+    pub fn keys(&self) -> Vec<RowId> {
+        let now = Instant::now();
+        self.rows
+            .iter()
+            .map(|r| *r.key())
+            .filter(|id| !self.deadlines.is_expired(*id, now))
+            .collect()
+    }
+
+    pub fn by_id(&self, id: RowId) -> Option<RowT> {
+        if self.deadlines.is_expired(id, Instant::now()) {
+            return None;
+        }
+        self.rows.get(&id).map(|r| r.value().clone())
+    }
+
+    pub fn by_id_indexed(&self, id: RowId) -> Option<Indexed<RowT>> {
+        self.by_id(id).map(|row| Indexed::new(id, row))
+    }
+
+    pub fn by_id_consistent(&self, id: RowId) -> Option<RowT> {
+        if self.deadlines.is_expired(id, Instant::now()) {
+            return None;
+        }
+        by_id_consistent(&self.rows, &self.replacing, id)
+    }
+
+    pub fn by_ids_consistent(&self, ids: &[RowId]) -> Vec<Option<RowT>> {
+        let now = Instant::now();
+        self.consistent(|| {
+            ids.iter()
+                .map(|id| {
+                    if self.deadlines.is_expired(*id, now) {
+                        return None;
+                    }
+                    self.rows.get(id).map(|r| r.value().clone())
+                })
+                .collect()
+        })
+    }
+
+    pub(crate) fn consistent<T, ReadFn: Fn() -> T>(&self, read_fn: ReadFn) -> T {
+        read_consistent(&self.replacing, read_fn)
+    }
+
+    pub fn snapshot(&self) -> Snapshot<RowT> {
+        // Single-row writes don't take the seqlock, so the version is checked
+        // as well: a copy that raced two separate inserts sees it move
+        loop {
+            let version = self.version.load(Ordering::SeqCst);
+            let now = Instant::now();
+            let rows = self.consistent(|| {
+                self.rows
+                    .iter()
+                    .filter(|r| !self.deadlines.is_expired(*r.key(), now))
+                    .map(|r| (*r.key(), r.value().clone()))
+                    .collect()
+            });
+            if self.version.load(Ordering::SeqCst) == version {
+                return Snapshot::new(rows, version);
+            }
+        }
+    }
+}
+
+pub(crate) fn by_id_consistent<RowT: Clone>(
+    rows: &DashMap<RowId, RowT>,
+    replacing: &AtomicU64,
+    id: RowId,
+) -> Option<RowT> {
+    read_consistent(replacing, || rows.get(&id).map(|r| r.value().clone()))
+}
+
+// Replaces bump `replacing` to an odd value before removing the old rows and
+// back to even after inserting the new ones, so a read that starts and ends on
+// the same even value did not overlap the delete-insert window
+pub(crate) fn read_consistent<T, ReadFn: Fn() -> T>(replacing: &AtomicU64, read_fn: ReadFn) -> T {
+    loop {
+        let before = replacing.load(Ordering::SeqCst);
+        if before % 2 == 1 {
+            spin_loop();
+            continue;
+        }
+        let result = read_fn();
+        if replacing.load(Ordering::SeqCst) == before {
+            return result;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::atomic::AtomicBool, thread};
+
+    use super::*;
+    use crate::hashsync::HashSync;
+
+    #[test]
+    fn by_id_consistent_never_observes_replace_gap() {
+        let mut hs = HashSync::new();
+        let row_id = hs.insert(0);
+        let reader = hs.reader();
+        let done = AtomicBool::new(false);
+
+        thread::scope(|scope| {
+            scope.spawn(|| {
+                while !done.load(Ordering::SeqCst) {
+                    assert!(reader.by_id_consistent(row_id).is_some());
+                }
+            });
+            for i in 1..10_000 {
+                hs.replace(row_id, i);
+            }
+            done.store(true, Ordering::SeqCst);
+        });
+
+        assert_eq!(reader.by_id(row_id), Some(9_999));
+        assert_eq!(hs.by_id_consistent(row_id), Some(9_999));
+    }
+
+    #[test]
+    fn snapshot_never_observes_half_applied_batch() {
+        let mut hs = HashSync::new();
+        let reader = hs.reader();
+        let done = AtomicBool::new(false);
+
+        thread::scope(|scope| {
+            scope.spawn(|| {
+                while !done.load(Ordering::SeqCst) {
+                    assert!(reader.snapshot().len().is_multiple_of(4));
+                }
+            });
+            for i in 0..2_000 {
+                let ids = hs.insert_batch([i; 4]).unwrap();
+                if i % 2 == 0 {
+                    hs.delete_many(&ids);
+                }
+            }
+            done.store(true, Ordering::SeqCst);
+        });
+
+        assert_eq!(reader.snapshot().len(), 4_000);
+    }
+
+    #[test]
+    fn by_ids_consistent_never_observes_half_applied_swap() {
+        let mut hs = HashSync::new();
+        let id1 = hs.insert(true);
+        let id2 = hs.insert(false);
+        let reader = hs.reader();
+        let done = AtomicBool::new(false);
+
+        thread::scope(|scope| {
+            scope.spawn(|| {
+                while !done.load(Ordering::SeqCst) {
+                    let rows = reader.by_ids_consistent(&[id1, id2]);
+                    let primaries = rows.iter().filter(|row| **row == Some(true)).count();
+                    assert_eq!(primaries, 1);
+                }
+            });
+            for _ in 0..10_000 {
+                hs.swap(id1, id2).unwrap();
+            }
+            done.store(true, Ordering::SeqCst);
+        });
+    }
+}
@@ -0,0 +1,199 @@
+use std::{
+    convert::Infallible,
+    hash::Hash,
+    sync::{Arc, RwLock},
+};
+
+use crate::{error::UniqueViolation, hashsync::HashSync, id::RowId, merkle::MerkleTree};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum SyncMessage<RowT> {
+    Digests(Vec<u64>),
+    Leaves(Vec<(RowId, u64)>),
+    Request(Vec<RowId>),
+    Rows(Vec<(RowId, Option<RowT>)>),
+}
+
+// One session pulls rows from a remote store. The puller sends its bucket
+// digests, the remote answers with row hashes and tombstones for buckets that
+// differ, and the puller requests only the rows whose hashes it doesn't hold.
+// A requested row the remote has deleted comes back as None and is deleted
+// locally, so running a pull in each direction converges both stores on the
+// union of their rows less every row either side deleted.
+pub struct SyncSession<'s, 'a, RowT> {
+    hs: &'s HashSync<'a, RowT>,
+}
+
+impl<'s, 'a, RowT: Clone + Hash + 'a> SyncSession<'s, 'a, RowT> {
+    pub fn new(hs: &'s HashSync<'a, RowT>) -> Option<Self> {
+        hs.merkle_tree()?;
+        Some(SyncSession { hs })
+    }
+
+    pub fn digests(&self) -> SyncMessage<RowT> {
+        let merkle = self.merkle().read().unwrap();
+        SyncMessage::Digests(merkle.bucket_hashes().to_vec())
+    }
+
+    pub fn diff(&self, remote_digests: &[u64]) -> SyncMessage<RowT> {
+        let merkle = self.merkle().read().unwrap();
+        let buckets: Vec<usize> = merkle
+            .bucket_hashes()
+            .iter()
+            .enumerate()
+            .filter(|(bucket, hash)| remote_digests.get(*bucket) != Some(hash))
+            .map(|(bucket, _hash)| bucket)
+            .collect();
+        SyncMessage::Leaves(merkle.leaves_in(&buckets))
+    }
+
+    pub fn request(&self, remote_leaves: &[(RowId, u64)]) -> SyncMessage<RowT> {
+        let merkle = self.merkle().read().unwrap();
+        let ids = remote_leaves
+            .iter()
+            .filter(|(id, leaf)| merkle.entry(*id) != Some(*leaf))
+            .map(|(id, _leaf)| *id)
+            .collect();
+        SyncMessage::Request(ids)
+    }
+
+    pub fn rows(&self, ids: &[RowId]) -> SyncMessage<RowT> {
+        let merkle = self.merkle().read().unwrap();
+        let rows = ids
+            .iter()
+            .filter_map(|id| match self.hs.by_id(*id) {
+                Some(row) => Some((*id, Some(row))),
+                None => merkle.is_tombstone(*id).then_some((*id, None)),
+            })
+            .collect();
+        SyncMessage::Rows(rows)
+    }
+
+    pub fn respond(&self, message: &SyncMessage<RowT>) -> Option<SyncMessage<RowT>> {
+        match message {
+            SyncMessage::Digests(digests) => Some(self.diff(digests)),
+            SyncMessage::Leaves(leaves) => Some(self.request(leaves)),
+            SyncMessage::Request(ids) => Some(self.rows(ids)),
+            SyncMessage::Rows(_rows) => None,
+        }
+    }
+
+    fn merkle(&self) -> &Arc<RwLock<MerkleTree<RowT>>> {
+        self.hs.merkle_tree().unwrap()
+    }
+}
+
+impl<'a, RowT: Clone + 'a> HashSync<'a, RowT> {
+    // The delta is applied as one transaction, so a unique index that rejects
+    // a row leaves the store as it was
+    pub fn apply_sync(&mut self, message: SyncMessage<RowT>) -> Result<usize, UniqueViolation> {
+        let rows = match message {
+            SyncMessage::Rows(rows) if !rows.is_empty() => rows,
+            _ => return Ok(0),
+        };
+        let applied = rows.len();
+        let deleted: Vec<RowId> = rows
+            .iter()
+            .filter(|(_id, row)| row.is_none())
+            .map(|(id, _row)| *id)
+            .collect();
+        self.transaction(|tx| {
+            for (id, row) in rows {
+                match row {
+                    Some(row) => tx.replace(id, row),
+                    None => {
+                        tx.delete(id);
+                    }
+                }
+            }
+            Ok::<(), Infallible>(())
+        })?;
+        if let Some(merkle) = self.merkle_tree() {
+            let mut merkle = merkle.write().unwrap();
+            for id in deleted {
+                merkle.bury(id);
+            }
+        }
+        Ok(applied)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pull(local: &mut HashSync<(u32, u32)>, remote: &HashSync<(u32, u32)>) -> usize {
+        let remote_session = SyncSession::new(remote).unwrap();
+        let (leaves, request) = {
+            let local_session = SyncSession::new(local).unwrap();
+            let leaves = remote_session.respond(&local_session.digests()).unwrap();
+            let request = local_session.respond(&leaves).unwrap();
+            (leaves, request)
+        };
+        assert!(matches!(leaves, SyncMessage::Leaves(_)));
+        let rows = remote_session.respond(&request).unwrap();
+        local.apply_sync(rows).unwrap()
+    }
+
+    #[test]
+    fn pulls_converge_replicas() {
+        let mut hs1 = HashSync::new();
+        hs1.enable_merkle();
+        let mut hs2 = HashSync::new();
+        hs2.enable_merkle();
+        for n in 0..50 {
+            hs1.replace(RowId::new(n), (n as u32, 0));
+            hs2.replace(RowId::new(n), (n as u32, 0));
+        }
+        hs1.replace(RowId::new(3), (3, 1));
+        hs2.replace(RowId::new(60), (60, 0));
+
+        assert_eq!(pull(&mut hs2, &hs1), 1);
+        assert_eq!(hs2.by_id(RowId::new(3)), Some((3, 1)));
+        assert_eq!(pull(&mut hs1, &hs2), 1);
+        assert_eq!(hs1.root_hash(), hs2.root_hash());
+        assert_eq!(pull(&mut hs1, &hs2), 0);
+    }
+
+    #[test]
+    fn pulls_carry_deletes() {
+        let mut hs1 = HashSync::new();
+        hs1.enable_merkle();
+        let mut hs2 = HashSync::new();
+        hs2.enable_merkle();
+        for n in 0..20 {
+            hs1.replace(RowId::new(n), (n as u32, 0));
+            hs2.replace(RowId::new(n), (n as u32, 0));
+        }
+        hs1.delete(RowId::new(4));
+        hs1.replace(RowId::new(30), (30, 0));
+        hs1.delete(RowId::new(30));
+
+        assert_eq!(pull(&mut hs2, &hs1), 2);
+        assert_eq!(hs2.by_id(RowId::new(4)), None);
+        assert_eq!(hs2.keys().len(), 19);
+        assert_eq!(hs1.root_hash(), hs2.root_hash());
+        assert_eq!(pull(&mut hs2, &hs1), 0);
+        assert_eq!(pull(&mut hs1, &hs2), 0);
+        assert_eq!(hs2.verify_indexes(), Ok(()));
+    }
+
+    #[test]
+    fn rejected_rows_leave_the_puller_unchanged() {
+        let mut hs1 = HashSync::new();
+        hs1.enable_merkle();
+        let mut hs2 = HashSync::new();
+        hs2.enable_merkle();
+        hs2.unique_index(|&(_a, b)| b).unwrap();
+        hs1.replace(RowId::new(0), (0, 7));
+        hs1.replace(RowId::new(1), (1, 8));
+        hs2.replace(RowId::new(2), (2, 7));
+
+        let rows = SyncSession::new(&hs1)
+            .unwrap()
+            .rows(&[RowId::new(0), RowId::new(1)]);
+        assert!(hs2.apply_sync(rows).is_err());
+        assert_eq!(hs2.keys(), vec![RowId::new(2)]);
+    }
+}
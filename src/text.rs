@@ -0,0 +1,168 @@
+use std::sync::Arc;
+
+use crate::{hashsync::HashSync, id::Indexed, index::IndexRead};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenMode {
+    Whitespace,
+    NGram(usize),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Tokenizer {
+    mode: TokenMode,
+    lowercase: bool,
+}
+
+impl Tokenizer {
+    pub fn whitespace() -> Self {
+        Tokenizer {
+            mode: TokenMode::Whitespace,
+            lowercase: false,
+        }
+    }
+
+    pub fn ngram(n: usize) -> Self {
+        assert!(n > 0, "n-grams must be at least one character long");
+        Tokenizer {
+            mode: TokenMode::NGram(n),
+            lowercase: false,
+        }
+    }
+
+    pub fn lowercase(self) -> Self {
+        Tokenizer {
+            lowercase: true,
+            ..self
+        }
+    }
+
+    pub fn tokenize(&self, text: &str) -> Vec<String> {
+        let text = if self.lowercase {
+            text.to_lowercase()
+        } else {
+            text.to_string()
+        };
+        let mut tokens: Vec<String> = match self.mode {
+            TokenMode::Whitespace => text.split_whitespace().map(str::to_string).collect(),
+            TokenMode::NGram(n) => text
+                .split_whitespace()
+                .flat_map(|word| {
+                    let chars: Vec<char> = word.chars().collect();
+                    if chars.len() < n {
+                        vec![word.to_string()]
+                    } else {
+                        chars.windows(n).map(String::from_iter).collect()
+                    }
+                })
+                .collect(),
+        };
+        tokens.sort();
+        tokens.dedup();
+        tokens
+    }
+}
+
+impl Default for Tokenizer {
+    fn default() -> Self {
+        Tokenizer::whitespace().lowercase()
+    }
+}
+
+pub struct TextIndexRead<ValueT> {
+    index: IndexRead<String, ValueT>,
+    tokenizer: Arc<Tokenizer>,
+}
+
+impl<ValueT> Clone for TextIndexRead<ValueT> {
+    fn clone(&self) -> Self {
+        TextIndexRead {
+            index: self.index.clone(),
+            tokenizer: self.tokenizer.clone(),
+        }
+    }
+}
+
+impl<ValueT: Clone> TextIndexRead<ValueT> {
+    pub fn new(index: IndexRead<String, ValueT>, tokenizer: Tokenizer) -> Self {
+        TextIndexRead {
+            index,
+            tokenizer: Arc::new(tokenizer),
+        }
+    }
+
+    pub fn search(&self, query: &str) -> Vec<Indexed<ValueT>> {
+        let mut terms = self.tokenizer.tokenize(query).into_iter();
+        let first = match terms.next() {
+            Some(first) => self.index.ids(&first),
+            None => return Vec::new(),
+        };
+        terms
+            .fold(first, |matches, term| matches.and(self.index.ids(&term)))
+            .fetch()
+    }
+
+    pub fn search_values(&self, query: &str) -> Vec<ValueT> {
+        let indexed = self.search(query);
+        indexed.into_iter().map(|row| row.into_value()).collect()
+    }
+
+    pub fn tokenizer(&self) -> Tokenizer {
+        *self.tokenizer
+    }
+}
+
+impl<'a, RowT: Clone + 'a> HashSync<'a, RowT> {
+    pub fn text_index<TextFn>(&mut self, text_fn: TextFn) -> TextIndexRead<RowT>
+    where
+        TextFn: for<'r> Fn(&'r RowT) -> &'r str + Send + Sync + 'static,
+    {
+        self.text_index_with(Tokenizer::default(), text_fn)
+    }
+
+    pub fn text_index_with<TextFn>(
+        &mut self,
+        tokenizer: Tokenizer,
+        text_fn: TextFn,
+    ) -> TextIndexRead<RowT>
+    where
+        TextFn: for<'r> Fn(&'r RowT) -> &'r str + Send + Sync + 'static,
+    {
+        let index = self.index_many(move |row: &RowT| tokenizer.tokenize(text_fn(row)));
+        TextIndexRead::new(index, tokenizer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn search_requires_every_term() {
+        let mut hs = HashSync::new();
+        let text = hs.text_index(|row: &String| row.as_str());
+        hs.insert("The quick brown fox".to_string());
+        hs.insert("A quick red fox".to_string());
+        hs.insert("lazy brown dog".to_string());
+
+        assert_eq!(text.search("QUICK fox").len(), 2);
+        assert_eq!(
+            text.search_values("brown fox"),
+            vec!["The quick brown fox".to_string()]
+        );
+        assert!(text.search("quick dog").is_empty());
+        assert!(text.search("   ").is_empty());
+    }
+
+    #[test]
+    fn ngram_tokenizer_matches_substrings() {
+        let mut hs = HashSync::new();
+        let text = hs.text_index_with(Tokenizer::ngram(3).lowercase(), |row: &String| row.as_str());
+        hs.insert("Hashsync".to_string());
+        hs.insert("Dashmap".to_string());
+
+        assert_eq!(text.search_values("sync"), vec!["Hashsync".to_string()]);
+        assert_eq!(text.search("ash").len(), 2);
+        assert_eq!(Tokenizer::ngram(3).tokenize("ab"), vec!["ab".to_string()]);
+    }
+}
@@ -0,0 +1,230 @@
+#[cfg(feature = "serde")]
+use std::{
+    fs::File,
+    io::{self, BufReader, BufWriter},
+    path::Path,
+};
+use std::{
+    sync::{Arc, RwLock},
+    thread,
+    time::SystemTime,
+};
+
+use crate::{
+    describe::IndexDescription,
+    hashsync::HashSync,
+    id::{Indexed, RowId},
+    index::{IndexId, Indexable},
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum TraceOp<RowT> {
+    Insert(RowT),
+    Replace(RowT),
+    Delete,
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TraceEntry<RowT> {
+    seq: u64,
+    at: SystemTime,
+    // ThreadId can't be serialized or rebuilt, so its debug form is kept
+    thread: String,
+    id: RowId,
+    op: TraceOp<RowT>,
+}
+
+impl<RowT> TraceEntry<RowT> {
+    pub fn seq(&self) -> u64 {
+        self.seq
+    }
+
+    pub fn at(&self) -> SystemTime {
+        self.at
+    }
+
+    pub fn thread(&self) -> &str {
+        &self.thread
+    }
+
+    pub fn id(&self) -> RowId {
+        self.id
+    }
+
+    pub fn op(&self) -> &TraceOp<RowT> {
+        &self.op
+    }
+}
+
+pub struct TraceRecorder<RowT> {
+    entries: Vec<TraceEntry<RowT>>,
+}
+
+impl<RowT> TraceRecorder<RowT> {
+    pub fn new() -> Self {
+        TraceRecorder {
+            entries: Vec::new(),
+        }
+    }
+
+    fn record(&mut self, id: RowId, op: TraceOp<RowT>) {
+        self.entries.push(TraceEntry {
+            seq: self.entries.len() as u64,
+            at: SystemTime::now(),
+            thread: format!("{:?}", thread::current().id()),
+            id,
+            op,
+        });
+    }
+}
+
+impl<RowT> Default for TraceRecorder<RowT> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<RowT: Clone> Indexable<RowT> for TraceRecorder<RowT> {
+    fn insert(&mut self, row: &Indexed<RowT>) -> IndexId {
+        self.record(row.id(), TraceOp::Insert(row.value().clone()));
+        IndexId::new(0)
+    }
+
+    fn delete(&mut self, row: &Indexed<RowT>) {
+        self.record(row.id(), TraceOp::Delete);
+    }
+
+    fn update(&mut self, _old: &Indexed<RowT>, new: &Indexed<RowT>) {
+        self.record(new.id(), TraceOp::Replace(new.value().clone()));
+    }
+
+    fn describe(&self) -> IndexDescription {
+        IndexDescription::new("trace")
+    }
+}
+
+pub struct Trace<RowT> {
+    recorder: Arc<RwLock<TraceRecorder<RowT>>>,
+}
+
+impl<RowT> Clone for Trace<RowT> {
+    fn clone(&self) -> Self {
+        Trace {
+            recorder: self.recorder.clone(),
+        }
+    }
+}
+
+impl<RowT: Clone> Trace<RowT> {
+    pub fn new(recorder: Arc<RwLock<TraceRecorder<RowT>>>) -> Self {
+        Trace { recorder }
+    }
+
+    pub fn len(&self) -> usize {
+        self.recorder.read().unwrap().entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn entries(&self) -> Vec<TraceEntry<RowT>> {
+        self.recorder.read().unwrap().entries.clone()
+    }
+
+    #[cfg(feature = "serde")]
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()>
+    where
+        RowT: serde::Serialize,
+    {
+        let file = BufWriter::new(File::create(path)?);
+        serde_json::to_writer(file, &self.recorder.read().unwrap().entries)?;
+        Ok(())
+    }
+
+    #[cfg(feature = "serde")]
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Vec<TraceEntry<RowT>>>
+    where
+        RowT: serde::de::DeserializeOwned,
+    {
+        let file = BufReader::new(File::open(path)?);
+        Ok(serde_json::from_reader(file)?)
+    }
+}
+
+impl<'a, RowT: Clone + 'a> HashSync<'a, RowT> {
+    pub fn record_trace(&mut self) -> Trace<RowT>
+    where
+        RowT: Send + Sync,
+    {
+        Trace::new(self.register_internal(TraceRecorder::new()))
+    }
+
+    pub fn replay(entries: &[TraceEntry<RowT>], until_seq: Option<u64>) -> Self {
+        let mut hs = HashSync::new();
+        for entry in entries {
+            if until_seq.is_some_and(|until_seq| entry.seq > until_seq) {
+                break;
+            }
+            match &entry.op {
+                TraceOp::Insert(row) | TraceOp::Replace(row) => hs.replace(entry.id, row.clone()),
+                TraceOp::Delete => {
+                    hs.delete(entry.id);
+                }
+            }
+        }
+        hs
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn record_and_replay() {
+        let mut hs = HashSync::new();
+        let existing = hs.insert((1, 2));
+        let trace = hs.record_trace();
+        let inserted = hs.insert((3, 4));
+        hs.replace(existing, (1, 3));
+        hs.delete(inserted);
+
+        let entries = trace.entries();
+        assert_eq!(entries.len(), 4);
+        assert_eq!(entries[0].op(), &TraceOp::Insert((1, 2)));
+        assert_eq!(entries[2].op(), &TraceOp::Replace((1, 3)));
+        assert_eq!(entries[3].id(), inserted);
+        assert_eq!(entries[3].op(), &TraceOp::Delete);
+
+        let replayed = HashSync::replay(&entries, None);
+        assert_eq!(replayed.keys(), vec![existing]);
+        assert_eq!(replayed.by_id(existing), Some((1, 3)));
+
+        let replayed = HashSync::replay(&entries, Some(1));
+        assert_eq!(replayed.by_id(existing), Some((1, 2)));
+        assert_eq!(replayed.by_id(inserted), Some((3, 4)));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn trace_round_trips_through_file() {
+        let mut hs = HashSync::new();
+        let trace = hs.record_trace();
+        let id = hs.insert((1, "a".to_string()));
+        hs.replace(id, (1, "b".to_string()));
+
+        let path = std::env::temp_dir().join(format!("hashsync-trace-{}.json", std::process::id()));
+        trace.save(&path).unwrap();
+        let entries: Vec<TraceEntry<(u32, String)>> = Trace::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[1].op(), &TraceOp::Replace((1, "b".to_string())));
+        assert_eq!(entries[1].thread(), trace.entries()[1].thread());
+        let replayed = HashSync::replay(&entries, None);
+        assert_eq!(replayed.by_id(id), Some((1, "b".to_string())));
+    }
+}
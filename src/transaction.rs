@@ -0,0 +1,74 @@
+use std::collections::BTreeMap;
+
+use crate::{id::RowId, store::StoreRead};
+
+pub struct Transaction<RowT> {
+    store: StoreRead<RowT>,
+    next_id: RowId,
+    staged: BTreeMap<RowId, Option<RowT>>,
+}
+
+impl<RowT: Clone> Transaction<RowT> {
+    pub(crate) fn new(store: StoreRead<RowT>, next_id: RowId) -> Self {
+        Transaction {
+            store,
+            next_id,
+            staged: BTreeMap::new(),
+        }
+    }
+
+    pub(crate) fn into_staged(self) -> (RowId, BTreeMap<RowId, Option<RowT>>) {
+        (self.next_id, self.staged)
+    }
+
+    pub fn by_id(&self, id: RowId) -> Option<RowT> {
+        match self.staged.get(&id) {
+            Some(staged) => staged.clone(),
+            None => self.store.by_id(id),
+        }
+    }
+
+    pub fn insert(&mut self, row: RowT) -> RowId {
+        let id = self.next_id;
+        self.staged.insert(id, Some(row));
+        self.next_id = self.next_id.next();
+        id
+    }
+
+    pub fn delete(&mut self, id: RowId) -> Option<RowT> {
+        let row = self.by_id(id);
+        if row.is_some() {
+            self.staged.insert(id, None);
+        }
+        row
+    }
+
+    pub fn replace(&mut self, id: RowId, row: RowT) {
+        if id >= self.next_id {
+            self.next_id = id.next();
+        }
+        self.staged.insert(id, Some(row));
+    }
+
+    pub fn update<UpdateFn>(&mut self, id: RowId, update_fn: UpdateFn) -> bool
+    where
+        UpdateFn: FnOnce(&mut RowT),
+    {
+        match self.by_id(id) {
+            Some(mut row) => {
+                update_fn(&mut row);
+                self.staged.insert(id, Some(row));
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.staged.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.staged.is_empty()
+    }
+}
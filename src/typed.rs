@@ -0,0 +1,92 @@
+use std::{any::Any, hash::Hash, sync::Arc};
+
+use crate::{
+    hashsync::HashSync,
+    id::{Indexed, RowId},
+    index::IndexRead,
+};
+
+pub type AnyRow = Arc<dyn Any + Send + Sync>;
+
+impl<'a> HashSync<'a, AnyRow> {
+    pub fn insert_typed<T: Any + Send + Sync>(&mut self, row: T) -> RowId {
+        self.insert(Arc::new(row))
+    }
+
+    pub fn by_id_typed<T: Any + Send + Sync>(&self, id: RowId) -> Option<Arc<T>> {
+        self.by_id(id)?.downcast::<T>().ok()
+    }
+
+    pub fn index_typed<T, IndexKeyT, IndexFn>(
+        &mut self,
+        index_fn: IndexFn,
+    ) -> IndexRead<IndexKeyT, AnyRow>
+    where
+        T: Any + Send + Sync,
+        IndexFn: Fn(&T) -> IndexKeyT + Send + Sync + 'static,
+        IndexKeyT: PartialEq + Eq + Hash + Send + Sync + 'a,
+    {
+        self.index_many(move |row: &AnyRow| {
+            row.downcast_ref::<T>().map(&index_fn).into_iter().collect()
+        })
+    }
+}
+
+impl<KeyT: PartialEq + Eq + Hash> IndexRead<KeyT, AnyRow> {
+    pub fn get_typed<T: Any + Send + Sync>(&self, key: &KeyT) -> Vec<Indexed<Arc<T>>> {
+        self.get(key)
+            .into_iter()
+            .filter_map(|indexed| {
+                let id = indexed.id();
+                indexed
+                    .into_value()
+                    .downcast::<T>()
+                    .ok()
+                    .map(|row| Indexed::new(id, row))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, PartialEq)]
+    struct User {
+        name: &'static str,
+        team: u32,
+    }
+
+    #[derive(Debug, PartialEq)]
+    struct Team {
+        id: u32,
+    }
+
+    #[test]
+    fn mixed_row_types() {
+        let mut hs: HashSync<AnyRow> = HashSync::new();
+        let alice = hs.insert_typed(User {
+            name: "alice",
+            team: 1,
+        });
+        let team = hs.insert_typed(Team { id: 1 });
+        let users_by_team = hs.index_typed(|user: &User| user.team);
+        let teams_by_id = hs.index_typed(|team: &Team| team.id);
+        hs.insert_typed(User {
+            name: "bob",
+            team: 2,
+        });
+
+        assert_eq!(hs.by_id_typed::<User>(alice).unwrap().name, "alice");
+        assert!(hs.by_id_typed::<Team>(alice).is_none());
+        assert_eq!(hs.by_id_typed::<Team>(team).unwrap().id, 1);
+
+        let users = users_by_team.get_typed::<User>(&1);
+        assert_eq!(users.len(), 1);
+        assert_eq!(users[0].id(), alice);
+        assert_eq!(teams_by_id.get_typed::<Team>(&1).len(), 1);
+        assert!(teams_by_id.get_typed::<User>(&1).is_empty());
+        assert_eq!(users_by_team.get_typed::<User>(&2)[0].value().name, "bob");
+    }
+}
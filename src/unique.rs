@@ -0,0 +1,202 @@
+use std::{
+    hash::Hash,
+    sync::{Arc, RwLock},
+    time::Instant,
+};
+
+use dashmap::DashMap;
+use fxhash::{FxHashMap, FxHashSet};
+
+use crate::{
+    deadline::Deadlines,
+    describe::IndexDescription,
+    eviction::LruTracker,
+    id::{Indexed, RowId},
+    index::{hashed_entries, verify_entries, IndexId, Indexable},
+    stamp::Stamp,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UniquePolicy {
+    Reject,
+    Replace,
+}
+
+pub type UniqueIndexFunction<KeyT, ValueT> = Box<dyn Fn(&Indexed<ValueT>) -> KeyT + Send + Sync>;
+
+pub struct UniqueIndex<KeyT, ValueT> {
+    index_function: UniqueIndexFunction<KeyT, ValueT>,
+    policy: UniquePolicy,
+    index: FxHashMap<KeyT, RowId>,
+}
+
+impl<KeyT: PartialEq + Eq + Hash, ValueT> UniqueIndex<KeyT, ValueT> {
+    pub fn new(index_function: UniqueIndexFunction<KeyT, ValueT>, policy: UniquePolicy) -> Self {
+        UniqueIndex {
+            index_function,
+            policy,
+            index: FxHashMap::default(),
+        }
+    }
+
+    pub fn get(&self, key: &KeyT) -> Option<RowId> {
+        self.index.get(key).copied()
+    }
+
+    pub fn policy(&self) -> UniquePolicy {
+        self.policy
+    }
+
+    pub fn conflicts(&self, rows: &[Indexed<ValueT>], changing: &FxHashSet<RowId>) -> Vec<RowId> {
+        let mut owners: FxHashMap<KeyT, RowId> = FxHashMap::default();
+        let mut conflicts = Vec::new();
+        for row in rows {
+            let key = (self.index_function)(row);
+            if let Some(owner) = self.get(&key).filter(|owner| !changing.contains(owner)) {
+                conflicts.push(owner);
+            }
+            if let Some(earlier) = owners
+                .insert(key, row.id())
+                .filter(|earlier| *earlier != row.id())
+            {
+                conflicts.push(earlier);
+            }
+        }
+        conflicts
+    }
+
+    pub fn key_count(&self) -> usize {
+        self.index.len()
+    }
+}
+
+impl<KeyT: PartialEq + Eq + Hash, ValueT> Indexable<ValueT> for UniqueIndex<KeyT, ValueT> {
+    fn insert(&mut self, row: &Indexed<ValueT>) -> IndexId {
+        // Every write is checked before it reaches the index, so a key can
+        // only be taken over mid-write from a row that is giving it up
+        self.index.insert((self.index_function)(row), row.id());
+        IndexId::new(0)
+    }
+
+    fn delete(&mut self, row: &Indexed<ValueT>) {
+        let key = (self.index_function)(row);
+        if self.index.get(&key) == Some(&row.id()) {
+            self.index.remove(&key);
+        }
+    }
+
+    fn verify(&self, rows: &[Indexed<ValueT>]) -> (Vec<RowId>, Vec<RowId>) {
+        let expected = rows
+            .iter()
+            .map(|row| (row.id(), vec![(self.index_function)(row)]))
+            .collect();
+        let entries = self.index.iter().map(|(key, id)| (key, *id));
+        verify_entries(expected, entries)
+    }
+
+    fn dump_entries(&self) -> Vec<(String, Vec<RowId>)> {
+        hashed_entries(self.index.iter().map(|(key, id)| (key, vec![*id])))
+    }
+
+    fn describe(&self) -> IndexDescription {
+        IndexDescription::new("unique")
+            .keyed_by::<KeyT>()
+            .with_option("policy", format!("{:?}", self.policy))
+    }
+}
+
+pub struct UniqueIndexRead<KeyT, ValueT> {
+    rows: Arc<DashMap<RowId, ValueT>>,
+    index: Arc<RwLock<UniqueIndex<KeyT, ValueT>>>,
+    stamp: Stamp,
+    deadlines: Deadlines,
+    lru: Option<Arc<LruTracker>>,
+}
+
+impl<KeyT, ValueT> Clone for UniqueIndexRead<KeyT, ValueT> {
+    fn clone(&self) -> Self {
+        UniqueIndexRead {
+            rows: self.rows.clone(),
+            index: self.index.clone(),
+            stamp: self.stamp.clone(),
+            deadlines: self.deadlines.clone(),
+            lru: self.lru.clone(),
+        }
+    }
+}
+
+impl<KeyT: PartialEq + Eq + Hash, ValueT: Clone> UniqueIndexRead<KeyT, ValueT> {
+    pub fn new(
+        rows: Arc<DashMap<RowId, ValueT>>,
+        index: Arc<RwLock<UniqueIndex<KeyT, ValueT>>>,
+    ) -> Self {
+        UniqueIndexRead {
+            rows,
+            index,
+            stamp: Stamp::default(),
+            deadlines: Deadlines::default(),
+            lru: None,
+        }
+    }
+
+    pub(crate) fn stamped(
+        self,
+        stamp: Stamp,
+        deadlines: Deadlines,
+        lru: Option<Arc<LruTracker>>,
+    ) -> Self {
+        UniqueIndexRead {
+            stamp,
+            deadlines,
+            lru,
+            ..self
+        }
+    }
+
+    pub fn is_live(&self) -> bool {
+        self.stamp.is_current()
+    }
+
+    pub fn get_one(&self, key: &KeyT) -> Option<Indexed<ValueT>> {
+        let id = self.get_id(key)?;
+        let row = self
+            .rows
+            .get(&id)
+            .map(|value| Indexed::new(id, value.clone()));
+        if let (Some(lru), Some(_row)) = (self.lru.as_ref(), row.as_ref()) {
+            lru.touch(id);
+        }
+        row
+    }
+
+    pub fn get_id(&self, key: &KeyT) -> Option<RowId> {
+        self.stamp.consistent(|| {
+            let index_guard = self.index.read().unwrap();
+            if !self.is_live() {
+                return None;
+            }
+            index_guard
+                .get(key)
+                .filter(|id| !self.deadlines.is_expired(*id, Instant::now()))
+        })
+    }
+
+    pub fn get_one_value(&self, key: &KeyT) -> Option<ValueT> {
+        self.get_one(key).map(|indexed| indexed.into_value())
+    }
+
+    pub fn contains_key(&self, key: &KeyT) -> bool {
+        self.get_id(key).is_some()
+    }
+
+    // Like IndexRead::key_count, expired owners count until they're swept
+    pub fn key_count(&self) -> usize {
+        self.stamp.consistent(|| {
+            let index_guard = self.index.read().unwrap();
+            if !self.is_live() {
+                return 0;
+            }
+            index_guard.key_count()
+        })
+    }
+}
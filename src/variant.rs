@@ -0,0 +1,53 @@
+use std::mem::{discriminant, Discriminant};
+
+use crate::{hashsync::HashSync, id::Indexed, index::IndexRead};
+
+impl<'a, RowT: Clone + 'static> HashSync<'a, RowT> {
+    pub fn variant_index(&mut self) -> IndexRead<Discriminant<RowT>, RowT> {
+        self.index(discriminant)
+    }
+}
+
+impl<RowT: Clone> IndexRead<Discriminant<RowT>, RowT> {
+    pub fn get_variant(&self, sample: &RowT) -> Vec<Indexed<RowT>> {
+        self.get(&discriminant(sample))
+    }
+
+    pub fn get_variant_values(&self, sample: &RowT) -> Vec<RowT> {
+        self.get_values(&discriminant(sample))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, Clone, PartialEq)]
+    enum Account {
+        Active(u32),
+        Suspended(u32, &'static str),
+        Closed,
+    }
+
+    #[test]
+    fn variant_index() {
+        let mut hs = HashSync::new();
+        hs.insert(Account::Active(1));
+        hs.insert(Account::Suspended(2, "fraud"));
+        let closed = hs.insert(Account::Closed);
+        let by_variant = hs.variant_index();
+        hs.insert(Account::Active(3));
+
+        let active = by_variant.get_variant_values(&Account::Active(0));
+        assert_eq!(active.len(), 2);
+        assert!(active.contains(&Account::Active(1)));
+        assert!(active.contains(&Account::Active(3)));
+        assert_eq!(
+            by_variant.get_variant_values(&Account::Suspended(0, "")),
+            vec![Account::Suspended(2, "fraud")]
+        );
+
+        hs.delete(closed);
+        assert!(by_variant.get_variant(&Account::Closed).is_empty());
+    }
+}
@@ -0,0 +1,433 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+};
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::{
+    describe::IndexDescription,
+    error::WalError,
+    hashsync::HashSync,
+    id::{Indexed, RowId},
+    index::{IndexId, Indexable},
+    snapshot::HashSyncSnapshot,
+};
+
+#[derive(Debug, Serialize, Deserialize)]
+enum WalRecord<RowT> {
+    Put(RowId, RowT),
+    Delete(RowId),
+    Checkpoint(PathBuf),
+}
+
+type EncodeFn<RowT> = for<'r> fn(WalRecord<&'r RowT>) -> serde_json::Result<String>;
+type EncodeSnapshotFn<RowT> = fn(&HashSyncSnapshot<RowT>) -> serde_json::Result<String>;
+
+fn encode<RowT: Serialize>(record: WalRecord<&RowT>) -> serde_json::Result<String> {
+    serde_json::to_string(&record)
+}
+
+fn encode_snapshot<RowT: Serialize>(
+    snapshot: &HashSyncSnapshot<RowT>,
+) -> serde_json::Result<String> {
+    serde_json::to_string(snapshot)
+}
+
+pub struct WriteAheadLog<RowT> {
+    file: File,
+    len: u64,
+    encode: EncodeFn<RowT>,
+    encode_snapshot: EncodeSnapshotFn<RowT>,
+    auto_checkpoint: Option<(PathBuf, u64)>,
+    error: Option<WalError>,
+}
+
+impl<RowT> WriteAheadLog<RowT> {
+    fn new(file: File, len: u64) -> Self
+    where
+        RowT: Serialize,
+    {
+        WriteAheadLog {
+            file,
+            len,
+            encode: encode::<RowT>,
+            encode_snapshot: encode_snapshot::<RowT>,
+            auto_checkpoint: None,
+            error: None,
+        }
+    }
+
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    fn append<'r>(&mut self, records: impl IntoIterator<Item = WalRecord<&'r RowT>>)
+    where
+        RowT: 'r,
+    {
+        // Appending past a failed write would leave a gap that recovery
+        // replays as if nothing happened, so the first error stops the log
+        if self.error.is_some() {
+            return;
+        }
+        let mut lines = String::new();
+        for record in records {
+            match (self.encode)(record) {
+                Ok(line) => {
+                    lines.push_str(&line);
+                    lines.push('\n');
+                }
+                Err(err) => {
+                    self.error = Some(WalError::new(err.to_string()));
+                    return;
+                }
+            }
+        }
+        match self.file.write_all(lines.as_bytes()) {
+            Ok(()) => self.len += lines.len() as u64,
+            Err(err) => self.error = Some(WalError::new(err.to_string())),
+        }
+    }
+
+    fn due_checkpoint(&self) -> Option<PathBuf> {
+        match self.auto_checkpoint.as_ref() {
+            Some((path, max_len)) if self.error.is_none() && self.len > *max_len => {
+                Some(path.clone())
+            }
+            _ => None,
+        }
+    }
+
+    fn checkpoint(
+        &mut self,
+        path: &Path,
+        snapshot: &HashSyncSnapshot<RowT>,
+    ) -> Result<(), WalError> {
+        if let Some(err) = self.error.as_ref() {
+            return Err(err.clone());
+        }
+        let result = self.write_checkpoint(path, snapshot);
+        if let Err(err) = result.as_ref() {
+            self.error = Some(err.clone());
+        }
+        result
+    }
+
+    fn write_checkpoint(
+        &mut self,
+        path: &Path,
+        snapshot: &HashSyncSnapshot<RowT>,
+    ) -> Result<(), WalError> {
+        let snapshot =
+            (self.encode_snapshot)(snapshot).map_err(|err| WalError::new(err.to_string()))?;
+        // The snapshot is renamed into place so a crash never leaves a torn
+        // one behind. Until the log is truncated it still replays cleanly on
+        // top of the new snapshot, since every record only moves rows towards
+        // the state the snapshot already holds
+        let staging = path.with_extension("tmp");
+        let io_err = |err: std::io::Error| WalError::new(format!("{}: {}", path.display(), err));
+        let mut file = File::create(&staging).map_err(io_err)?;
+        file.write_all(snapshot.as_bytes()).map_err(io_err)?;
+        file.sync_data().map_err(io_err)?;
+        fs::rename(&staging, path).map_err(io_err)?;
+
+        self.file.set_len(0).map_err(io_err)?;
+        self.file.seek(SeekFrom::Start(0)).map_err(io_err)?;
+        self.len = 0;
+        self.append([WalRecord::Checkpoint(path.to_path_buf())]);
+        self.sync()
+    }
+
+    pub fn sync(&mut self) -> Result<(), WalError> {
+        if let Some(err) = self.error.as_ref() {
+            return Err(err.clone());
+        }
+        self.file
+            .sync_data()
+            .map_err(|err| WalError::new(err.to_string()))
+    }
+}
+
+impl<RowT> Indexable<RowT> for WriteAheadLog<RowT> {
+    fn insert(&mut self, row: &Indexed<RowT>) -> IndexId {
+        self.append([WalRecord::Put(row.id(), row.value())]);
+        IndexId::new(0)
+    }
+
+    fn delete(&mut self, row: &Indexed<RowT>) {
+        self.append([WalRecord::Delete(row.id())]);
+    }
+
+    fn describe(&self) -> IndexDescription {
+        IndexDescription::new("wal")
+    }
+
+    fn insert_many(&mut self, rows: &[Indexed<RowT>]) {
+        self.append(rows.iter().map(|row| WalRecord::Put(row.id(), row.value())));
+    }
+
+    fn delete_many(&mut self, rows: &[Indexed<RowT>]) {
+        self.append(rows.iter().map(|row| WalRecord::Delete(row.id())));
+    }
+
+    fn update(&mut self, _old: &Indexed<RowT>, new: &Indexed<RowT>) {
+        self.append([WalRecord::Put(new.id(), new.value())]);
+    }
+}
+
+fn open(path: &Path) -> Result<File, WalError> {
+    OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(path)
+        .map_err(|err| WalError::new(format!("{}: {}", path.display(), err)))
+}
+
+fn read_records<RowT: DeserializeOwned>(
+    file: &mut File,
+) -> Result<(Vec<WalRecord<RowT>>, u64), WalError> {
+    // Read as bytes since a torn write can end partway through a multi-byte
+    // character, which would fail UTF-8 decoding of the whole log
+    let mut log = Vec::new();
+    file.read_to_end(&mut log)
+        .map_err(|err| WalError::new(err.to_string()))?;
+
+    let mut records = Vec::new();
+    let mut offset = 0;
+    for (number, line) in log.split_inclusive(|byte| *byte == b'\n').enumerate() {
+        match serde_json::from_slice(line) {
+            Ok(record) => records.push(record),
+            // A crash mid-append leaves an unterminated last line; that write
+            // never completed, so it is cut off rather than treated as corrupt
+            Err(_) if !line.ends_with(b"\n") => {
+                file.set_len(offset as u64)
+                    .map_err(|err| WalError::new(err.to_string()))?;
+                break;
+            }
+            Err(err) => return Err(WalError::new(format!("line {}: {}", number + 1, err))),
+        }
+        offset += line.len();
+    }
+    let len = file
+        .seek(SeekFrom::End(0))
+        .map_err(|err| WalError::new(err.to_string()))?;
+    Ok((records, len))
+}
+
+fn read_snapshot<RowT: DeserializeOwned>(path: &Path) -> Result<HashSyncSnapshot<RowT>, WalError> {
+    let snapshot = fs::read_to_string(path)
+        .map_err(|err| WalError::new(format!("{}: {}", path.display(), err)))?;
+    serde_json::from_str(&snapshot)
+        .map_err(|err| WalError::new(format!("{}: {}", path.display(), err)))
+}
+
+impl<'a, RowT: Clone + 'a> HashSync<'a, RowT> {
+    pub fn with_wal<P: AsRef<Path>>(path: P) -> Result<Self, WalError>
+    where
+        RowT: Serialize,
+    {
+        // An existing log holds rows that only recover can bring back, so it
+        // is never reused as the start of a fresh store
+        let file = open(path.as_ref())?;
+        let len = file
+            .metadata()
+            .map_err(|err| WalError::new(err.to_string()))?
+            .len();
+        if len > 0 {
+            return Err(WalError::new(format!(
+                "{}: log is not empty, use recover to reopen it",
+                path.as_ref().display()
+            )));
+        }
+        let mut hs = HashSync::new();
+        hs.attach_wal(WriteAheadLog::new(file, 0));
+        Ok(hs)
+    }
+
+    pub fn recover<P: AsRef<Path>>(path: P) -> Result<Self, WalError>
+    where
+        RowT: Serialize + DeserializeOwned,
+    {
+        let mut file = open(path.as_ref())?;
+        let (records, len) = read_records(&mut file)?;
+        let mut hs = HashSync::new();
+        for record in records {
+            match record {
+                WalRecord::Put(id, row) => hs.replace(id, row),
+                WalRecord::Delete(id) => {
+                    hs.delete(id);
+                }
+                WalRecord::Checkpoint(path) => hs = HashSync::from_snapshot(read_snapshot(&path)?),
+            }
+        }
+        hs.attach_wal(WriteAheadLog::new(file, len));
+        Ok(hs)
+    }
+
+    pub fn sync_wal(&self) -> Result<(), WalError> {
+        match self.wal() {
+            Some(wal) => wal.write().unwrap().sync(),
+            None => Ok(()),
+        }
+    }
+
+    pub fn wal_len(&self) -> Option<u64> {
+        self.wal().map(|wal| wal.read().unwrap().len())
+    }
+
+    pub fn checkpoint<P: AsRef<Path>>(&mut self, path: P) -> Result<(), WalError> {
+        let wal = match self.wal() {
+            Some(wal) => wal.clone(),
+            None => return Err(WalError::new("no write-ahead log is attached".to_string())),
+        };
+        let snapshot = self.to_snapshot();
+        let mut wal = wal.write().unwrap();
+        wal.checkpoint(path.as_ref(), &snapshot)
+    }
+
+    pub fn auto_checkpoint<P: AsRef<Path>>(&mut self, path: P, max_log_len: u64) {
+        if let Some(wal) = self.wal() {
+            wal.write().unwrap().auto_checkpoint = Some((path.as_ref().to_path_buf(), max_log_len));
+        }
+        self.checkpoint_if_due();
+    }
+
+    pub(crate) fn checkpoint_if_due(&mut self) {
+        let path = match self
+            .wal()
+            .and_then(|wal| wal.read().unwrap().due_checkpoint())
+        {
+            Some(path) => path,
+            None => return,
+        };
+        // Failures are kept on the log and surface through sync_wal
+        let _ = self.checkpoint(path);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, path::PathBuf};
+
+    use super::*;
+
+    fn log_path(name: &str) -> PathBuf {
+        env::temp_dir().join(format!("hashsync-{}-{}.wal", name, std::process::id()))
+    }
+
+    #[test]
+    fn recover_replays_mutations() {
+        let path = log_path("replay");
+        let mut hs = HashSync::with_wal(&path).unwrap();
+        let kept = hs.insert((1, "a".to_string()));
+        let deleted = hs.insert((2, "b".to_string()));
+        hs.insert_batch([(1, "c".to_string()), (3, "d".to_string())])
+            .unwrap();
+        hs.replace(kept, (1, "e".to_string()));
+        hs.update(kept, |row| row.1.push('!'));
+        hs.delete(deleted);
+        hs.sync_wal().unwrap();
+        let expected = hs.to_snapshot();
+        drop(hs);
+
+        let mut recovered: HashSync<(u32, String)> = HashSync::recover(&path).unwrap();
+        assert_eq!(recovered.to_snapshot(), expected);
+        let by_number = recovered.index(|(number, _name)| *number);
+        assert_eq!(by_number.get(&1).len(), 2);
+
+        let id = recovered.insert((4, "f".to_string()));
+        assert!(id > deleted);
+        let recovered_again: HashSync<(u32, String)> = HashSync::recover(&path).unwrap();
+        assert_eq!(recovered_again.by_id(id), Some((4, "f".to_string())));
+
+        let err = HashSync::<(u32, String)>::with_wal(&path).err().unwrap();
+        assert!(err.message().contains("recover"));
+        let recovered: HashSync<(u32, String)> = HashSync::recover(&path).unwrap();
+        assert_eq!(recovered.keys().len(), 4);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn recover_drops_torn_tail() {
+        let path = log_path("torn");
+        let mut hs = HashSync::with_wal(&path).unwrap();
+        let id = hs.insert(1u32);
+        drop(hs);
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(b"{\"Put\":[1,").unwrap();
+
+        let mut recovered: HashSync<u32> = HashSync::recover(&path).unwrap();
+        assert_eq!(recovered.keys(), vec![id]);
+        recovered.insert(2);
+        let recovered: HashSync<u32> = HashSync::recover(&path).unwrap();
+        assert_eq!(recovered.keys().len(), 2);
+
+        let path_text = log_path("torn-text");
+        let mut hs = HashSync::with_wal(&path_text).unwrap();
+        let id = hs.insert("caf".to_string());
+        drop(hs);
+        let mut file = OpenOptions::new().append(true).open(&path_text).unwrap();
+        file.write_all(&"{\"Put\":[1,\"é\"]}".as_bytes()[..12])
+            .unwrap();
+        let recovered: HashSync<String> = HashSync::recover(&path_text).unwrap();
+        assert_eq!(recovered.keys(), vec![id]);
+        fs::remove_file(&path_text).unwrap();
+
+        fs::write(&path, "{\"Put\":[0,1]}\nnot json\n").unwrap();
+        let err = HashSync::<u32>::recover(&path).err().unwrap();
+        assert!(err.message().starts_with("line 2:"));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn checkpoint_truncates_log() {
+        let path = log_path("checkpoint");
+        let snapshot_path = log_path("checkpoint-snapshot");
+        let mut hs = HashSync::with_wal(&path).unwrap();
+        let ids = hs.insert_batch(0..100u32).unwrap();
+        hs.delete(ids[0]);
+        let before = hs.wal_len().unwrap();
+        hs.checkpoint(&snapshot_path).unwrap();
+        assert!(hs.wal_len().unwrap() < before);
+
+        hs.replace(ids[1], 1_000);
+        let expected = hs.to_snapshot();
+        drop(hs);
+        let recovered: HashSync<u32> = HashSync::recover(&path).unwrap();
+        assert_eq!(recovered.to_snapshot(), expected);
+        fs::remove_file(&path).unwrap();
+        fs::remove_file(&snapshot_path).unwrap();
+    }
+
+    #[test]
+    fn auto_checkpoint_bounds_log() {
+        let path = log_path("auto");
+        let snapshot_path = log_path("auto-snapshot");
+        let mut hs = HashSync::with_wal(&path).unwrap();
+        hs.auto_checkpoint(&snapshot_path, 512);
+        for i in 0..1_000u32 {
+            let id = hs.insert(i);
+            if i % 3 == 0 {
+                hs.delete(id);
+            }
+            assert!(hs.wal_len().unwrap() <= 512 + 256);
+        }
+        hs.sync_wal().unwrap();
+        let expected = hs.to_snapshot();
+        drop(hs);
+
+        let recovered: HashSync<u32> = HashSync::recover(&path).unwrap();
+        assert_eq!(recovered.to_snapshot(), expected);
+        assert_eq!(recovered.keys().len(), 666);
+        fs::remove_file(&path).unwrap();
+        fs::remove_file(&snapshot_path).unwrap();
+    }
+}
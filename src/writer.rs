@@ -0,0 +1,177 @@
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+use crate::{error::UniqueViolation, hashsync::HashSync, id::RowId, store::StoreRead};
+
+pub struct HashSyncWriter<'a, RowT> {
+    inner: Arc<Mutex<HashSync<'a, RowT>>>,
+}
+
+impl<RowT> Clone for HashSyncWriter<'_, RowT> {
+    fn clone(&self) -> Self {
+        HashSyncWriter {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<'a, RowT: Clone + 'a> HashSyncWriter<'a, RowT> {
+    pub fn new(hs: HashSync<'a, RowT>) -> Self {
+        HashSyncWriter {
+            inner: Arc::new(Mutex::new(hs)),
+        }
+    }
+
+    // A panic in a with closure or an update_fn poisons the mutex, but every
+    // write leaves the store whole before user code runs or after it returns,
+    // so the lock is taken back rather than failing every later call
+    fn lock(&self) -> MutexGuard<'_, HashSync<'a, RowT>> {
+        self.inner.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    // The plain writes run their _unique variant and panic only once the
+    // lock is released, so a rejected key doesn't poison the store for every
+    // other writer
+    pub fn insert(&self, row: RowT) -> RowId {
+        let result = self.insert_unique(row);
+        result.unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    pub fn insert_unique(&self, row: RowT) -> Result<RowId, UniqueViolation> {
+        self.lock().insert_unique(row)
+    }
+
+    pub fn delete(&self, id: RowId) -> Option<RowT> {
+        self.lock().delete(id)
+    }
+
+    pub fn replace(&self, id: RowId, row: RowT) {
+        let result = self.replace_unique(id, row);
+        result.unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    pub fn replace_unique(&self, id: RowId, row: RowT) -> Result<(), UniqueViolation> {
+        self.lock().replace_unique(id, row)
+    }
+
+    pub fn update<UpdateFn>(&self, id: RowId, update_fn: UpdateFn) -> bool
+    where
+        UpdateFn: FnOnce(&mut RowT),
+    {
+        let result = self.update_unique(id, update_fn);
+        result.unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    pub fn update_unique<UpdateFn>(
+        &self,
+        id: RowId,
+        update_fn: UpdateFn,
+    ) -> Result<bool, UniqueViolation>
+    where
+        UpdateFn: FnOnce(&mut RowT),
+    {
+        self.lock().update_unique(id, update_fn)
+    }
+
+    pub fn by_id(&self, id: RowId) -> Option<RowT> {
+        self.lock().by_id(id)
+    }
+
+    pub fn reader(&self) -> StoreRead<RowT> {
+        self.lock().reader()
+    }
+
+    pub fn with<R, WithFn>(&self, with_fn: WithFn) -> R
+    where
+        WithFn: FnOnce(&mut HashSync<'a, RowT>) -> R,
+    {
+        with_fn(&mut self.lock())
+    }
+
+    pub fn into_inner(self) -> Result<HashSync<'a, RowT>, Self> {
+        match Arc::try_unwrap(self.inner) {
+            Ok(inner) => Ok(inner.into_inner().unwrap_or_else(PoisonError::into_inner)),
+            Err(inner) => Err(HashSyncWriter { inner }),
+        }
+    }
+}
+
+impl<'a, RowT: Clone + 'a> HashSync<'a, RowT> {
+    pub fn into_writer(self) -> HashSyncWriter<'a, RowT> {
+        HashSyncWriter::new(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+
+    #[test]
+    fn writes_from_multiple_threads() {
+        let mut hs = HashSync::new();
+        let index = hs.index(|&(thread, _n)| thread);
+        let writer = hs.into_writer();
+
+        let handles: Vec<_> = (0..4)
+            .map(|thread| {
+                let writer = writer.clone();
+                thread::spawn(move || {
+                    for n in 0..25 {
+                        writer.insert((thread, n));
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        assert_eq!(writer.reader().keys().len(), 100);
+        assert_eq!(index.get(&2).len(), 25);
+        let hs = writer.into_inner().ok().unwrap();
+        assert_eq!(hs.verify_indexes(), Ok(()));
+    }
+
+    #[test]
+    fn rejected_write_does_not_poison_writer() {
+        let mut hs = HashSync::new();
+        hs.unique_index(|&(name, _score)| name).unwrap();
+        let writer = hs.into_writer();
+        let alice = writer.insert(("alice", 1));
+
+        let rejected = writer.clone();
+        let result = thread::spawn(move || rejected.insert(("alice", 2))).join();
+        assert!(result.is_err());
+        assert!(writer.insert_unique(("alice", 3)).is_err());
+        assert_eq!(writer.update_unique(alice, |row| row.1 = 4), Ok(true));
+        assert_eq!(writer.by_id(alice), Some(("alice", 4)));
+        assert_eq!(writer.reader().by_id_consistent(alice), Some(("alice", 4)));
+    }
+
+    #[test]
+    fn panicking_closure_does_not_poison_writer() {
+        let writer = HashSync::new().into_writer();
+        let id = writer.insert((1, 1));
+
+        let panicking = writer.clone();
+        let result = thread::spawn(move || {
+            panicking.with(|hs| {
+                hs.replace(id, (1, 2));
+                panic!("closure failed");
+            })
+        })
+        .join();
+        assert!(result.is_err());
+
+        let panicking = writer.clone();
+        let result = thread::spawn(move || panicking.update(id, |_row| panic!("update failed")));
+        assert!(result.join().is_err());
+
+        assert_eq!(writer.by_id(id), Some((1, 2)));
+        writer.insert((3, 3));
+        let hs = writer.into_inner().ok().unwrap();
+        assert_eq!(hs.keys().len(), 2);
+        assert_eq!(hs.verify_indexes(), Ok(()));
+    }
+}
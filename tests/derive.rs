@@ -0,0 +1,41 @@
+#![cfg(feature = "derive")]
+
+use hashsync::{hashsync::HashSync, HashSyncRow};
+
+#[derive(Debug, Clone, PartialEq, HashSyncRow)]
+struct User {
+    #[index]
+    team: String,
+    #[index(unique)]
+    email: String,
+    #[index(many)]
+    tags: Vec<String>,
+    visits: u32,
+}
+
+fn user(team: &str, email: &str, tags: &[&str]) -> User {
+    User {
+        team: team.to_string(),
+        email: email.to_string(),
+        tags: tags.iter().map(|tag| tag.to_string()).collect(),
+        visits: 0,
+    }
+}
+
+#[test]
+fn derived_indexes() {
+    let mut hs = HashSync::new();
+    let indexes = hs.row_indexes().unwrap();
+    hs.insert(user("red", "a@example.com", &["admin", "ops"]));
+    hs.insert(user("red", "b@example.com", &["ops"]));
+    hs.insert_unique(user("blue", "a@example.com", &[]))
+        .unwrap_err();
+
+    assert_eq!(indexes.by_team(&"red".to_string()).len(), 2);
+    assert_eq!(
+        indexes.by_email(&"b@example.com".to_string()),
+        Some(user("red", "b@example.com", &["ops"]))
+    );
+    assert_eq!(indexes.by_tags(&"ops".to_string()).len(), 2);
+    assert_eq!(indexes.by_tags(&"admin".to_string())[0].visits, 0);
+}